@@ -32,20 +32,38 @@
 #[cfg(any(doc, feature = "std"))]
 extern crate std; //allow rustdoc links to reference std (e.g. println docs below).
 
+mod memory_log;
+mod spin_lock;
+
 use core::{
     ffi::c_void,
     fmt::{self, Write},
+    mem,
+    ops::BitOr,
     ptr,
-    sync::atomic::{AtomicPtr, Ordering},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering},
 };
+use memory_log::{MemoryLog, PersistentLogHeader};
 use r_efi::{
-    efi::{Guid, Status},
-    system::BootServices,
+    efi::{Guid, PhysicalAddress, Status},
+    protocols::simple_text_output,
+    system::{BootServices, SystemTable},
 };
+use spin_lock::SpinLock;
 
 //Global static logger instance - this is a singleton.
 static LOGGER: AdvancedLogger = AdvancedLogger::new();
 
+//Global debug level mask - output at levels not in the mask is discarded. All levels are enabled by default.
+static DEBUG_LEVEL_MASK: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Maximum number of modules that may have a debug level mask set with [`set_module_debug_level`].
+pub const MAX_MODULE_DEBUG_LEVELS: usize = 16;
+
+//Per-module debug level masks, keyed by module GUID. These override the global debug level mask.
+static MODULE_DEBUG_LEVELS: SpinLock<[Option<(Guid, usize)>; MAX_MODULE_DEBUG_LEVELS]> =
+    SpinLock::new([None; MAX_MODULE_DEBUG_LEVELS]);
+
 /// Standard UEFI DEBUG_INIT level.
 pub const DEBUG_INIT: usize = 0x00000001;
 /// Standard UEFI DEBUG_WARN level.
@@ -70,70 +88,381 @@ struct AdvancedLoggerProtocol {
     write_log: AdvancedLoggerWriteProtocol,
 }
 
+/// A set of output targets that formatted log lines are written to. Targets may be combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogTarget(u32);
+
+impl LogTarget {
+    /// No output (other than to the in-memory log, if registered).
+    pub const NONE: LogTarget = LogTarget(0);
+    /// The AdvancedLogger protocol, which forwards output to the serial port.
+    pub const SERIAL: LogTarget = LogTarget(0x1);
+    /// The system console (the `ConOut` Simple Text Output protocol of the system table).
+    pub const CONSOLE: LogTarget = LogTarget(0x2);
+
+    /// Returns true if all the targets in `other` are present in `self`.
+    pub const fn contains(self, other: LogTarget) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for LogTarget {
+    type Output = LogTarget;
+    fn bitor(self, rhs: LogTarget) -> LogTarget {
+        LogTarget(self.0 | rhs.0)
+    }
+}
+
+/// A source of monotonic ticks used to timestamp log lines.
+pub type TimestampSource = fn() -> u64;
+
+/// The default [`TimestampSource`]: returns the CPU timestamp counter (TSC on x64, the virtual counter on AArch64), or
+/// 0 on architectures without one. Callers that need ticks on the same scale as log timestamps should use
+/// [`timestamp`], which reads the configured source.
+pub fn default_timestamp() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    let ticks = unsafe { core::arch::x86_64::_rdtsc() };
+    #[cfg(target_arch = "aarch64")]
+    let ticks = {
+        let ticks: u64;
+        unsafe { core::arch::asm!("mrs {}, cntvct_el0", out(reg) ticks) };
+        ticks
+    };
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    let ticks = 0;
+    ticks
+}
+
 // Private un-synchronized AdvancedLogger wrapper. Provides implementation of fmt::Write for AdvancedLogger.
 #[derive(Debug)]
 struct AdvancedLogger {
+    boot_services: AtomicPtr<BootServices>,
     protocol: AtomicPtr<AdvancedLoggerProtocol>,
+    system_table: AtomicPtr<SystemTable>,
+    targets: AtomicU32,
+    timestamp_enabled: AtomicBool,
+    timestamp_source: AtomicPtr<()>,
+    line_start: AtomicBool,
+    memory_log: MemoryLog,
 }
 
 impl AdvancedLogger {
     // creates a new AdvancedLogger
     const fn new() -> Self {
-        AdvancedLogger { protocol: AtomicPtr::new(ptr::null_mut()) }
+        AdvancedLogger {
+            boot_services: AtomicPtr::new(ptr::null_mut()),
+            protocol: AtomicPtr::new(ptr::null_mut()),
+            system_table: AtomicPtr::new(ptr::null_mut()),
+            targets: AtomicU32::new(LogTarget::SERIAL.0),
+            timestamp_enabled: AtomicBool::new(false),
+            timestamp_source: AtomicPtr::new(ptr::null_mut()),
+            line_start: AtomicBool::new(true),
+            memory_log: MemoryLog::new(),
+        }
+    }
+
+    // enables or disables the timestamp prefix on log lines.
+    fn set_timestamp_enabled(&self, enabled: bool) {
+        self.timestamp_enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    // sets the source of ticks used for the timestamp prefix.
+    fn set_timestamp_source(&self, source: TimestampSource) {
+        self.timestamp_source.store(source as *mut (), Ordering::SeqCst);
+    }
+
+    // returns the configured timestamp source, or the default source if none was set.
+    fn configured_timestamp_source(&self) -> TimestampSource {
+        let source = self.timestamp_source.load(Ordering::SeqCst);
+        if source.is_null() {
+            default_timestamp
+        } else {
+            unsafe { core::mem::transmute::<*mut (), TimestampSource>(source) }
+        }
+    }
+
+    // returns the timestamp source to use for this line, or None if timestamps are disabled.
+    fn timestamp_source(&self) -> Option<TimestampSource> {
+        if !self.timestamp_enabled.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(self.configured_timestamp_source())
+    }
+
+    // returns the current ticks of the configured timestamp source, whether or not the timestamp prefix is enabled.
+    fn timestamp(&self) -> u64 {
+        (self.configured_timestamp_source())()
     }
 
-    // initialize the AdvancedLogger by acquiring a pointer to the AdvancedLogger protocol.
+    // initialize the AdvancedLogger by acquiring a pointer to the AdvancedLogger protocol through `bs`. Initializing
+    // again with the same boot services table only locates it if it was not previously found (e.g. because it was not
+    // yet installed), while a different table re-locates it using that table. The targets (serial by default) and
+    // system table set by a previous init_with_target are kept.
     fn init(&self, bs: *mut BootServices) {
         assert!(!bs.is_null(), "BootServices should not be NULL");
         let boot_services = unsafe { &mut ptr::read(bs) };
 
+        if self.boot_services.swap(bs, Ordering::SeqCst) != bs {
+            self.protocol.store(ptr::null_mut(), Ordering::SeqCst);
+        }
+
+        if self.protocol.load(Ordering::SeqCst).is_null() {
+            self.protocol
+                .store(Self::locate(boot_services, &ADVANCED_LOGGER_PROTOCOL_GUID) as *mut _, Ordering::SeqCst);
+        }
+    }
+
+    // initialize the AdvancedLogger to write to the given targets, locating the AdvancedLogger protocol through `bs` as
+    // init does. The console is written through the ConOut of `system_table` (which may be NULL if the console is not
+    // a target).
+    fn init_with_target(&self, bs: *mut BootServices, system_table: *mut SystemTable, targets: LogTarget) {
+        self.targets.store(targets.0, Ordering::SeqCst);
+        self.system_table.store(system_table, Ordering::SeqCst);
+        self.init(bs);
+    }
+
+    // initialize the AdvancedLogger to write to the given targets using the boot services and console of the system
+    // table.
+    fn init_with_system_table(&self, system_table: *mut SystemTable, targets: LogTarget) {
+        assert!(!system_table.is_null(), "SystemTable should not be NULL");
+        self.init_with_target(unsafe { (*system_table).boot_services }, system_table, targets);
+    }
+
+    // returns the console to write to, if any. ConOut is read from the system table for each line, since the console
+    // may be replaced during boot (e.g. when the console splitter starts).
+    fn console(&self) -> Option<&simple_text_output::Protocol> {
+        let system_table = unsafe { self.system_table.load(Ordering::SeqCst).as_ref() }?;
+        unsafe { system_table.con_out.as_ref() }
+    }
+
+    // returns the first instance of the protocol identified by `guid`, or NULL if it could not be located.
+    fn locate(boot_services: &mut BootServices, guid: &Guid) -> *mut c_void {
         let mut ptr: *mut c_void = ptr::null_mut();
 
-        let status = (boot_services.locate_protocol)(
-            &ADVANCED_LOGGER_PROTOCOL_GUID as *const _ as *mut _,
-            ptr::null_mut(),
-            ptr::addr_of_mut!(ptr),
-        );
+        let status =
+            (boot_services.locate_protocol)(guid as *const _ as *mut _, ptr::null_mut(), ptr::addr_of_mut!(ptr));
+
+        if status == Status::SUCCESS {
+            ptr
+        } else {
+            ptr::null_mut()
+        }
+    }
 
-        self.protocol.store(
-            if status == Status::SUCCESS { ptr as *mut AdvancedLoggerProtocol } else { ptr::null_mut() },
-            Ordering::SeqCst,
-        )
+    // returns true if the logger has been initialized (whether or not any of its targets were located).
+    fn is_initialized(&self) -> bool {
+        !self.boot_services.load(Ordering::SeqCst).is_null()
     }
 
     // log the debug output in `args` at the given log level.
     fn log(&self, level: usize, args: fmt::Arguments) {
-        let logger_ptr = self.protocol.load(Ordering::SeqCst);
-        if let Some(protocol) = unsafe { logger_ptr.as_mut() } {
-            let mut log_transaction = LogTransactor { protocol, level };
-            log_transaction.write_fmt(args).expect("Printing to log failed.");
+        let targets = LogTarget(self.targets.load(Ordering::SeqCst));
+        let protocol =
+            unsafe { self.protocol.load(Ordering::SeqCst).as_ref() }.filter(|_| targets.contains(LogTarget::SERIAL));
+        let console = self.console().filter(|_| targets.contains(LogTarget::CONSOLE));
+        if protocol.is_none() && console.is_none() && !self.memory_log.is_initialized() {
+            return; //nowhere to write the output.
         }
+        let mut log_transaction = LogTransactor {
+            protocol,
+            console,
+            memory_log: &self.memory_log,
+            level,
+            timestamp_source: self.timestamp_source(),
+            line_start: self.line_start.load(Ordering::SeqCst),
+        };
+        log_transaction.write_fmt(args).expect("Printing to log failed.");
+        self.line_start.store(log_transaction.line_start, Ordering::SeqCst);
     }
 }
 
 struct LogTransactor<'a> {
-    protocol: &'a mut AdvancedLoggerProtocol,
+    protocol: Option<&'a AdvancedLoggerProtocol>,
+    console: Option<&'a simple_text_output::Protocol>,
+    memory_log: &'a MemoryLog,
     level: usize,
+    timestamp_source: Option<TimestampSource>,
+    line_start: bool,
 }
 
 impl<'a> fmt::Write for LogTransactor<'a> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        (self.protocol.write_log)(
-            self.protocol as *const AdvancedLoggerProtocol,
-            self.level,
-            s.as_ptr(),
-            s.as_bytes().len(),
-        );
+        for line in s.split_inclusive('\n') {
+            // prefix each new line with the timestamp if enabled. line_start is cleared first so that writing the
+            // prefix does not recurse.
+            if let (Some(timestamp_source), true) = (self.timestamp_source, self.line_start) {
+                self.line_start = false;
+                write!(self, "[{:>16}] ", timestamp_source())?;
+            }
+            if let Some(protocol) = self.protocol {
+                (protocol.write_log)(protocol as *const AdvancedLoggerProtocol, self.level, line.as_ptr(), line.len());
+            }
+            if let Some(console) = self.console {
+                write_console(console, line);
+            }
+            self.memory_log.write(line.as_bytes());
+            self.line_start = line.ends_with('\n');
+        }
         Ok(())
     }
 }
 
+// writes `line` to the console, translating it to UCS-2 with CRLF line endings. Failures are ignored so that output
+// to the other targets is not affected.
+fn write_console(console: &simple_text_output::Protocol, line: &str) {
+    let console = console as *const simple_text_output::Protocol as *mut simple_text_output::Protocol;
+    let mut buffer = [0u16; 64];
+    let mut len = 0;
+    let flush = |buffer: &mut [u16; 64], len: &mut usize| {
+        buffer[*len] = 0;
+        let _ = unsafe { ((*console).output_string)(console, buffer.as_mut_ptr()) };
+        *len = 0;
+    };
+    for unit in line.encode_utf16() {
+        // leave room for a CR LF pair and the terminating NULL.
+        if len + 3 > buffer.len() {
+            flush(&mut buffer, &mut len);
+        }
+        if unit == '\n' as u16 {
+            buffer[len] = '\r' as u16;
+            len += 1;
+        }
+        buffer[len] = unit;
+        len += 1;
+    }
+    if len > 0 {
+        flush(&mut buffer, &mut len);
+    }
+}
+
 /// Initializes the logging subsystem. The `debug` and `debugln` macros may be called before calling this function, but
 /// output is discarded if the logger has not yet been initialized via this routine.
+///
+/// Calling this routine again with the same boot services table (e.g. from a second driver entry point in the same
+/// image) only retries locating the AdvancedLogger protocol if it was not found previously (e.g. because it was not yet
+/// installed). Calling it with a different table re-points the logger at the AdvancedLogger protocol located through
+/// that table. The targets selected with [`init_debug_with_target`] (serial if it was not called) are kept.
 pub fn init_debug(bs: *mut BootServices) {
     LOGGER.init(bs);
 }
 
+/// Initializes the logging subsystem to write formatted output to `targets` (e.g. `LogTarget::SERIAL |
+/// LogTarget::CONSOLE`, or `LogTarget::NONE` for silent). Without a call to this routine, [`init_debug`] writes to
+/// [`LogTarget::SERIAL`].
+///
+/// Console output is written to the `ConOut` of `system_table` as it is at the time of each write, so it follows the
+/// system console if that is replaced during boot.
+///
+/// May be called again to change the targets (e.g. to mirror output to the console once it is available). Output to
+/// each enabled target is independent: a target that fails to write does not prevent output to the others.
+pub fn init_debug_with_target(system_table: *mut SystemTable, targets: LogTarget) {
+    LOGGER.init_with_system_table(system_table, targets);
+}
+
+/// Returns true if the logging subsystem has been initialized with [`init_debug`] (or [`init_debug_with_target`]).
+///
+/// Note that this does not indicate that output is reaching a target: initialization succeeds even if the
+/// AdvancedLogger protocol is not yet installed, in which case output is discarded until `init_debug` is called again
+/// after it has been installed.
+pub fn is_debug_initialized() -> bool {
+    LOGGER.is_initialized()
+}
+
+/// Enables or disables a timestamp prefix on each line of log output. Disabled by default.
+///
+/// The timestamp is a monotonic tick count from the CPU timestamp counter, unless another source is provided with
+/// [`set_timestamp_source`].
+pub fn set_timestamp_enabled(enabled: bool) {
+    LOGGER.set_timestamp_enabled(enabled);
+}
+
+/// Sets the source of ticks used for the timestamp prefix (e.g. to inject a deterministic counter).
+pub fn set_timestamp_source(source: TimestampSource) {
+    LOGGER.set_timestamp_source(source);
+}
+
+/// Returns the current tick count of the source used for log timestamps (see [`set_timestamp_source`]), so that
+/// intervals measured with it can be compared with timestamped log output. Ticks are returned whether or not the
+/// timestamp prefix is enabled.
+pub fn timestamp() -> u64 {
+    LOGGER.timestamp()
+}
+
+/// Registers `buffer` as an in-memory log. Once registered, all log output is also retained in `buffer` (discarding
+/// the oldest output when full) so that it can be retrieved later with [`drain_memory_log`], even if the AdvancedLogger
+/// protocol is not available.
+pub fn init_memory_log(buffer: &'static mut [u8]) {
+    LOGGER.memory_log.init(buffer);
+}
+
+/// Registers the reserved memory region at physical address `base` of `size` bytes as a persistent in-memory log, so
+/// that log output is retained across a warm reset for post-mortem analysis. The region begins with a small header
+/// (signature, capacity and ring state) followed by the ring buffer. Output is retrieved with [`drain_memory_log`] as
+/// for [`init_memory_log`].
+///
+/// Returns `Ok(true)` if the region held a valid log from before the reset (which is resumed), or `Ok(false)` if the
+/// region was initialized to an empty log. Returns `Err(Status::INVALID_PARAMETER)` if `base` is NULL or not 8-byte
+/// aligned, or `Err(Status::BUFFER_TOO_SMALL)` if `size` cannot hold the header.
+///
+/// # Safety
+///
+/// The region must be identity-mapped memory that is reserved for the log (and not otherwise accessed) for the
+/// remainder of the boot.
+pub unsafe fn init_debug_with_persistent_buffer(base: PhysicalAddress, size: usize) -> Result<bool, Status> {
+    if base == 0 || base as usize & (mem::align_of::<PersistentLogHeader>() - 1) != 0 {
+        return Err(Status::INVALID_PARAMETER);
+    }
+    if size < mem::size_of::<PersistentLogHeader>() {
+        return Err(Status::BUFFER_TOO_SMALL);
+    }
+    let region = core::slice::from_raw_parts_mut(base as usize as *mut u8, size);
+    Ok(LOGGER.memory_log.init_persistent(region))
+}
+
+/// Passes the output retained in the in-memory log (oldest first, in one or more slices) to `f`, and empties the log.
+pub fn drain_memory_log(f: &mut impl FnMut(&[u8])) {
+    LOGGER.memory_log.drain(f);
+}
+
+/// Sets the debug level mask. Output from the `debug` and `debugln` macros is discarded (without being formatted)
+/// unless its level is present in the mask. All levels are enabled by default.
+pub fn set_debug_level(mask: usize) {
+    DEBUG_LEVEL_MASK.store(mask, Ordering::SeqCst);
+}
+
+/// Returns the current debug level mask.
+pub fn get_debug_level() -> usize {
+    DEBUG_LEVEL_MASK.load(Ordering::SeqCst)
+}
+
+/// Sets the debug level mask for the module identified by `guid`, overriding the global debug level mask for output
+/// from the `debug` and `debugln` macros that specify that module GUID.
+///
+/// Returns `Err(Status::OUT_OF_RESOURCES)` if [`MAX_MODULE_DEBUG_LEVELS`] other modules already have a mask set.
+pub fn set_module_debug_level(guid: &Guid, mask: usize) -> Result<(), Status> {
+    let mut module_levels = MODULE_DEBUG_LEVELS.lock();
+    let entry = match module_levels.iter().position(|x| matches!(x, Some((module, _)) if module == guid)) {
+        Some(index) => &mut module_levels[index],
+        None => module_levels.iter_mut().find(|x| x.is_none()).ok_or(Status::OUT_OF_RESOURCES)?,
+    };
+    *entry = Some((*guid, mask));
+    Ok(())
+}
+
+// Returns the debug level mask for the module identified by `guid` (or the global mask if it has none).
+#[doc(hidden)]
+pub fn _module_debug_level(guid: &Guid) -> usize {
+    // don't spin if interrupted while the table is being updated; use the global mask instead.
+    if let Some(module_levels) = MODULE_DEBUG_LEVELS.try_lock() {
+        for (module, mask) in module_levels.iter().flatten() {
+            if module == guid {
+                return *mask;
+            }
+        }
+    }
+    get_debug_level()
+}
+
 #[doc(hidden)]
 pub fn _log(level: usize, args: fmt::Arguments) {
     LOGGER.log(level, args)
@@ -144,7 +473,9 @@ mod no_std_debug {
     /// Prints to the AdvancedLogger log at the specified level.
     ///
     /// This macro uses the same syntax as rust std [`std::println!`] macro, with the addition of a level argument that
-    /// indicates what debug level the output is to be written at.
+    /// indicates what debug level the output is to be written at. The level may be preceded by `guid: <module guid>,`
+    /// to filter the output with the mask set for that module by
+    /// [`set_module_debug_level`](crate::set_module_debug_level).
     ///
     /// See [`std::fmt`] for details on format strings.
     ///
@@ -167,9 +498,18 @@ mod no_std_debug {
     /// ```
     #[macro_export]
     macro_rules! debug {
-      ($level:expr, $($arg:tt)*) => {
-          $crate::_log($level, format_args!($($arg)*))
-      }
+      (guid: $guid:expr, $level:expr, $($arg:tt)*) => {{
+          let level = $level;
+          if (level & $crate::_module_debug_level(&$guid)) != 0 {
+              $crate::_log(level, format_args!($($arg)*))
+          }
+      }};
+      ($level:expr, $($arg:tt)*) => {{
+          let level = $level;
+          if (level & $crate::get_debug_level()) != 0 {
+              $crate::_log(level, format_args!($($arg)*))
+          }
+      }}
   }
 }
 
@@ -178,7 +518,9 @@ mod std_debug {
     /// Prints to the console log.
     ///
     /// This macro uses the same syntax as rust std [`std::println!`] macro, with the addition of a level argument that
-    /// indicates what debug level the output is to be written at.
+    /// indicates what debug level the output is to be written at. The level may be preceded by `guid: <module guid>,`
+    /// to filter the output with the mask set for that module by
+    /// [`set_module_debug_level`](crate::set_module_debug_level).
     ///
     /// See [`std::fmt`] for details on format strings.
     ///
@@ -201,10 +543,16 @@ mod std_debug {
     /// ```
     #[macro_export]
     macro_rules! debug {
-      ($level:expr, $($arg:tt)*) => {
-          let _ = $level;
-          std::print!($($arg)*)
-      }
+      (guid: $guid:expr, $level:expr, $($arg:tt)*) => {{
+          if ($level & $crate::_module_debug_level(&$guid)) != 0 {
+              std::print!($($arg)*)
+          }
+      }};
+      ($level:expr, $($arg:tt)*) => {{
+          if ($level & $crate::get_debug_level()) != 0 {
+              std::print!($($arg)*)
+          }
+      }}
   }
 }
 
@@ -212,6 +560,8 @@ mod std_debug {
 ///
 /// Equivalent to the [`debug!`] macro except that a newline is appended to the format string.
 ///
+/// As with [`debug!`], the level may be preceded by `guid: <module guid>,` to use a per-module debug level mask.
+///
 /// ```no_run
 /// use rust_advanced_logger_dxe::{init_debug, debugln, DEBUG_INFO};
 /// use r_efi::efi::Status;
@@ -230,6 +580,11 @@ mod std_debug {
 /// ```
 #[macro_export]
 macro_rules! debugln {
+    (guid: $guid:expr, $level:expr) => ($crate::debug!(guid: $guid, $level, "\n"));
+    (guid: $guid:expr, $level:expr, $fmt:expr) => ($crate::debug!(guid: $guid, $level, concat!($fmt, "\n")));
+    (guid: $guid:expr, $level:expr, $fmt:expr, $($arg:tt)*) => (
+        $crate::debug!(guid: $guid, $level, concat!($fmt, "\n"), $($arg)*)
+    );
     ($level:expr) => ($crate::debug!($level, "\n"));
     ($level:expr, $fmt:expr) => ($crate::debug!($level, concat!($fmt, "\n")));
     ($level:expr, $fmt:expr, $($arg:tt)*) => ($crate::debug!($level, concat!($fmt, "\n"), $($arg)*));
@@ -252,19 +607,34 @@ macro_rules! function {
 mod tests {
     extern crate std;
     use crate::{
-        debug, init_debug, AdvancedLogger, AdvancedLoggerProtocol, ADVANCED_LOGGER_PROTOCOL_GUID, DEBUG_ERROR,
-        DEBUG_INFO, DEBUG_INIT, DEBUG_VERBOSE, DEBUG_WARN, LOGGER,
+        _module_debug_level, debug, get_debug_level, init_debug, init_debug_with_persistent_buffer, set_debug_level,
+        set_module_debug_level, AdvancedLogger, AdvancedLoggerProtocol, LogTarget, ADVANCED_LOGGER_PROTOCOL_GUID,
+        DEBUG_ERROR, DEBUG_INFO, DEBUG_INIT, DEBUG_VERBOSE, DEBUG_WARN, LOGGER, MAX_MODULE_DEBUG_LEVELS,
+    };
+    use core::{
+        ffi::c_void,
+        fmt,
+        mem::MaybeUninit,
+        ptr,
+        slice::from_raw_parts,
+        sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     };
-    use core::{ffi::c_void, mem::MaybeUninit, slice::from_raw_parts, sync::atomic::Ordering};
     use r_efi::{
-        efi::{Guid, Status},
-        system::BootServices,
+        efi::{Char16, Guid, Status},
+        protocols::simple_text_output,
+        system::{BootServices, SystemTable},
     };
-    use std::{println, str};
+    use std::{println, str, string::String, sync::Mutex};
+
+    // serializes tests that set or depend on the global debug level mask, since tests run in parallel.
+    static DEBUG_LEVEL_LOCK: Mutex<()> = Mutex::new(());
 
     static ADVANCED_LOGGER_INSTANCE: AdvancedLoggerProtocol =
         AdvancedLoggerProtocol { signature: 0, version: 0, write_log: mock_advanced_logger_write };
 
+    // number of writes at DEBUG_ERROR level.
+    static ERROR_WRITES: AtomicUsize = AtomicUsize::new(0);
+
     extern "efiapi" fn mock_advanced_logger_write(
         this: *const AdvancedLoggerProtocol,
         error_level: usize,
@@ -279,6 +649,9 @@ mod tests {
         let buf: &[u8] = unsafe { from_raw_parts(buffer, buffer_size) };
         let str = str::from_utf8(buf).unwrap();
         println!("buffer {buffer:?}:{buffer_size:?}, str: {str:?}");
+        if error_level == DEBUG_ERROR {
+            ERROR_WRITES.fetch_add(1, Ordering::SeqCst);
+        }
         match error_level {
             DEBUG_INIT => assert!("This is a DEBUG_INIT test.\n".contains(str)),
             DEBUG_WARN => assert!("This is a DEBUG_WARN test.\n".contains(str)),
@@ -303,6 +676,32 @@ mod tests {
         Status::SUCCESS
     }
 
+    static RECORDING_LOGGER_INSTANCE: AdvancedLoggerProtocol =
+        AdvancedLoggerProtocol { signature: 0, version: 0, write_log: mock_recording_write };
+
+    static RECORDED_LOG: Mutex<String> = Mutex::new(String::new());
+
+    extern "efiapi" fn mock_recording_write(
+        _this: *const AdvancedLoggerProtocol,
+        _error_level: usize,
+        buffer: *const u8,
+        buffer_size: usize,
+    ) {
+        let buf: &[u8] = unsafe { from_raw_parts(buffer, buffer_size) };
+        RECORDED_LOG.lock().unwrap().push_str(str::from_utf8(buf).unwrap());
+    }
+
+    extern "efiapi" fn mock_locate_recording_protocol(
+        _protocol: *mut Guid,
+        _registration: *mut c_void,
+        interface: *mut *mut c_void,
+    ) -> Status {
+        unsafe {
+            interface.write(&RECORDING_LOGGER_INSTANCE as *const AdvancedLoggerProtocol as *mut c_void);
+        }
+        Status::SUCCESS
+    }
+
     fn mock_boot_services() -> BootServices {
         let boot_services = MaybeUninit::zeroed();
         let mut boot_services: BootServices = unsafe { boot_services.assume_init() };
@@ -322,6 +721,216 @@ mod tests {
         );
     }
 
+    #[test]
+    fn init_should_be_idempotent_for_the_same_boot_services() {
+        static LOCATE_COUNT: AtomicUsize = AtomicUsize::new(0);
+        extern "efiapi" fn counting_locate_protocol(
+            protocol: *mut Guid,
+            registration: *mut c_void,
+            interface: *mut *mut c_void,
+        ) -> Status {
+            LOCATE_COUNT.fetch_add(1, Ordering::SeqCst);
+            mock_locate_protocol(protocol, registration, interface)
+        }
+
+        let mut boot_services = mock_boot_services();
+        boot_services.locate_protocol = counting_locate_protocol;
+        static TEST_LOGGER: AdvancedLogger = AdvancedLogger::new();
+        assert!(!TEST_LOGGER.is_initialized());
+
+        TEST_LOGGER.init(&mut boot_services);
+        TEST_LOGGER.init(&mut boot_services);
+        assert!(TEST_LOGGER.is_initialized());
+        assert_eq!(LOCATE_COUNT.load(Ordering::SeqCst), 1);
+
+        // a different table re-points the logger.
+        let mut other_boot_services = mock_boot_services();
+        other_boot_services.locate_protocol = mock_locate_recording_protocol;
+        TEST_LOGGER.init(&mut other_boot_services);
+        assert_eq!(LOCATE_COUNT.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            TEST_LOGGER.protocol.load(Ordering::SeqCst) as *const AdvancedLoggerProtocol,
+            &RECORDING_LOGGER_INSTANCE as *const AdvancedLoggerProtocol
+        );
+    }
+
+    #[test]
+    fn init_should_retry_locating_protocol_if_not_found() {
+        static INSTALLED: AtomicBool = AtomicBool::new(false);
+        extern "efiapi" fn late_locate_protocol(
+            protocol: *mut Guid,
+            registration: *mut c_void,
+            interface: *mut *mut c_void,
+        ) -> Status {
+            if !INSTALLED.load(Ordering::SeqCst) {
+                return Status::NOT_FOUND;
+            }
+            mock_locate_protocol(protocol, registration, interface)
+        }
+
+        let mut boot_services = mock_boot_services();
+        boot_services.locate_protocol = late_locate_protocol;
+        static TEST_LOGGER: AdvancedLogger = AdvancedLogger::new();
+
+        // initialized before the protocol is installed: nowhere to write yet.
+        TEST_LOGGER.init(&mut boot_services);
+        assert!(TEST_LOGGER.is_initialized());
+        assert!(TEST_LOGGER.protocol.load(Ordering::SeqCst).is_null());
+
+        // initializing again with the same table once the protocol is installed locates it.
+        INSTALLED.store(true, Ordering::SeqCst);
+        TEST_LOGGER.init(&mut boot_services);
+        assert_eq!(
+            TEST_LOGGER.protocol.load(Ordering::SeqCst) as *const AdvancedLoggerProtocol,
+            &ADVANCED_LOGGER_INSTANCE as *const AdvancedLoggerProtocol
+        );
+    }
+
+    #[test]
+    fn logger_should_write_to_each_enabled_target() {
+        static SERIAL_SINK: AdvancedLoggerProtocol =
+            AdvancedLoggerProtocol { signature: 0, version: 0, write_log: mock_serial_write };
+        static SERIAL_OUTPUT: Mutex<String> = Mutex::new(String::new());
+        static CONSOLE_OUTPUT: Mutex<String> = Mutex::new(String::new());
+
+        extern "efiapi" fn mock_serial_write(
+            _this: *const AdvancedLoggerProtocol,
+            _error_level: usize,
+            buffer: *const u8,
+            buffer_size: usize,
+        ) {
+            let buf: &[u8] = unsafe { from_raw_parts(buffer, buffer_size) };
+            SERIAL_OUTPUT.lock().unwrap().push_str(str::from_utf8(buf).unwrap());
+        }
+
+        // records the output, but reports failure to check that it does not affect the other targets.
+        extern "efiapi" fn mock_console_output_string(
+            _this: *mut simple_text_output::Protocol,
+            string: *mut Char16,
+        ) -> Status {
+            let len = (0..).take_while(|&i| unsafe { *string.add(i) } != 0).count();
+            let string = String::from_utf16(unsafe { from_raw_parts(string, len) }).unwrap();
+            CONSOLE_OUTPUT.lock().unwrap().push_str(&string);
+            Status::DEVICE_ERROR
+        }
+
+        // the console is taken from the system table rather than located.
+        extern "efiapi" fn mock_locate_sinks(
+            protocol: *mut Guid,
+            _registration: *mut c_void,
+            interface: *mut *mut c_void,
+        ) -> Status {
+            let protocol = unsafe { protocol.as_ref().unwrap() };
+            assert_eq!(protocol, &ADVANCED_LOGGER_PROTOCOL_GUID);
+            unsafe { interface.write(&SERIAL_SINK as *const AdvancedLoggerProtocol as *mut c_void) };
+            Status::SUCCESS
+        }
+
+        let console_sink = MaybeUninit::zeroed();
+        let console_sink: &mut simple_text_output::Protocol =
+            std::boxed::Box::leak(std::boxed::Box::new(unsafe { console_sink.assume_init() }));
+        console_sink.output_string = mock_console_output_string;
+        let mut boot_services = mock_boot_services();
+        boot_services.locate_protocol = mock_locate_sinks;
+        let system_table = MaybeUninit::zeroed();
+        let system_table: &mut SystemTable =
+            std::boxed::Box::leak(std::boxed::Box::new(unsafe { system_table.assume_init() }));
+        system_table.con_out = console_sink;
+
+        static BOTH_LOGGER: AdvancedLogger = AdvancedLogger::new();
+        BOTH_LOGGER.init_with_target(&mut boot_services, system_table, LogTarget::SERIAL | LogTarget::CONSOLE);
+        BOTH_LOGGER.log(DEBUG_INFO, format_args!("to {:}\n", "both"));
+        assert_eq!(SERIAL_OUTPUT.lock().unwrap().as_str(), "to both\n");
+        assert_eq!(CONSOLE_OUTPUT.lock().unwrap().as_str(), "to both\r\n");
+
+        SERIAL_OUTPUT.lock().unwrap().clear();
+        CONSOLE_OUTPUT.lock().unwrap().clear();
+
+        static SERIAL_LOGGER: AdvancedLogger = AdvancedLogger::new();
+        SERIAL_LOGGER.init_with_target(&mut boot_services, system_table, LogTarget::SERIAL);
+        SERIAL_LOGGER.log(DEBUG_INFO, format_args!("to {:}\n", "serial"));
+        assert_eq!(SERIAL_OUTPUT.lock().unwrap().as_str(), "to serial\n");
+        assert_eq!(CONSOLE_OUTPUT.lock().unwrap().as_str(), "");
+
+        // lines longer than the conversion buffer reach the console intact.
+        let long_line = "0123456789".repeat(10);
+        BOTH_LOGGER.log(DEBUG_INFO, format_args!("{:}\n", long_line));
+        assert_eq!(CONSOLE_OUTPUT.lock().unwrap().as_str(), long_line + "\r\n");
+
+        // NONE silences all targets.
+        CONSOLE_OUTPUT.lock().unwrap().clear();
+        SERIAL_OUTPUT.lock().unwrap().clear();
+        BOTH_LOGGER.init_with_target(&mut boot_services, system_table, LogTarget::NONE);
+        BOTH_LOGGER.log(DEBUG_INFO, format_args!("to nowhere\n"));
+        assert_eq!(SERIAL_OUTPUT.lock().unwrap().as_str(), "");
+        assert_eq!(CONSOLE_OUTPUT.lock().unwrap().as_str(), "");
+
+        // console output follows ConOut if it is replaced (e.g. when the console splitter starts); without a console,
+        // output still reaches the serial target.
+        BOTH_LOGGER.init_with_target(&mut boot_services, system_table, LogTarget::SERIAL | LogTarget::CONSOLE);
+        system_table.con_out = ptr::null_mut();
+        BOTH_LOGGER.log(DEBUG_INFO, format_args!("without console\n"));
+        assert_eq!(SERIAL_OUTPUT.lock().unwrap().as_str(), "without console\n");
+        assert_eq!(CONSOLE_OUTPUT.lock().unwrap().as_str(), "");
+    }
+
+    #[test]
+    fn init_should_keep_the_targets_set_by_init_with_target() {
+        static SERIAL_SINK: AdvancedLoggerProtocol =
+            AdvancedLoggerProtocol { signature: 0, version: 0, write_log: mock_serial_write };
+        static SERIAL_OUTPUT: Mutex<String> = Mutex::new(String::new());
+        static CONSOLE_OUTPUT: Mutex<String> = Mutex::new(String::new());
+
+        extern "efiapi" fn mock_serial_write(
+            _this: *const AdvancedLoggerProtocol,
+            _error_level: usize,
+            buffer: *const u8,
+            buffer_size: usize,
+        ) {
+            let buf: &[u8] = unsafe { from_raw_parts(buffer, buffer_size) };
+            SERIAL_OUTPUT.lock().unwrap().push_str(str::from_utf8(buf).unwrap());
+        }
+
+        extern "efiapi" fn mock_console_output_string(
+            _this: *mut simple_text_output::Protocol,
+            string: *mut Char16,
+        ) -> Status {
+            let len = (0..).take_while(|&i| unsafe { *string.add(i) } != 0).count();
+            let string = String::from_utf16(unsafe { from_raw_parts(string, len) }).unwrap();
+            CONSOLE_OUTPUT.lock().unwrap().push_str(&string);
+            Status::SUCCESS
+        }
+
+        extern "efiapi" fn mock_locate_serial(
+            _protocol: *mut Guid,
+            _registration: *mut c_void,
+            interface: *mut *mut c_void,
+        ) -> Status {
+            unsafe { interface.write(&SERIAL_SINK as *const AdvancedLoggerProtocol as *mut c_void) };
+            Status::SUCCESS
+        }
+
+        let console_sink = MaybeUninit::zeroed();
+        let console_sink: &mut simple_text_output::Protocol =
+            std::boxed::Box::leak(std::boxed::Box::new(unsafe { console_sink.assume_init() }));
+        console_sink.output_string = mock_console_output_string;
+        let boot_services: &mut BootServices = std::boxed::Box::leak(std::boxed::Box::new(mock_boot_services()));
+        boot_services.locate_protocol = mock_locate_serial;
+        let system_table = MaybeUninit::zeroed();
+        let system_table: &mut SystemTable =
+            std::boxed::Box::leak(std::boxed::Box::new(unsafe { system_table.assume_init() }));
+        system_table.con_out = console_sink;
+        system_table.boot_services = boot_services;
+
+        static TEST_LOGGER: AdvancedLogger = AdvancedLogger::new();
+        TEST_LOGGER.init_with_system_table(system_table, LogTarget::SERIAL | LogTarget::CONSOLE);
+        // e.g. a second entry point in the image calling init_debug after init_debug_with_target.
+        TEST_LOGGER.init(system_table.boot_services);
+        TEST_LOGGER.log(DEBUG_INFO, format_args!("still on the console\n"));
+        assert_eq!(SERIAL_OUTPUT.lock().unwrap().as_str(), "still on the console\n");
+        assert_eq!(CONSOLE_OUTPUT.lock().unwrap().as_str(), "still on the console\r\n");
+    }
+
     #[test]
     fn debug_macro_should_log_things() {
         let mut boot_services = mock_boot_services();
@@ -338,4 +947,161 @@ mod tests {
         debug!(DEBUG_VERBOSE, "This {:} {:} {:} test.\n", "is", "a", "DEBUG_VERBOSE");
         debug!(DEBUG_ERROR, "{:}", "This is a DEBUG_ERROR test.\n");
     }
+
+    #[test]
+    fn logger_should_prefix_lines_with_timestamp_if_enabled() {
+        static FAKE_CLOCK: AtomicU64 = AtomicU64::new(0);
+        fn fake_clock() -> u64 {
+            FAKE_CLOCK.fetch_add(5, Ordering::SeqCst) + 5
+        }
+
+        let mut boot_services = mock_boot_services();
+        boot_services.locate_protocol = mock_locate_recording_protocol;
+        static TEST_LOGGER: AdvancedLogger = AdvancedLogger::new();
+        TEST_LOGGER.init(&mut boot_services);
+
+        // disabled by default: output is unprefixed.
+        TEST_LOGGER.log(DEBUG_INFO, format_args!("unprefixed {:}\n", "line"));
+        assert_eq!(RECORDED_LOG.lock().unwrap().as_str(), "unprefixed line\n");
+        RECORDED_LOG.lock().unwrap().clear();
+
+        TEST_LOGGER.set_timestamp_source(fake_clock);
+        TEST_LOGGER.set_timestamp_enabled(true);
+
+        // a line built from several writes gets a single prefix, and embedded newlines start a new prefixed line.
+        TEST_LOGGER.log(DEBUG_INFO, format_args!("first {:}", "line"));
+        TEST_LOGGER.log(DEBUG_INFO, format_args!(" continued\nsecond line\n"));
+        TEST_LOGGER.log(DEBUG_ERROR, format_args!("third line\n"));
+        assert_eq!(
+            RECORDED_LOG.lock().unwrap().as_str(),
+            "[               5] first line continued\n[              10] second line\n[              15] third line\n"
+        );
+
+        let mut last_timestamp = 0;
+        for line in RECORDED_LOG.lock().unwrap().lines() {
+            let timestamp: u64 = line[1..17].trim().parse().unwrap();
+            assert!(timestamp >= last_timestamp);
+            last_timestamp = timestamp;
+        }
+        RECORDED_LOG.lock().unwrap().clear();
+
+        TEST_LOGGER.set_timestamp_enabled(false);
+        TEST_LOGGER.log(DEBUG_INFO, format_args!("unprefixed line\n"));
+        assert_eq!(RECORDED_LOG.lock().unwrap().as_str(), "unprefixed line\n");
+    }
+
+    #[test]
+    fn timestamp_should_use_configured_source_even_if_prefix_is_disabled() {
+        fn fixed_clock() -> u64 {
+            42
+        }
+
+        static TEST_LOGGER: AdvancedLogger = AdvancedLogger::new();
+        TEST_LOGGER.set_timestamp_source(fixed_clock);
+        assert!(TEST_LOGGER.timestamp_source().is_none());
+        assert_eq!(TEST_LOGGER.timestamp(), 42);
+    }
+
+    #[test]
+    fn logger_should_retain_output_in_memory_log_without_protocol() {
+        static TEST_LOGGER: AdvancedLogger = AdvancedLogger::new();
+        TEST_LOGGER.memory_log.init(std::boxed::Box::leak(std::vec![0u8; 32].into_boxed_slice()));
+
+        TEST_LOGGER.log(DEBUG_INFO, format_args!("This is {:} line.\n", "the first"));
+        TEST_LOGGER.log(DEBUG_ERROR, format_args!("This is {:} line.\n", "the second"));
+
+        let mut output = std::vec::Vec::new();
+        TEST_LOGGER.memory_log.drain(&mut |bytes| output.extend_from_slice(bytes));
+        assert_eq!(str::from_utf8(&output).unwrap(), " line.\nThis is the second line.\n");
+    }
+
+    #[test]
+    fn persistent_buffer_should_reject_invalid_regions() {
+        let region = std::boxed::Box::leak(std::vec![0u64; 8].into_boxed_slice());
+        let base = region.as_mut_ptr() as u64;
+        assert_eq!(unsafe { init_debug_with_persistent_buffer(0, 64) }, Err(Status::INVALID_PARAMETER));
+        assert_eq!(unsafe { init_debug_with_persistent_buffer(base + 1, 63) }, Err(Status::INVALID_PARAMETER));
+        assert_eq!(unsafe { init_debug_with_persistent_buffer(base, 16) }, Err(Status::BUFFER_TOO_SMALL));
+    }
+
+    #[test]
+    fn debug_macro_should_respect_module_debug_level() {
+        // records whether it has been formatted.
+        static FORMATTED: AtomicBool = AtomicBool::new(false);
+        struct FormatTracker;
+        impl fmt::Display for FormatTracker {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                FORMATTED.store(true, Ordering::SeqCst);
+                f.write_str("DEBUG_INFO")
+            }
+        }
+
+        const HID_GUID: Guid = Guid::from_fields(0x1, 0, 0, 0, 0, &[0, 0, 0, 0, 0, 0]);
+        const TELEMETRY_GUID: Guid = Guid::from_fields(0x2, 0, 0, 0, 0, &[0, 0, 0, 0, 0, 0]);
+        const OTHER_GUID: Guid = Guid::from_fields(0x3, 0, 0, 0, 0, &[0, 0, 0, 0, 0, 0]);
+
+        let _lock = DEBUG_LEVEL_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        assert_eq!(set_module_debug_level(&HID_GUID, DEBUG_VERBOSE | DEBUG_INFO | DEBUG_ERROR), Ok(()));
+        assert_eq!(set_module_debug_level(&TELEMETRY_GUID, DEBUG_INFO), Ok(()));
+        assert_eq!(set_module_debug_level(&TELEMETRY_GUID, DEBUG_ERROR), Ok(()));
+
+        assert_eq!(_module_debug_level(&HID_GUID), DEBUG_VERBOSE | DEBUG_INFO | DEBUG_ERROR);
+        assert_eq!(_module_debug_level(&TELEMETRY_GUID), DEBUG_ERROR);
+        // modules without a mask of their own use the global mask.
+        set_debug_level(DEBUG_WARN | DEBUG_ERROR);
+        assert_eq!(_module_debug_level(&OTHER_GUID), DEBUG_WARN | DEBUG_ERROR);
+        set_debug_level(usize::MAX);
+
+        debugln!(guid: TELEMETRY_GUID, DEBUG_INFO, "This is a {:} test.", FormatTracker);
+        assert!(!FORMATTED.load(Ordering::SeqCst));
+
+        debugln!(guid: HID_GUID, DEBUG_INFO, "This is a {:} test.", FormatTracker);
+        assert!(FORMATTED.load(Ordering::SeqCst));
+
+        // the table is fixed-capacity; existing entries can still be updated when it is full.
+        for index in 0..MAX_MODULE_DEBUG_LEVELS - 2 {
+            let guid = Guid::from_fields(0x100 + index as u32, 0, 0, 0, 0, &[0, 0, 0, 0, 0, 0]);
+            assert_eq!(set_module_debug_level(&guid, DEBUG_ERROR), Ok(()));
+        }
+        assert_eq!(set_module_debug_level(&OTHER_GUID, DEBUG_ERROR), Err(Status::OUT_OF_RESOURCES));
+        assert_eq!(set_module_debug_level(&TELEMETRY_GUID, DEBUG_WARN), Ok(()));
+        assert_eq!(_module_debug_level(&TELEMETRY_GUID), DEBUG_WARN);
+    }
+
+    #[test]
+    fn debug_macro_should_respect_debug_level() {
+        // records whether it has been formatted.
+        static FORMATTED: AtomicBool = AtomicBool::new(false);
+        struct FormatTracker;
+        impl fmt::Display for FormatTracker {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                FORMATTED.store(true, Ordering::SeqCst);
+                f.write_str("DEBUG_INFO")
+            }
+        }
+
+        let _lock = DEBUG_LEVEL_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut boot_services = mock_boot_services();
+        init_debug(&mut boot_services);
+
+        assert_eq!(get_debug_level(), usize::MAX);
+        set_debug_level(usize::MAX & !DEBUG_INFO);
+        assert_eq!(get_debug_level(), usize::MAX & !DEBUG_INFO);
+
+        debugln!(DEBUG_INFO, "This is a {:} test.", FormatTracker);
+        assert!(!FORMATTED.load(Ordering::SeqCst));
+
+        //with the std feature, output goes to the console rather than the logger protocol.
+        #[cfg(not(feature = "std"))]
+        {
+            let error_writes = ERROR_WRITES.load(Ordering::SeqCst);
+            debugln!(DEBUG_ERROR, "This is a DEBUG_ERROR test.");
+            assert!(ERROR_WRITES.load(Ordering::SeqCst) > error_writes);
+        }
+
+        set_debug_level(usize::MAX);
+        debugln!(DEBUG_INFO, "This is a {:} test.", FormatTracker);
+        assert!(FORMATTED.load(Ordering::SeqCst));
+    }
 }
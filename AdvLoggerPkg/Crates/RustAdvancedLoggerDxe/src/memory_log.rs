@@ -0,0 +1,283 @@
+//! In-memory ring buffer log backend.
+//!
+//! Retains the most recent log output in a caller-provided buffer so that it can be retrieved by a later phase (e.g.
+//! on systems with no active serial port). The buffer may optionally live in a reserved memory region that survives a
+//! warm reset, in which case a header describing the ring is kept at the start of the region so that the log can be
+//! validated and resumed after the reset.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation. All rights reserved.
+//!
+//! SPDX-License-Identifier: BSD-2-Clause-Patent
+//!
+use core::{
+    mem, ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::spin_lock::SpinLock;
+
+/// Signature identifying a valid persistent log header.
+pub(crate) const PERSISTENT_LOG_SIGNATURE: u64 = u64::from_le_bytes(*b"RUSTLOG1");
+
+/// Header at the start of a persistent log region, describing the ring buffer that follows it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PersistentLogHeader {
+    pub(crate) signature: u64,
+    pub(crate) capacity: u64,
+    pub(crate) head: u64,
+    pub(crate) len: u64,
+}
+
+// Ring buffer state. `head` is the index of the oldest byte, and `len` is the number of valid bytes. If `header` is
+// not NULL, it is kept in sync with `head` and `len` so that the ring can be resumed after a warm reset.
+#[derive(Debug)]
+struct RingBuffer {
+    buffer: *mut u8,
+    capacity: usize,
+    head: usize,
+    len: usize,
+    header: *mut PersistentLogHeader,
+}
+
+// Safety: `buffer` is a `&'static mut [u8]` that is exclusively owned by the ring.
+unsafe impl Send for RingBuffer {}
+
+impl RingBuffer {
+    // appends `bytes` to the ring, discarding the oldest bytes if there is not enough room.
+    fn write(&mut self, bytes: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let buffer = unsafe { core::slice::from_raw_parts_mut(self.buffer, self.capacity) };
+
+        // only the last `capacity` bytes can be retained.
+        let bytes = &bytes[bytes.len().saturating_sub(self.capacity)..];
+
+        // copy in up to two segments: from the tail to the end of the buffer, then wrapped to the start.
+        let tail = (self.head + self.len) % self.capacity;
+        let first = bytes.len().min(self.capacity - tail);
+        buffer[tail..tail + first].copy_from_slice(&bytes[..first]);
+        buffer[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+
+        let new_len = self.len + bytes.len();
+        if new_len > self.capacity {
+            self.head = (self.head + new_len - self.capacity) % self.capacity;
+            self.len = self.capacity;
+        } else {
+            self.len = new_len;
+        }
+        self.sync_header();
+    }
+
+    // passes the retained bytes (oldest first) to `f` in up to two slices, then empties the ring.
+    fn drain(&mut self, f: &mut impl FnMut(&[u8])) {
+        if self.len == 0 {
+            return;
+        }
+        let buffer = unsafe { core::slice::from_raw_parts(self.buffer, self.capacity) };
+        let first = self.len.min(self.capacity - self.head);
+        f(&buffer[self.head..self.head + first]);
+        if first < self.len {
+            f(&buffer[..self.len - first]);
+        }
+        self.head = 0;
+        self.len = 0;
+        self.sync_header();
+    }
+
+    // records the current ring state in the persistent header, if there is one.
+    fn sync_header(&mut self) {
+        if let Some(header) = unsafe { self.header.as_mut() } {
+            header.head = self.head as u64;
+            header.len = self.len as u64;
+        }
+    }
+}
+
+/// A fixed-capacity in-memory log that retains the most recent output.
+///
+/// Access is serialized with a spinlock. Writes do not wait for the lock: log output can be produced at any TPL, so a
+/// write that interrupts another holder of the lock (e.g. a drain) is discarded rather than deadlocking.
+#[derive(Debug)]
+pub(crate) struct MemoryLog {
+    initialized: AtomicBool,
+    ring: SpinLock<RingBuffer>,
+}
+
+impl MemoryLog {
+    // creates a new MemoryLog with no backing store; writes are discarded until `init` is called.
+    pub(crate) const fn new() -> Self {
+        Self {
+            initialized: AtomicBool::new(false),
+            ring: SpinLock::new(RingBuffer {
+                buffer: ptr::null_mut(),
+                capacity: 0,
+                head: 0,
+                len: 0,
+                header: ptr::null_mut(),
+            }),
+        }
+    }
+
+    /// Registers `buffer` as the backing store for the log, discarding any previously retained output.
+    pub(crate) fn init(&self, buffer: &'static mut [u8]) {
+        *self.ring.lock() = RingBuffer {
+            buffer: buffer.as_mut_ptr(),
+            capacity: buffer.len(),
+            head: 0,
+            len: 0,
+            header: ptr::null_mut(),
+        };
+        self.initialized.store(true, Ordering::SeqCst);
+    }
+
+    /// Registers `region` as a persistent backing store for the log. The region starts with a [`PersistentLogHeader`]
+    /// and the remainder holds the ring buffer. If the region already contains a valid header for a ring of the same
+    /// capacity (e.g. written before a warm reset), the retained output is resumed and true is returned; otherwise the
+    /// header is (re)initialized to an empty ring and false is returned.
+    ///
+    /// `region` must be aligned for, and at least as large as, [`PersistentLogHeader`].
+    pub(crate) fn init_persistent(&self, region: &'static mut [u8]) -> bool {
+        assert!(region.len() >= mem::size_of::<PersistentLogHeader>(), "persistent log region too small");
+        assert_eq!(region.as_ptr() as usize % mem::align_of::<PersistentLogHeader>(), 0, "persistent log misaligned");
+
+        let (header, buffer) = region.split_at_mut(mem::size_of::<PersistentLogHeader>());
+        let header = header.as_mut_ptr() as *mut PersistentLogHeader;
+        let capacity = buffer.len();
+
+        let existing = unsafe { header.read() };
+        let resumed = existing.signature == PERSISTENT_LOG_SIGNATURE
+            && existing.capacity == capacity as u64
+            && existing.head < existing.capacity.max(1)
+            && existing.len <= existing.capacity;
+        if !resumed {
+            let empty =
+                PersistentLogHeader { signature: PERSISTENT_LOG_SIGNATURE, capacity: capacity as u64, head: 0, len: 0 };
+            unsafe { header.write(empty) };
+        }
+
+        let header_state = unsafe { header.read() };
+        *self.ring.lock() = RingBuffer {
+            buffer: buffer.as_mut_ptr(),
+            capacity,
+            head: header_state.head as usize,
+            len: header_state.len as usize,
+            header,
+        };
+        self.initialized.store(true, Ordering::SeqCst);
+        resumed
+    }
+
+    /// Returns whether a backing store has been registered.
+    pub(crate) fn is_initialized(&self) -> bool {
+        self.initialized.load(Ordering::SeqCst)
+    }
+
+    /// Appends `bytes` to the log. Discarded if the log is in use.
+    pub(crate) fn write(&self, bytes: &[u8]) {
+        if let Some(mut ring) = self.ring.try_lock() {
+            ring.write(bytes);
+        }
+    }
+
+    /// Passes the retained output (oldest first) to `f`, and empties the log.
+    pub(crate) fn drain(&self, f: &mut impl FnMut(&[u8])) {
+        self.ring.lock().drain(f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::{boxed::Box, vec, vec::Vec};
+
+    use super::{MemoryLog, PersistentLogHeader, PERSISTENT_LOG_SIGNATURE};
+
+    fn drain_to_vec(log: &MemoryLog) -> Vec<u8> {
+        let mut output = Vec::new();
+        log.drain(&mut |bytes| output.extend_from_slice(bytes));
+        output
+    }
+
+    #[test]
+    fn memory_log_should_discard_writes_until_initialized() {
+        let log = MemoryLog::new();
+        assert!(!log.is_initialized());
+        log.write(b"lost\n");
+        assert!(drain_to_vec(&log).is_empty());
+    }
+
+    #[test]
+    fn memory_log_should_retain_most_recent_bytes() {
+        let log = MemoryLog::new();
+        log.init(Box::leak(vec![0u8; 16].into_boxed_slice()));
+        assert!(log.is_initialized());
+
+        log.write(b"line 1\n");
+        assert_eq!(drain_to_vec(&log), b"line 1\n");
+        assert!(drain_to_vec(&log).is_empty());
+
+        // write more than the capacity in several pieces - only the last 16 bytes should remain.
+        log.write(b"line 2\n");
+        log.write(b"line 3\n");
+        log.write(b"line 4\n");
+        let output = drain_to_vec(&log);
+        assert_eq!(output, b"2\nline 3\nline 4\n");
+
+        // the oldest line may be truncated, but every line after the first newline is intact.
+        let lines: Vec<&[u8]> = output.split_inclusive(|x| *x == b'\n').skip(1).collect();
+        assert_eq!(lines, [&b"line 3\n"[..], &b"line 4\n"[..]]);
+
+        // a single write larger than the capacity.
+        log.write(b"line 5\nline 6\nline 7\n");
+        assert_eq!(drain_to_vec(&log), b"5\nline 6\nline 7\n");
+    }
+
+    #[test]
+    fn persistent_memory_log_should_be_resumed_after_reset() {
+        // simulated reserved region: a 32-byte header followed by a 16-byte ring.
+        let region = Box::leak(vec![0u64; 6].into_boxed_slice());
+        let base = region.as_mut_ptr() as *mut u8;
+        let region = || unsafe { core::slice::from_raw_parts_mut(base, 48) };
+        let header = || unsafe { (base as *const PersistentLogHeader).read() };
+
+        // an uninitialized region gets a fresh header.
+        let log = MemoryLog::new();
+        assert!(!log.init_persistent(region()));
+        assert_eq!(
+            header(),
+            PersistentLogHeader { signature: PERSISTENT_LOG_SIGNATURE, capacity: 16, head: 0, len: 0 }
+        );
+        assert_eq!(&region()[..8], b"RUSTLOG1");
+
+        log.write(b"line 1\n");
+        log.write(b"line 2\nline 3\n");
+        assert_eq!(
+            header(),
+            PersistentLogHeader { signature: PERSISTENT_LOG_SIGNATURE, capacity: 16, head: 5, len: 16 }
+        );
+
+        // after a reset, the retained output is resumed and new output is appended to it.
+        let log = MemoryLog::new();
+        assert!(log.init_persistent(region()));
+        log.write(b"4\n");
+        assert_eq!(drain_to_vec(&log), b"line 2\nline 3\n4\n");
+        assert_eq!(header().len, 0);
+
+        // a corrupted header is not trusted.
+        log.write(b"lost\n");
+        region()[0] = 0;
+        let log = MemoryLog::new();
+        assert!(!log.init_persistent(region()));
+        assert!(drain_to_vec(&log).is_empty());
+
+        // nor is a header describing a ring of a different size.
+        log.write(b"lost\n");
+        let log = MemoryLog::new();
+        assert!(!log.init_persistent(unsafe { core::slice::from_raw_parts_mut(base, 40) }));
+        assert!(drain_to_vec(&log).is_empty());
+    }
+}
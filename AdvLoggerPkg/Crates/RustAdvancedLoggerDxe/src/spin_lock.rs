@@ -0,0 +1,94 @@
+//! Minimal spinlock for the advanced logger.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation. All rights reserved.
+//!
+//! SPDX-License-Identifier: BSD-2-Clause-Patent
+//!
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// A spinlock protecting a value of type `T`.
+///
+/// Log output can be produced at any TPL, so callers that may interrupt another holder of the lock should use
+/// [`SpinLock::try_lock`] rather than spinning forever.
+#[derive(Debug)]
+pub(crate) struct SpinLock<T> {
+    lock: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+// Safety: all access to `data` is serialized by `lock`.
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    /// Creates a new, unlocked SpinLock containing `data`.
+    pub(crate) const fn new(data: T) -> Self {
+        Self { lock: AtomicBool::new(false), data: UnsafeCell::new(data) }
+    }
+
+    /// Acquires the lock if it is available.
+    pub(crate) fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        if self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            Some(SpinLockGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    /// Acquires the lock, spinning until it is available.
+    pub(crate) fn lock(&self) -> SpinLockGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Provides access to the data protected by a [`SpinLock`]; the lock is released when the guard is dropped.
+pub(crate) struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.lock.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpinLock;
+
+    #[test]
+    fn spin_lock_should_be_exclusive() {
+        let lock = SpinLock::new(0);
+        {
+            let mut guard = lock.lock();
+            *guard += 1;
+            assert!(lock.try_lock().is_none());
+        }
+        let guard = lock.try_lock().unwrap();
+        assert_eq!(*guard, 1);
+    }
+}
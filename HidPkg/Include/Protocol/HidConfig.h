@@ -0,0 +1,218 @@
+/*++ @file HidConfig.h
+
+  Copyright (C) Microsoft Corporation. All rights reserved.
+  SPDX-License-Identifier: BSD-2-Clause-Patent
+
+Module Name:
+
+  HidConfig.h
+
+Abstract:
+
+  This header defines an interface for tuning the UefiHidDxeV2 driver at runtime. Settings are driver-wide and take
+  effect on the next report received from each device.
+
+Environment:
+
+  UEFI pre-boot Driver Execution Environment (DXE).
+
+--*/
+
+#ifndef __HID_CONFIG_PROTOCOL_H__
+#define __HID_CONFIG_PROTOCOL_H__
+
+typedef struct _HID_CONFIG_PROTOCOL HID_CONFIG_PROTOCOL;
+
+#define HID_CONFIG_PROTOCOL_REVISION  1
+
+// Supported range for the pointer movement scale, in percent.
+#define HID_CONFIG_MIN_POINTER_SCALE  10
+#define HID_CONFIG_MAX_POINTER_SCALE  1000
+
+// Maximum key debounce time, in milliseconds.
+#define HID_CONFIG_MAX_DEBOUNCE_TIME  100
+
+// Supported ranges for the key repeat delay and interval, in milliseconds.
+#define HID_CONFIG_MAX_KEY_REPEAT_DELAY     2000
+#define HID_CONFIG_MIN_KEY_REPEAT_INTERVAL  10
+#define HID_CONFIG_MAX_KEY_REPEAT_INTERVAL  1000
+
+// Maximum number of reports that can be captured with SetVerboseCapture.
+#define HID_CONFIG_MAX_VERBOSE_REPORTS  1000
+
+/**
+  Returns the scale applied to relative pointer movement.
+
+  @param  This   - pointer to the protocol instance.
+  @param  Scale  - receives the pointer movement scale, in percent.
+
+  @retval EFI_SUCCESS           - The scale was returned.
+  @retval EFI_INVALID_PARAMETER - This or Scale is NULL.
+**/
+typedef
+EFI_STATUS
+(EFIAPI *HID_CONFIG_GET_POINTER_SCALE)(
+  IN  HID_CONFIG_PROTOCOL  *This,
+  OUT UINT32               *Scale
+  );
+
+/**
+  Sets the scale applied to relative pointer movement.
+
+  @param  This   - pointer to the protocol instance.
+  @param  Scale  - pointer movement scale, in percent (100 is unscaled).
+
+  @retval EFI_SUCCESS           - The scale was set.
+  @retval EFI_INVALID_PARAMETER - This is NULL, or Scale is outside of the supported range.
+**/
+typedef
+EFI_STATUS
+(EFIAPI *HID_CONFIG_SET_POINTER_SCALE)(
+  IN HID_CONFIG_PROTOCOL  *This,
+  IN UINT32               Scale
+  );
+
+/**
+  Returns the key debounce time.
+
+  @param  This          - pointer to the protocol instance.
+  @param  DebounceTime  - receives the debounce time, in milliseconds (zero if debouncing is disabled).
+
+  @retval EFI_SUCCESS           - The debounce time was returned.
+  @retval EFI_INVALID_PARAMETER - This or DebounceTime is NULL.
+**/
+typedef
+EFI_STATUS
+(EFIAPI *HID_CONFIG_GET_DEBOUNCE_TIME)(
+  IN  HID_CONFIG_PROTOCOL  *This,
+  OUT UINT32               *DebounceTime
+  );
+
+/**
+  Sets the key debounce time. A key that is pressed again within DebounceTime of being released is treated as if it
+  had been held.
+
+  @param  This          - pointer to the protocol instance.
+  @param  DebounceTime  - debounce time, in milliseconds, or zero to disable debouncing.
+
+  @retval EFI_SUCCESS           - The debounce time was set.
+  @retval EFI_INVALID_PARAMETER - This is NULL, or DebounceTime is greater than HID_CONFIG_MAX_DEBOUNCE_TIME.
+**/
+typedef
+EFI_STATUS
+(EFIAPI *HID_CONFIG_SET_DEBOUNCE_TIME)(
+  IN HID_CONFIG_PROTOCOL  *This,
+  IN UINT32               DebounceTime
+  );
+
+/**
+  Returns the key repeat settings.
+
+  @param  This      - pointer to the protocol instance.
+  @param  Delay     - receives the delay before a held key starts to repeat, in milliseconds (zero if key repeat is
+                      disabled).
+  @param  Interval  - receives the interval between repeats, in milliseconds.
+
+  @retval EFI_SUCCESS           - The key repeat settings were returned.
+  @retval EFI_INVALID_PARAMETER - This, Delay or Interval is NULL.
+**/
+typedef
+EFI_STATUS
+(EFIAPI *HID_CONFIG_GET_KEY_REPEAT)(
+  IN  HID_CONFIG_PROTOCOL  *This,
+  OUT UINT32               *Delay,
+  OUT UINT32               *Interval
+  );
+
+/**
+  Sets the key repeat settings.
+
+  @param  This      - pointer to the protocol instance.
+  @param  Delay     - delay before a held key starts to repeat, in milliseconds, or zero to disable key repeat.
+  @param  Interval  - interval between repeats, in milliseconds.
+
+  @retval EFI_SUCCESS           - The key repeat settings were set.
+  @retval EFI_INVALID_PARAMETER - This is NULL, Delay is greater than HID_CONFIG_MAX_KEY_REPEAT_DELAY, or Interval is
+                                  outside of the supported range.
+**/
+typedef
+EFI_STATUS
+(EFIAPI *HID_CONFIG_SET_KEY_REPEAT)(
+  IN HID_CONFIG_PROTOCOL  *This,
+  IN UINT32               Delay,
+  IN UINT32               Interval
+  );
+
+/**
+  Returns whether secure input is enabled.
+
+  @param  This     - pointer to the protocol instance.
+  @param  Enabled  - receives TRUE if secure input is enabled.
+
+  @retval EFI_SUCCESS           - The secure input state was returned.
+  @retval EFI_INVALID_PARAMETER - This or Enabled is NULL.
+**/
+typedef
+EFI_STATUS
+(EFIAPI *HID_CONFIG_GET_SECURE_INPUT)(
+  IN  HID_CONFIG_PROTOCOL  *This,
+  OUT BOOLEAN              *Enabled
+  );
+
+/**
+  Enables or disables secure input. While secure input is enabled, the contents of input reports are not written to
+  the debug log (including by SetVerboseCapture), so that secrets such as passwords do not end up in the log.
+
+  @param  This     - pointer to the protocol instance.
+  @param  Enabled  - TRUE to enable secure input, FALSE to disable it.
+
+  @retval EFI_SUCCESS           - Secure input was enabled or disabled.
+  @retval EFI_INVALID_PARAMETER - This is NULL.
+**/
+typedef
+EFI_STATUS
+(EFIAPI *HID_CONFIG_SET_SECURE_INPUT)(
+  IN HID_CONFIG_PROTOCOL  *This,
+  IN BOOLEAN              Enabled
+  );
+
+/**
+  Enables verbose logging of the next ReportCount input reports received from a single device. Capture turns itself
+  off once ReportCount reports have been logged. Only one device is captured at a time; a new request replaces any
+  capture in progress.
+
+  @param  This         - pointer to the protocol instance.
+  @param  Controller   - controller handle of the HID device to capture.
+  @param  ReportCount  - number of reports to capture, or zero to stop any capture in progress.
+
+  @retval EFI_SUCCESS           - Capture was started or stopped.
+  @retval EFI_INVALID_PARAMETER - This is NULL, Controller is NULL and ReportCount is non-zero, or ReportCount is
+                                  greater than HID_CONFIG_MAX_VERBOSE_REPORTS.
+**/
+typedef
+EFI_STATUS
+(EFIAPI *HID_CONFIG_SET_VERBOSE_CAPTURE)(
+  IN HID_CONFIG_PROTOCOL  *This,
+  IN EFI_HANDLE           Controller,
+  IN UINT32               ReportCount
+  );
+
+//
+// HID Config Protocol struct.
+//
+struct _HID_CONFIG_PROTOCOL {
+  UINT32                          Revision;
+  HID_CONFIG_GET_POINTER_SCALE    GetPointerScale;
+  HID_CONFIG_SET_POINTER_SCALE    SetPointerScale;
+  HID_CONFIG_GET_DEBOUNCE_TIME    GetDebounceTime;
+  HID_CONFIG_SET_DEBOUNCE_TIME    SetDebounceTime;
+  HID_CONFIG_GET_KEY_REPEAT       GetKeyRepeat;
+  HID_CONFIG_SET_KEY_REPEAT       SetKeyRepeat;
+  HID_CONFIG_GET_SECURE_INPUT     GetSecureInput;
+  HID_CONFIG_SET_SECURE_INPUT     SetSecureInput;
+  HID_CONFIG_SET_VERBOSE_CAPTURE  SetVerboseCapture;
+};
+
+extern EFI_GUID  gHidConfigProtocolGuid;
+
+#endif //__HID_CONFIG_PROTOCOL_H__
@@ -42,6 +42,8 @@ pub trait UefiBootServices {
 
     fn signal_event(&self, event: efi::Event) -> efi::Status;
 
+    fn set_timer(&self, event: efi::Event, r#type: efi::TimerDelay, trigger_time: u64) -> efi::Status;
+
     fn raise_tpl(&self, new_tpl: efi::Tpl) -> efi::Tpl;
 
     fn restore_tpl(&self, old_tpl: efi::Tpl);
@@ -143,6 +145,9 @@ impl UefiBootServices for StandardUefiBootServices {
     fn signal_event(&self, event: efi::Event) -> efi::Status {
         (self.boot_services().signal_event)(event)
     }
+    fn set_timer(&self, event: efi::Event, r#type: efi::TimerDelay, trigger_time: u64) -> efi::Status {
+        (self.boot_services().set_timer)(event, r#type, trigger_time)
+    }
     fn raise_tpl(&self, new_tpl: efi::Tpl) -> efi::Tpl {
         (self.boot_services().raise_tpl)(new_tpl)
     }
@@ -232,6 +237,10 @@ mod test {
         efi::Status::SUCCESS
     }
 
+    extern "efiapi" fn mock_set_timer(_event: efi::Event, _type: efi::TimerDelay, _trigger_time: u64) -> efi::Status {
+        efi::Status::SUCCESS
+    }
+
     extern "efiapi" fn mock_raise_tpl(_new_tpl: efi::Tpl) -> efi::Tpl {
         efi::TPL_APPLICATION
     }
@@ -291,6 +300,7 @@ mod test {
         boot_services.create_event_ex = mock_create_event_ex;
         boot_services.close_event = mock_close_event;
         boot_services.signal_event = mock_signal_event;
+        boot_services.set_timer = mock_set_timer;
         boot_services.raise_tpl = mock_raise_tpl;
         boot_services.restore_tpl = mock_restore_tpl;
         boot_services.install_protocol_interface = mock_install_protocol_interface;
@@ -330,6 +340,7 @@ mod test {
 
         assert_eq!(test_boot_services.close_event(event), efi::Status::SUCCESS);
         assert_eq!(test_boot_services.signal_event(event), efi::Status::SUCCESS);
+        assert_eq!(test_boot_services.set_timer(event, efi::TIMER_PERIODIC, 0), efi::Status::SUCCESS);
         assert_eq!(test_boot_services.raise_tpl(efi::TPL_HIGH_LEVEL), efi::TPL_APPLICATION);
         test_boot_services.restore_tpl(efi::TPL_APPLICATION);
         assert_eq!(
@@ -0,0 +1,572 @@
+//! HID Driver Configuration Protocol FFI Support.
+//!
+//! This module provides driver-wide settings that can be tuned at runtime, and a
+//! vendor protocol that exposes them to other modules. Handlers read the settings
+//! as they process reports, so changes take effect on the next report.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation. All rights reserved.
+//! SPDX-License-Identifier: BSD-2-Clause-Patent
+//!
+use alloc::boxed::Box;
+use core::{
+    ffi::c_void,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering},
+};
+
+use r_efi::efi;
+
+use crate::boot_services::UefiBootServices;
+
+/// HID Driver Configuration Protocol GUID: {5c6e8b36-8d0a-4b8e-9f3c-6a1d2e7b4f90}
+pub const PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x5c6e8b36, 0x8d0a, 0x4b8e, 0x9f, 0x3c, &[0x6a, 0x1d, 0x2e, 0x7b, 0x4f, 0x90]);
+
+/// Revision of the protocol structure.
+pub const PROTOCOL_REVISION: u32 = 1;
+
+/// Default pointer movement scale, in percent (i.e. unscaled).
+pub const DEFAULT_POINTER_SCALE: u32 = 100;
+/// Minimum supported pointer movement scale, in percent.
+pub const MIN_POINTER_SCALE: u32 = 10;
+/// Maximum supported pointer movement scale, in percent.
+pub const MAX_POINTER_SCALE: u32 = 1000;
+/// Maximum supported key debounce time, in milliseconds.
+pub const MAX_DEBOUNCE_TIME: u32 = 100;
+/// Maximum supported delay before a held key starts to repeat, in milliseconds.
+pub const MAX_KEY_REPEAT_DELAY: u32 = 2000;
+/// Minimum supported interval between repeats of a held key, in milliseconds.
+pub const MIN_KEY_REPEAT_INTERVAL: u32 = 10;
+/// Maximum supported interval between repeats of a held key, in milliseconds.
+pub const MAX_KEY_REPEAT_INTERVAL: u32 = 1000;
+/// Default interval between repeats of a held key, in milliseconds.
+pub const DEFAULT_KEY_REPEAT_INTERVAL: u32 = 20;
+/// Maximum number of reports that can be captured with a single verbose capture request.
+pub const MAX_VERBOSE_REPORTS: u32 = 1000;
+
+/// Returns the current pointer movement scale, in percent.
+pub type GetPointerScale = extern "efiapi" fn(this: *mut Protocol, scale: *mut u32) -> efi::Status;
+
+/// Sets the pointer movement scale, in percent. Returns `INVALID_PARAMETER` if the scale is outside of
+/// [`MIN_POINTER_SCALE`]..=[`MAX_POINTER_SCALE`].
+pub type SetPointerScale = extern "efiapi" fn(this: *mut Protocol, scale: u32) -> efi::Status;
+
+/// Returns the current key debounce time, in milliseconds.
+pub type GetDebounceTime = extern "efiapi" fn(this: *mut Protocol, debounce_time: *mut u32) -> efi::Status;
+
+/// Sets the key debounce time, in milliseconds, or zero to disable debouncing. Returns `INVALID_PARAMETER` if the time
+/// is larger than [`MAX_DEBOUNCE_TIME`].
+pub type SetDebounceTime = extern "efiapi" fn(this: *mut Protocol, debounce_time: u32) -> efi::Status;
+
+/// Returns the current key repeat delay and interval, in milliseconds.
+pub type GetKeyRepeat = extern "efiapi" fn(this: *mut Protocol, delay: *mut u32, interval: *mut u32) -> efi::Status;
+
+/// Sets the delay before a held key starts to repeat (or zero to disable key repeat), and the interval between
+/// repeats, in milliseconds. Returns `INVALID_PARAMETER` if the delay is larger than [`MAX_KEY_REPEAT_DELAY`] or the
+/// interval is outside of [`MIN_KEY_REPEAT_INTERVAL`]..=[`MAX_KEY_REPEAT_INTERVAL`].
+pub type SetKeyRepeat = extern "efiapi" fn(this: *mut Protocol, delay: u32, interval: u32) -> efi::Status;
+
+/// Returns whether secure input is enabled.
+pub type GetSecureInput = extern "efiapi" fn(this: *mut Protocol, enabled: *mut efi::Boolean) -> efi::Status;
+
+/// Enables or disables secure input. While it is enabled, the contents of input reports are not written to the debug
+/// log (including by verbose capture), so that secrets such as passwords do not end up in the log.
+pub type SetSecureInput = extern "efiapi" fn(this: *mut Protocol, enabled: efi::Boolean) -> efi::Status;
+
+/// Logs each of the next `report_count` reports from the given controller to the debug log, then stops. A
+/// `report_count` of zero stops any capture in progress. Returns `INVALID_PARAMETER` if the controller is null for a
+/// non-zero `report_count`, or `report_count` is larger than [`MAX_VERBOSE_REPORTS`].
+pub type SetVerboseCapture =
+    extern "efiapi" fn(this: *mut Protocol, controller: efi::Handle, report_count: u32) -> efi::Status;
+
+/// HID Driver Configuration Protocol interface.
+#[repr(C)]
+pub struct Protocol {
+    pub revision: u32,
+    pub get_pointer_scale: GetPointerScale,
+    pub set_pointer_scale: SetPointerScale,
+    pub get_debounce_time: GetDebounceTime,
+    pub set_debounce_time: SetDebounceTime,
+    pub get_key_repeat: GetKeyRepeat,
+    pub set_key_repeat: SetKeyRepeat,
+    pub get_secure_input: GetSecureInput,
+    pub set_secure_input: SetSecureInput,
+    pub set_verbose_capture: SetVerboseCapture,
+}
+
+/// Driver-wide runtime settings.
+#[derive(Debug)]
+pub struct HidConfig {
+    pointer_scale: AtomicU32,
+    debounce_time: AtomicU32,
+    key_repeat_delay: AtomicU32,
+    key_repeat_interval: AtomicU32,
+    secure_input: AtomicBool,
+    verbose_controller: AtomicPtr<c_void>,
+    verbose_reports: AtomicU32,
+}
+
+impl Default for HidConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HidConfig {
+    /// Instantiates a new configuration with default settings.
+    pub const fn new() -> Self {
+        Self {
+            pointer_scale: AtomicU32::new(DEFAULT_POINTER_SCALE),
+            debounce_time: AtomicU32::new(0),
+            key_repeat_delay: AtomicU32::new(0),
+            key_repeat_interval: AtomicU32::new(DEFAULT_KEY_REPEAT_INTERVAL),
+            secure_input: AtomicBool::new(false),
+            verbose_controller: AtomicPtr::new(ptr::null_mut()),
+            verbose_reports: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns the scale (in percent) applied to relative pointer movement.
+    pub fn pointer_scale(&self) -> u32 {
+        self.pointer_scale.load(Ordering::Relaxed)
+    }
+
+    /// Sets the scale (in percent) applied to relative pointer movement.
+    pub fn set_pointer_scale(&self, scale: u32) -> Result<(), efi::Status> {
+        if !(MIN_POINTER_SCALE..=MAX_POINTER_SCALE).contains(&scale) {
+            Err(efi::Status::INVALID_PARAMETER)?;
+        }
+        self.pointer_scale.store(scale, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the time (in milliseconds) a key must stay released before the release is processed, or zero if
+    /// debouncing is disabled (the default).
+    pub fn debounce_time(&self) -> u32 {
+        self.debounce_time.load(Ordering::Relaxed)
+    }
+
+    /// Sets the key debounce time, in milliseconds. A key that is pressed again within this time of being released
+    /// (e.g. because its switch bounces) is treated as if it had been held.
+    pub fn set_debounce_time(&self, debounce_time: u32) -> Result<(), efi::Status> {
+        if debounce_time > MAX_DEBOUNCE_TIME {
+            Err(efi::Status::INVALID_PARAMETER)?;
+        }
+        self.debounce_time.store(debounce_time, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the delay before a held key starts to repeat, in milliseconds, or zero if key repeat is disabled (the
+    /// default).
+    pub fn key_repeat_delay(&self) -> u32 {
+        self.key_repeat_delay.load(Ordering::Relaxed)
+    }
+
+    /// Returns the interval between repeats of a held key, in milliseconds.
+    pub fn key_repeat_interval(&self) -> u32 {
+        self.key_repeat_interval.load(Ordering::Relaxed)
+    }
+
+    /// Sets the delay before a held key starts to repeat (zero disables key repeat) and the interval between repeats,
+    /// in milliseconds.
+    pub fn set_key_repeat(&self, delay: u32, interval: u32) -> Result<(), efi::Status> {
+        if delay > MAX_KEY_REPEAT_DELAY || !(MIN_KEY_REPEAT_INTERVAL..=MAX_KEY_REPEAT_INTERVAL).contains(&interval) {
+            Err(efi::Status::INVALID_PARAMETER)?;
+        }
+        self.key_repeat_interval.store(interval, Ordering::Relaxed);
+        self.key_repeat_delay.store(delay, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns true if secure input is enabled, in which case the contents of input reports must not be logged.
+    pub fn secure_input(&self) -> bool {
+        self.secure_input.load(Ordering::SeqCst)
+    }
+
+    /// Enables or disables secure input (e.g. while a password is entered).
+    pub fn set_secure_input(&self, enabled: bool) {
+        self.secure_input.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Captures the next `report_count` reports from `controller` to the debug log, to help debug a single device
+    /// without enabling verbose logging for all of them. A `report_count` of zero stops any capture in progress. Only
+    /// one controller can be captured at a time; a new capture replaces any capture in progress.
+    pub fn set_verbose_capture(&self, controller: efi::Handle, report_count: u32) -> Result<(), efi::Status> {
+        if report_count > MAX_VERBOSE_REPORTS || (controller.is_null() && report_count != 0) {
+            Err(efi::Status::INVALID_PARAMETER)?;
+        }
+        // stop the capture in progress before switching controllers, so that its remaining count is not applied to the
+        // new controller.
+        self.verbose_reports.store(0, Ordering::SeqCst);
+        self.verbose_controller.store(controller, Ordering::SeqCst);
+        self.verbose_reports.store(report_count, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Returns true if a report from `controller` should be captured to the debug log, and counts it against the
+    /// capture in progress. The capture stops once its report count is reached. Nothing is captured (or counted) while
+    /// secure input is enabled.
+    pub fn take_verbose_report(&self, controller: efi::Handle) -> bool {
+        if self.secure_input() || self.verbose_controller.load(Ordering::SeqCst) != controller {
+            return false;
+        }
+        match self.verbose_reports.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| count.checked_sub(1)) {
+            Ok(1) => {
+                // that was the last report for this capture.
+                let _ = self.verbose_controller.compare_exchange(
+                    controller,
+                    ptr::null_mut(),
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                );
+                true
+            }
+            Ok(_) => true,
+            Err(_) => false,
+        }
+    }
+
+    /// Installs the HID Driver Configuration protocol for this configuration on the given handle. The protocol remains
+    /// installed for the life of the driver.
+    pub fn install(
+        &'static self,
+        boot_services: &'static dyn UefiBootServices,
+        handle: efi::Handle,
+    ) -> Result<(), efi::Status> {
+        let config_ctx = ConfigContext {
+            protocol: Protocol {
+                revision: PROTOCOL_REVISION,
+                get_pointer_scale: ConfigContext::get_pointer_scale,
+                set_pointer_scale: ConfigContext::set_pointer_scale,
+                get_debounce_time: ConfigContext::get_debounce_time,
+                set_debounce_time: ConfigContext::set_debounce_time,
+                get_key_repeat: ConfigContext::get_key_repeat,
+                set_key_repeat: ConfigContext::set_key_repeat,
+                get_secure_input: ConfigContext::get_secure_input,
+                set_secure_input: ConfigContext::set_secure_input,
+                set_verbose_capture: ConfigContext::set_verbose_capture,
+            },
+            config: self,
+        };
+        let config_ptr = Box::into_raw(Box::new(config_ctx));
+
+        let mut handle = handle;
+        let status = boot_services.install_protocol_interface(
+            ptr::addr_of_mut!(handle),
+            &PROTOCOL_GUID as *const efi::Guid as *mut efi::Guid,
+            efi::NATIVE_INTERFACE,
+            config_ptr as *mut c_void,
+        );
+        if status.is_error() {
+            drop(unsafe { Box::from_raw(config_ptr) });
+            return Err(status);
+        }
+        Ok(())
+    }
+}
+
+// FFI context
+// The protocol element needs to be the first element in the structure so that the full structure can be recovered by
+// simple casting from the protocol pointer passed to the FFI interfaces. The settings are atomic, so no TPL is required
+// to access them.
+#[repr(C)]
+struct ConfigContext {
+    protocol: Protocol,
+    config: &'static HidConfig,
+}
+
+impl ConfigContext {
+    // returns the pointer scale in the `scale` buffer provided by the caller.
+    extern "efiapi" fn get_pointer_scale(this: *mut Protocol, scale: *mut u32) -> efi::Status {
+        if this.is_null() || scale.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        let config_ctx = unsafe { &*(this as *mut ConfigContext) };
+        unsafe { scale.write(config_ctx.config.pointer_scale()) };
+        efi::Status::SUCCESS
+    }
+
+    // sets the pointer scale.
+    extern "efiapi" fn set_pointer_scale(this: *mut Protocol, scale: u32) -> efi::Status {
+        if this.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        let config_ctx = unsafe { &*(this as *mut ConfigContext) };
+        match config_ctx.config.set_pointer_scale(scale) {
+            Ok(()) => efi::Status::SUCCESS,
+            Err(status) => status,
+        }
+    }
+
+    // returns the key debounce time in the `debounce_time` buffer provided by the caller.
+    extern "efiapi" fn get_debounce_time(this: *mut Protocol, debounce_time: *mut u32) -> efi::Status {
+        if this.is_null() || debounce_time.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        let config_ctx = unsafe { &*(this as *mut ConfigContext) };
+        unsafe { debounce_time.write(config_ctx.config.debounce_time()) };
+        efi::Status::SUCCESS
+    }
+
+    // sets the key debounce time.
+    extern "efiapi" fn set_debounce_time(this: *mut Protocol, debounce_time: u32) -> efi::Status {
+        if this.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        let config_ctx = unsafe { &*(this as *mut ConfigContext) };
+        match config_ctx.config.set_debounce_time(debounce_time) {
+            Ok(()) => efi::Status::SUCCESS,
+            Err(status) => status,
+        }
+    }
+
+    // returns the key repeat delay and interval in the buffers provided by the caller.
+    extern "efiapi" fn get_key_repeat(this: *mut Protocol, delay: *mut u32, interval: *mut u32) -> efi::Status {
+        if this.is_null() || delay.is_null() || interval.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        let config_ctx = unsafe { &*(this as *mut ConfigContext) };
+        unsafe {
+            delay.write(config_ctx.config.key_repeat_delay());
+            interval.write(config_ctx.config.key_repeat_interval());
+        }
+        efi::Status::SUCCESS
+    }
+
+    // sets the key repeat delay and interval.
+    extern "efiapi" fn set_key_repeat(this: *mut Protocol, delay: u32, interval: u32) -> efi::Status {
+        if this.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        let config_ctx = unsafe { &*(this as *mut ConfigContext) };
+        match config_ctx.config.set_key_repeat(delay, interval) {
+            Ok(()) => efi::Status::SUCCESS,
+            Err(status) => status,
+        }
+    }
+
+    // returns whether secure input is enabled in the `enabled` buffer provided by the caller.
+    extern "efiapi" fn get_secure_input(this: *mut Protocol, enabled: *mut efi::Boolean) -> efi::Status {
+        if this.is_null() || enabled.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        let config_ctx = unsafe { &*(this as *mut ConfigContext) };
+        unsafe { enabled.write(config_ctx.config.secure_input().into()) };
+        efi::Status::SUCCESS
+    }
+
+    // enables or disables secure input.
+    extern "efiapi" fn set_secure_input(this: *mut Protocol, enabled: efi::Boolean) -> efi::Status {
+        if this.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        let config_ctx = unsafe { &*(this as *mut ConfigContext) };
+        config_ctx.config.set_secure_input(enabled.into());
+        efi::Status::SUCCESS
+    }
+
+    // starts (or stops) verbose capture of reports from a controller.
+    extern "efiapi" fn set_verbose_capture(
+        this: *mut Protocol,
+        controller: efi::Handle,
+        report_count: u32,
+    ) -> efi::Status {
+        if this.is_null() {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        let config_ctx = unsafe { &*(this as *mut ConfigContext) };
+        match config_ctx.config.set_verbose_capture(controller, report_count) {
+            Ok(()) => efi::Status::SUCCESS,
+            Err(status) => status,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{ffi::c_void, ptr};
+
+    use r_efi::efi;
+
+    use super::{
+        HidConfig, Protocol, DEFAULT_KEY_REPEAT_INTERVAL, DEFAULT_POINTER_SCALE, MAX_DEBOUNCE_TIME,
+        MAX_KEY_REPEAT_DELAY, MAX_VERBOSE_REPORTS, MIN_KEY_REPEAT_INTERVAL, PROTOCOL_GUID, PROTOCOL_REVISION,
+    };
+    use crate::boot_services::MockUefiBootServices;
+
+    // In this module, the usage model for boot_services is global static, and so &'static dyn UefiBootServices is used
+    // throughout the API. For testing, each test will have a different set of expectations on the UefiBootServices mock
+    // object, and the mock object itself expects to be "mut", which makes it hard to handle as a single global static.
+    // Instead, raw pointers are used to simulate a MockUefiBootServices instance with 'static lifetime.
+    // This object needs to outlive anything that uses it - once created, it will live until the end of the program.
+    fn create_fake_static_boot_service() -> &'static mut MockUefiBootServices {
+        unsafe { Box::into_raw(Box::new(MockUefiBootServices::new())).as_mut().unwrap() }
+    }
+
+    #[test]
+    fn install_should_install_protocol_that_updates_config() {
+        let boot_services = create_fake_static_boot_service();
+        let config: &'static HidConfig = Box::leak(Box::new(HidConfig::new()));
+
+        static mut CONFIG_INTERFACE: *mut c_void = ptr::null_mut();
+        boot_services.expect_install_protocol_interface().returning(|_, guid, _, interface| {
+            assert_eq!(unsafe { *guid }, PROTOCOL_GUID);
+            unsafe { CONFIG_INTERFACE = interface };
+            efi::Status::SUCCESS
+        });
+
+        config.install(boot_services, 0x1 as efi::Handle).unwrap();
+
+        let protocol = unsafe { CONFIG_INTERFACE } as *mut Protocol;
+        assert_eq!(unsafe { (*protocol).revision }, PROTOCOL_REVISION);
+
+        let mut scale = 0;
+        assert_eq!(unsafe { ((*protocol).get_pointer_scale)(protocol, &mut scale) }, efi::Status::SUCCESS);
+        assert_eq!(scale, DEFAULT_POINTER_SCALE);
+
+        assert_eq!(unsafe { ((*protocol).set_pointer_scale)(protocol, 250) }, efi::Status::SUCCESS);
+        assert_eq!(config.pointer_scale(), 250);
+        assert_eq!(unsafe { ((*protocol).get_pointer_scale)(protocol, &mut scale) }, efi::Status::SUCCESS);
+        assert_eq!(scale, 250);
+
+        // out of range scales are rejected and leave the setting unchanged.
+        assert_eq!(unsafe { ((*protocol).set_pointer_scale)(protocol, 0) }, efi::Status::INVALID_PARAMETER);
+        assert_eq!(unsafe { ((*protocol).set_pointer_scale)(protocol, 5000) }, efi::Status::INVALID_PARAMETER);
+        assert_eq!(config.pointer_scale(), 250);
+
+        assert_eq!(
+            unsafe { ((*protocol).get_pointer_scale)(protocol, ptr::null_mut()) },
+            efi::Status::INVALID_PARAMETER
+        );
+    }
+
+    #[test]
+    fn protocol_should_update_keyboard_settings() {
+        let boot_services = create_fake_static_boot_service();
+        let config: &'static HidConfig = Box::leak(Box::new(HidConfig::new()));
+
+        static mut CONFIG_INTERFACE: *mut c_void = ptr::null_mut();
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, interface| {
+            unsafe { CONFIG_INTERFACE = interface };
+            efi::Status::SUCCESS
+        });
+        config.install(boot_services, 0x1 as efi::Handle).unwrap();
+        let protocol = unsafe { CONFIG_INTERFACE } as *mut Protocol;
+
+        // debounce, key repeat and secure input are all off by default.
+        let mut debounce_time = u32::MAX;
+        assert_eq!(unsafe { ((*protocol).get_debounce_time)(protocol, &mut debounce_time) }, efi::Status::SUCCESS);
+        assert_eq!(debounce_time, 0);
+        let (mut delay, mut interval) = (u32::MAX, u32::MAX);
+        assert_eq!(unsafe { ((*protocol).get_key_repeat)(protocol, &mut delay, &mut interval) }, efi::Status::SUCCESS);
+        assert_eq!((delay, interval), (0, DEFAULT_KEY_REPEAT_INTERVAL));
+        let mut secure_input = efi::Boolean::TRUE;
+        assert_eq!(unsafe { ((*protocol).get_secure_input)(protocol, &mut secure_input) }, efi::Status::SUCCESS);
+        assert_eq!(secure_input, efi::Boolean::FALSE);
+
+        assert_eq!(unsafe { ((*protocol).set_debounce_time)(protocol, 20) }, efi::Status::SUCCESS);
+        assert_eq!(config.debounce_time(), 20);
+        assert_eq!(unsafe { ((*protocol).set_key_repeat)(protocol, 500, 30) }, efi::Status::SUCCESS);
+        assert_eq!((config.key_repeat_delay(), config.key_repeat_interval()), (500, 30));
+        assert_eq!(unsafe { ((*protocol).set_secure_input)(protocol, efi::Boolean::TRUE) }, efi::Status::SUCCESS);
+        assert!(config.secure_input());
+        assert_eq!(unsafe { ((*protocol).get_secure_input)(protocol, &mut secure_input) }, efi::Status::SUCCESS);
+        assert_eq!(secure_input, efi::Boolean::TRUE);
+
+        // out of range settings are rejected and leave the settings unchanged.
+        assert_eq!(
+            unsafe { ((*protocol).set_debounce_time)(protocol, MAX_DEBOUNCE_TIME + 1) },
+            efi::Status::INVALID_PARAMETER
+        );
+        assert_eq!(
+            unsafe { ((*protocol).set_key_repeat)(protocol, MAX_KEY_REPEAT_DELAY + 1, 30) },
+            efi::Status::INVALID_PARAMETER
+        );
+        assert_eq!(
+            unsafe { ((*protocol).set_key_repeat)(protocol, 500, MIN_KEY_REPEAT_INTERVAL - 1) },
+            efi::Status::INVALID_PARAMETER
+        );
+        assert_eq!(config.debounce_time(), 20);
+        assert_eq!((config.key_repeat_delay(), config.key_repeat_interval()), (500, 30));
+
+        assert_eq!(
+            unsafe { ((*protocol).get_key_repeat)(protocol, ptr::null_mut(), &mut interval) },
+            efi::Status::INVALID_PARAMETER
+        );
+    }
+
+    #[test]
+    fn secure_input_should_suppress_verbose_capture() {
+        let config = HidConfig::new();
+        let controller = 0x2 as efi::Handle;
+
+        config.set_verbose_capture(controller, 2).unwrap();
+        config.set_secure_input(true);
+        assert!(!config.take_verbose_report(controller));
+
+        // reports seen during secure input are not counted against the capture.
+        config.set_secure_input(false);
+        assert!(config.take_verbose_report(controller));
+        assert!(config.take_verbose_report(controller));
+        assert!(!config.take_verbose_report(controller));
+    }
+
+    #[test]
+    fn verbose_capture_should_stop_after_requested_report_count() {
+        let boot_services = create_fake_static_boot_service();
+        let config: &'static HidConfig = Box::leak(Box::new(HidConfig::new()));
+
+        static mut CONFIG_INTERFACE: *mut c_void = ptr::null_mut();
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, interface| {
+            unsafe { CONFIG_INTERFACE = interface };
+            efi::Status::SUCCESS
+        });
+        config.install(boot_services, 0x1 as efi::Handle).unwrap();
+        let protocol = unsafe { CONFIG_INTERFACE } as *mut Protocol;
+
+        let controller = 0x2 as efi::Handle;
+        let other_controller = 0x3 as efi::Handle;
+
+        // no capture by default.
+        assert!(!config.take_verbose_report(controller));
+
+        // exactly three reports from the requested controller are captured, and none from other controllers.
+        assert_eq!(unsafe { ((*protocol).set_verbose_capture)(protocol, controller, 3) }, efi::Status::SUCCESS);
+        let captured = (0..5).filter(|_| config.take_verbose_report(controller)).count();
+        assert_eq!(captured, 3);
+        assert!(!config.take_verbose_report(other_controller));
+
+        // a new capture replaces the one in progress, and a zero count stops it.
+        assert_eq!(unsafe { ((*protocol).set_verbose_capture)(protocol, controller, 5) }, efi::Status::SUCCESS);
+        assert_eq!(unsafe { ((*protocol).set_verbose_capture)(protocol, other_controller, 1) }, efi::Status::SUCCESS);
+        assert!(!config.take_verbose_report(controller));
+        assert!(config.take_verbose_report(other_controller));
+        assert!(!config.take_verbose_report(other_controller));
+
+        assert_eq!(unsafe { ((*protocol).set_verbose_capture)(protocol, controller, 2) }, efi::Status::SUCCESS);
+        assert_eq!(unsafe { ((*protocol).set_verbose_capture)(protocol, controller, 0) }, efi::Status::SUCCESS);
+        assert!(!config.take_verbose_report(controller));
+
+        // unbounded or controller-less captures are rejected.
+        assert_eq!(
+            unsafe { ((*protocol).set_verbose_capture)(protocol, controller, MAX_VERBOSE_REPORTS + 1) },
+            efi::Status::INVALID_PARAMETER
+        );
+        assert_eq!(
+            unsafe { ((*protocol).set_verbose_capture)(protocol, ptr::null_mut(), 1) },
+            efi::Status::INVALID_PARAMETER
+        );
+    }
+
+    #[test]
+    fn install_should_return_error_if_protocol_install_fails() {
+        let boot_services = create_fake_static_boot_service();
+        let config: &'static HidConfig = Box::leak(Box::new(HidConfig::new()));
+
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, _| efi::Status::OUT_OF_RESOURCES);
+
+        assert_eq!(config.install(boot_services, 0x1 as efi::Handle), Err(efi::Status::OUT_OF_RESOURCES));
+    }
+}
@@ -37,11 +37,15 @@ pub trait DriverBinding {
         boot_services: &'static dyn UefiBootServices,
         controller: efi::Handle,
     ) -> Result<(), efi::Status>;
+    ///
+    /// `child_handles` lists the child handles to stop. If it is empty, the controller itself is to be stopped.
+    ///
     /// Reference: <https://uefi.org/specs/UEFI/2.10/11_Protocols_UEFI_Driver_Model.html#efi-driver-binding-protocol-stop>
     fn driver_binding_stop(
         &mut self,
         boot_services: &'static dyn UefiBootServices,
         controller: efi::Handle,
+        child_handles: &[efi::Handle],
     ) -> Result<(), efi::Status>;
 }
 
@@ -157,11 +161,16 @@ impl UefiDriverBinding {
     extern "efiapi" fn driver_binding_stop(
         this: *mut protocols::driver_binding::Protocol,
         controller: efi::Handle,
-        _num_children: usize,
-        _child_handle_buffer: *mut efi::Handle,
+        num_children: usize,
+        child_handle_buffer: *mut efi::Handle,
     ) -> efi::Status {
         let uefi_binding = unsafe { (this as *mut UefiDriverBinding).as_mut() }.expect("bad this pointer");
-        match uefi_binding.binding.driver_binding_stop(uefi_binding.boot_services, controller) {
+        let child_handles = match num_children {
+            0 => &[],
+            _ if child_handle_buffer.is_null() => return efi::Status::INVALID_PARAMETER,
+            _ => unsafe { core::slice::from_raw_parts(child_handle_buffer, num_children) },
+        };
+        match uefi_binding.binding.driver_binding_stop(uefi_binding.boot_services, controller, child_handles) {
             Ok(_) => efi::Status::SUCCESS,
             Err(err) => err,
         }
@@ -296,7 +305,16 @@ mod test {
         let mut binding = MockDriverBinding::new();
         binding.expect_driver_binding_supported().returning(|_, _| Ok(()));
         binding.expect_driver_binding_start().returning(|_, _| Ok(()));
-        binding.expect_driver_binding_stop().returning(|_, _| Ok(()));
+        binding
+            .expect_driver_binding_stop()
+            .times(1)
+            .withf(|_, _, children| children.is_empty())
+            .returning(|_, _, _| Ok(()));
+        binding
+            .expect_driver_binding_stop()
+            .times(1)
+            .withf(|_, _, children| children == [0x10 as efi::Handle, 0x11 as efi::Handle])
+            .returning(|_, _, _| Ok(()));
 
         let handle = 0x1234 as efi::Handle;
         let driver_binding = UefiDriverBinding::new(boot_services, Box::new(binding), handle);
@@ -318,5 +336,10 @@ mod test {
             (driver_binding_ref.uefi_binding.stop)(this_ptr, controller_handle, 0, core::ptr::null_mut()),
             efi::Status::SUCCESS
         );
+        let mut child_handles = [0x10 as efi::Handle, 0x11 as efi::Handle];
+        assert_eq!(
+            (driver_binding_ref.uefi_binding.stop)(this_ptr, controller_handle, 2, child_handles.as_mut_ptr()),
+            efi::Status::SUCCESS
+        );
     }
 }
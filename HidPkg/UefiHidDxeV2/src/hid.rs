@@ -9,6 +9,16 @@
 //! [`HidReceiverFactory`] is used to create a set of receivers for reports
 //! from the HidIo device.
 //!
+//! Composite devices that declare more than one top-level collection with the
+//! same usage (e.g. a dock presenting two keyboards) can optionally be split
+//! with [`HidFactory::set_collection_children`]: each collection then gets its
+//! own set of receivers, installed on a child handle of the controller. Each
+//! child can be stopped on its own, without disturbing the other children.
+//!
+//! Platforms that need to restrict which input devices are usable can set a
+//! [`DevicePolicy`] with [`HidFactory::set_device_policy`] to allow or deny
+//! devices by USB vendor/product ID.
+//!
 //! ## Example
 //! ```ignore
 //! //Create a receiver factory that creates Pointer and Keyboard Handlers as receivers.
@@ -41,13 +51,14 @@
 //!
 //! SPDX-License-Identifier: BSD-2-Clause-Patent
 //!
-use alloc::{boxed::Box, vec::Vec};
-use core::ffi::c_void;
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::{cell::RefCell, ffi::c_void, mem::size_of, ptr, slice::from_raw_parts};
 
+use hidparser::{ReportCollection, ReportDescriptor, ReportField};
 #[cfg(test)]
 use mockall::automock;
-use r_efi::efi;
-use rust_advanced_logger_dxe::{debugln, DEBUG_ERROR};
+use r_efi::{efi, protocols::device_path};
+use rust_advanced_logger_dxe::{debugln, DEBUG_ERROR, DEBUG_WARN};
 
 use crate::{
     boot_services::UefiBootServices,
@@ -55,6 +66,96 @@ use crate::{
     hid_io::{HidIo, HidIoFactory, HidReportReceiver},
 };
 
+// USB I/O protocol definition (subset). Mirrors EFI_USB_IO_PROTOCOL in MdePkg/Include/Protocol/UsbIo.h. Only the device
+// descriptor is used, so the preceding transfer functions are left opaque.
+const USB_IO_PROTOCOL_GUID: efi::Guid =
+    efi::Guid::from_fields(0x2b2f68d6, 0x0cd2, 0x44cf, 0x8e, 0x8b, &[0xbb, 0xa2, 0x0b, 0x1b, 0x5b, 0x75]);
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct UsbDeviceDescriptor {
+    length: u8,
+    descriptor_type: u8,
+    bcd_usb: u16,
+    device_class: u8,
+    device_sub_class: u8,
+    device_protocol: u8,
+    max_packet_size0: u8,
+    id_vendor: u16,
+    id_product: u16,
+    bcd_device: u16,
+    str_manufacturer: u8,
+    str_product: u8,
+    str_serial_number: u8,
+    num_configurations: u8,
+}
+
+type UsbGetDeviceDescriptor = extern "efiapi" fn(*mut UsbIoProtocol, *mut UsbDeviceDescriptor) -> efi::Status;
+
+#[repr(C)]
+struct UsbIoProtocol {
+    transfers: [*const c_void; 6],
+    usb_get_device_descriptor: UsbGetDeviceDescriptor,
+}
+
+/// The USB vendor and product ID of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceId {
+    pub vendor_id: u16,
+    pub product_id: u16,
+}
+
+/// Restricts which devices [`HidFactory`] will start, by USB vendor/product ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DevicePolicy {
+    /// All devices may be started (the default).
+    #[default]
+    AllowAll,
+    /// Only the listed devices may be started. Devices whose ID cannot be determined (e.g. devices that are not
+    /// attached via USB) are refused.
+    AllowList(Vec<DeviceId>),
+    /// All devices except the listed ones may be started.
+    DenyList(Vec<DeviceId>),
+}
+
+impl DevicePolicy {
+    // Returns true if a device with the given ID (or None if unknown) may be started.
+    fn permits(&self, device_id: Option<DeviceId>) -> bool {
+        match self {
+            DevicePolicy::AllowAll => true,
+            DevicePolicy::AllowList(list) => device_id.is_some_and(|id| list.contains(&id)),
+            DevicePolicy::DenyList(list) => !device_id.is_some_and(|id| list.contains(&id)),
+        }
+    }
+}
+
+// Returns the USB vendor and product ID of the given controller, or None if it does not support USB I/O.
+fn usb_device_id(
+    boot_services: &'static dyn UefiBootServices,
+    controller: efi::Handle,
+    agent: efi::Handle,
+) -> Option<DeviceId> {
+    let mut usb_io: *mut UsbIoProtocol = ptr::null_mut();
+    let status = boot_services.open_protocol(
+        controller,
+        &USB_IO_PROTOCOL_GUID as *const efi::Guid as *mut efi::Guid,
+        ptr::addr_of_mut!(usb_io) as *mut *mut c_void,
+        agent,
+        controller,
+        efi::OPEN_PROTOCOL_GET_PROTOCOL,
+    );
+    if status.is_error() || usb_io.is_null() {
+        return None;
+    }
+
+    let mut descriptor = UsbDeviceDescriptor::default();
+    let status = unsafe { ((*usb_io).usb_get_device_descriptor)(usb_io, ptr::addr_of_mut!(descriptor)) };
+    if status.is_error() {
+        return None;
+    }
+    Some(DeviceId { vendor_id: descriptor.id_vendor, product_id: descriptor.id_product })
+}
+
 /// This trait defines an abstraction for getting a list of receivers for HID reports.
 ///
 /// This is used to specify to a HidFactory how it should instantiate new receivers for HID reports.
@@ -69,8 +170,12 @@ pub trait HidReceiverFactory {
 // Note: a concrete structure is used here because Box<dyn HidIo> is a fat pointer that doesn't work well for FFI.
 // Wrapping it in HidInstance makes it a fixed size type: *mut HidInstance is a thin pointer that can be cast back and
 // forth to c_void.
+// Note: fields are dropped in declaration order, so the receivers uninstall their interfaces before the child handles
+// they were installed on are destroyed.
 struct HidInstance {
-    _hid_io: Box<dyn HidIo>,
+    hid_io: Box<dyn HidIo>,
+    receivers: Rc<RefCell<HidReceivers>>,
+    children: Vec<HidChild>,
 }
 
 impl HidInstance {
@@ -80,14 +185,258 @@ impl HidInstance {
         efi::Guid::from_fields(0xfb719b29, 0xfda7, 0x4359, 0xac, 0x68, &[0x0d, 0x46, 0xc3, 0x1a, 0x7a, 0x7e]);
 
     //create a new hid instance from
-    fn new(hid_io: Box<dyn HidIo>) -> Self {
-        HidInstance { _hid_io: hid_io }
+    fn new(hid_io: Box<dyn HidIo>, receivers: Rc<RefCell<HidReceivers>>, children: Vec<HidChild>) -> Self {
+        HidInstance { hid_io, receivers, children }
+    }
+
+    // Stops the given child handles: the receivers installed on them are removed (at TPL_NOTIFY, so that no report is
+    // delivered while they are removed) and dropped, and then the children are destroyed. The rest of the instance
+    // keeps running. Returns DEVICE_ERROR if any of the handles is not a child of this instance.
+    fn stop_children(
+        &mut self,
+        boot_services: &'static dyn UefiBootServices,
+        child_handles: &[efi::Handle],
+    ) -> Result<(), efi::Status> {
+        let old_tpl = boot_services.raise_tpl(efi::TPL_NOTIFY);
+        let stopped = self.receivers.borrow_mut().remove(child_handles);
+        boot_services.restore_tpl(old_tpl);
+
+        drop(stopped);
+
+        let child_count = self.children.len();
+        self.children.retain(|child| !child_handles.contains(&child.handle));
+        if child_count - self.children.len() != child_handles.len() {
+            debugln!(DEBUG_ERROR, "hid::driver_binding_stop: unknown child in {:?}", child_handles);
+            return Err(efi::Status::DEVICE_ERROR);
+        }
+        Ok(())
+    }
+}
+
+// A child handle created to host the receivers for one top-level collection of a composite device. The child carries a
+// device path made of the controller device path followed by a controller node numbered by collection, and opens the
+// controller's HidIo protocol BY_CHILD_CONTROLLER so that the firmware tracks it as a child of the controller.
+struct HidChild {
+    boot_services: &'static dyn UefiBootServices,
+    controller: efi::Handle,
+    agent: efi::Handle,
+    handle: efi::Handle,
+    device_path: Box<[u8]>,
+}
+
+impl HidChild {
+    // Creates a new child handle for the given controller.
+    fn new(
+        boot_services: &'static dyn UefiBootServices,
+        controller: efi::Handle,
+        agent: efi::Handle,
+        controller_number: u32,
+    ) -> Result<Self, efi::Status> {
+        let mut parent_device_path: *mut device_path::Protocol = ptr::null_mut();
+        let status = boot_services.open_protocol(
+            controller,
+            &device_path::PROTOCOL_GUID as *const efi::Guid as *mut efi::Guid,
+            ptr::addr_of_mut!(parent_device_path) as *mut *mut c_void,
+            agent,
+            controller,
+            efi::OPEN_PROTOCOL_GET_PROTOCOL,
+        );
+        if status.is_error() {
+            return Err(status);
+        }
+
+        let mut device_path = child_device_path(parent_device_path, controller_number)?.into_boxed_slice();
+
+        let mut handle: efi::Handle = ptr::null_mut();
+        let status = boot_services.install_protocol_interface(
+            ptr::addr_of_mut!(handle),
+            &device_path::PROTOCOL_GUID as *const efi::Guid as *mut efi::Guid,
+            efi::NATIVE_INTERFACE,
+            device_path.as_mut_ptr() as *mut c_void,
+        );
+        if status.is_error() {
+            return Err(status);
+        }
+
+        let mut hid_io: *mut c_void = ptr::null_mut();
+        let status = boot_services.open_protocol(
+            controller,
+            &hid_io::protocol::GUID as *const efi::Guid as *mut efi::Guid,
+            ptr::addr_of_mut!(hid_io),
+            agent,
+            handle,
+            efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER,
+        );
+        if status.is_error() {
+            let uninstall_status = boot_services.uninstall_protocol_interface(
+                handle,
+                &device_path::PROTOCOL_GUID as *const efi::Guid as *mut efi::Guid,
+                device_path.as_mut_ptr() as *mut c_void,
+            );
+            if uninstall_status.is_error() {
+                // the device path is still reachable through the handle, so it must not be freed.
+                core::mem::forget(device_path);
+            }
+            return Err(status);
+        }
+
+        Ok(HidChild { boot_services, controller, agent, handle, device_path })
+    }
+}
+
+impl Drop for HidChild {
+    // Closes the parent HidIo opened for the child and uninstalls the device path, which destroys the child handle once
+    // the receivers have uninstalled their interfaces.
+    fn drop(&mut self) {
+        let status = self.boot_services.close_protocol(
+            self.controller,
+            &hid_io::protocol::GUID as *const efi::Guid as *mut efi::Guid,
+            self.agent,
+            self.handle,
+        );
+        if status.is_error() {
+            debugln!(DEBUG_ERROR, "hid: failed to close parent HidIo for child: {:x?}", status);
+        }
+
+        let status = self.boot_services.uninstall_protocol_interface(
+            self.handle,
+            &device_path::PROTOCOL_GUID as *const efi::Guid as *mut efi::Guid,
+            self.device_path.as_mut_ptr() as *mut c_void,
+        );
+        if status.is_error() {
+            debugln!(DEBUG_ERROR, "hid: failed to uninstall child device path: {:x?}", status);
+            // the device path is still reachable through the handle, so it must not be freed.
+            core::mem::forget(core::mem::take(&mut self.device_path));
+        }
+    }
+}
+
+// Builds the device path for a child: the parent device path (without its end node), followed by a controller node
+// with the given controller number and an end node.
+fn child_device_path(parent: *const device_path::Protocol, controller_number: u32) -> Result<Vec<u8>, efi::Status> {
+    let mut device_path = Vec::new();
+    let mut node = parent as *const u8;
+    loop {
+        let header = unsafe { (node as *const device_path::Protocol).read_unaligned() };
+        if header.r#type == device_path::TYPE_END && header.sub_type == device_path::End::SUBTYPE_ENTIRE {
+            break;
+        }
+        let length = u16::from_le_bytes(header.length) as usize;
+        if length < size_of::<device_path::Protocol>() {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+        device_path.extend_from_slice(unsafe { from_raw_parts(node, length) });
+        node = unsafe { node.add(length) };
+    }
+    device_path.extend_from_slice(&[device_path::TYPE_HARDWARE, device_path::Hardware::SUBTYPE_CONTROLLER, 8, 0]);
+    device_path.extend_from_slice(&controller_number.to_le_bytes());
+    device_path.extend_from_slice(&[device_path::TYPE_END, device_path::End::SUBTYPE_ENTIRE, 4, 0]);
+    Ok(device_path)
+}
+
+// Returns the top-level collection that the given field belongs to, if any.
+fn top_level_collection(field: &ReportField) -> Option<&Rc<ReportCollection>> {
+    match field {
+        ReportField::Variable(field) => field.member_of.first(),
+        ReportField::Array(field) => field.member_of.first(),
+        ReportField::Padding(_) => None,
+    }
+}
+
+// Returns a descriptor for each top-level collection if the device declares more than one top-level collection with
+// the same usage, or an empty list if receivers can share the controller. Per the HID spec, a report cannot span
+// top-level collections, so each report is assigned to a collection as a whole.
+fn collection_descriptors(descriptor: &ReportDescriptor) -> Vec<ReportDescriptor> {
+    let reports = descriptor.input_reports.iter().chain(&descriptor.output_reports).chain(&descriptor.features);
+    let mut collections: Vec<&Rc<ReportCollection>> = Vec::new();
+    for collection in reports.flat_map(|report| report.fields.iter().filter_map(top_level_collection)) {
+        if !collections.iter().any(|x| Rc::ptr_eq(x, collection)) {
+            collections.push(collection);
+        }
+    }
+
+    let duplicate_usage =
+        collections.iter().enumerate().any(|(index, x)| collections[..index].iter().any(|y| y.usage == x.usage));
+    if !duplicate_usage {
+        return Vec::new();
+    }
+
+    collections
+        .iter()
+        .map(|collection| {
+            let mut collection_descriptor = descriptor.clone();
+            for reports in [
+                &mut collection_descriptor.input_reports,
+                &mut collection_descriptor.output_reports,
+                &mut collection_descriptor.features,
+            ] {
+                reports.retain(|report| {
+                    report.fields.iter().filter_map(top_level_collection).any(|x| Rc::ptr_eq(x, collection))
+                });
+            }
+            collection_descriptor
+        })
+        .collect()
+}
+
+// HidIo wrapper that presents a single top-level collection of a composite device to receivers, so that each receiver
+// handles only the reports of the logical device it was created for.
+struct CollectionHidIo<'a> {
+    hid_io: &'a dyn HidIo,
+    descriptor: ReportDescriptor,
+}
+
+impl HidIo for CollectionHidIo<'_> {
+    fn get_report_descriptor(&self) -> Result<ReportDescriptor, efi::Status> {
+        Ok(self.descriptor.clone())
+    }
+
+    fn set_output_report(&self, id: Option<u8>, report: &[u8]) -> Result<(), efi::Status> {
+        self.hid_io.set_output_report(id, report)
+    }
+
+    fn get_input_report(&self, id: Option<u8>, report: &mut [u8]) -> Result<(), efi::Status> {
+        self.hid_io.get_input_report(id, report)
+    }
+
+    // reports are delivered to receivers through the underlying HidIo instance.
+    fn set_report_receiver(&mut self, _receiver: Box<dyn HidReportReceiver>) -> Result<(), efi::Status> {
+        Err(efi::Status::UNSUPPORTED)
+    }
+
+    fn take_report_receiver(&mut self) -> Option<Box<dyn HidReportReceiver>> {
+        None
     }
 }
 
-// Structure used to manage multiple receivers and split reports between them.
+// The receivers of a HID instance, each with the handle (the controller or a child) it was initialized on.
+#[derive(Default)]
+struct HidReceivers(Vec<(efi::Handle, Box<dyn HidReportReceiver>)>);
+
+impl HidReceivers {
+    // Removes and returns the receivers that were initialized on any of the given handles.
+    fn remove(&mut self, handles: &[efi::Handle]) -> HidReceivers {
+        let (removed, kept) =
+            core::mem::take(&mut self.0).into_iter().partition(|(handle, _)| handles.contains(handle));
+        self.0 = kept;
+        HidReceivers(removed)
+    }
+}
+
+impl Drop for HidReceivers {
+    // Tears down receivers in the reverse order of their initialization, so that each receiver uninstalls its
+    // interfaces and closes its events before the receivers initialized ahead of it.
+    fn drop(&mut self) {
+        while let Some(receiver) = self.0.pop() {
+            drop(receiver);
+        }
+    }
+}
+
+// Structure used to manage multiple receivers and split reports between them. The receivers are shared with the
+// HidInstance, so that the receivers of a single child can be stopped while the others keep running.
 struct HidSplitter {
-    receivers: Vec<Box<dyn HidReportReceiver>>,
+    receivers: Rc<RefCell<HidReceivers>>,
 }
 
 impl HidReportReceiver for HidSplitter {
@@ -99,7 +448,7 @@ impl HidReportReceiver for HidSplitter {
 
     //iterates over the receivers and passes the report to each one.
     fn receive_report(&mut self, report: &[u8], hid_io: &dyn HidIo) {
-        for receiver in &mut self.receivers {
+        for (_, receiver) in &mut self.receivers.borrow_mut().0 {
             receiver.receive_report(report, hid_io)
         }
     }
@@ -113,6 +462,10 @@ pub struct HidFactory {
     hid_io_factory: Box<dyn HidIoFactory>,
     receiver_factory: Box<dyn HidReceiverFactory>,
     agent: efi::Handle,
+    collection_children: bool,
+    max_controllers: usize,
+    active_controllers: usize,
+    device_policy: DevicePolicy,
 }
 
 impl HidFactory {
@@ -132,7 +485,35 @@ impl HidFactory {
         receiver_factory: Box<dyn HidReceiverFactory>,
         agent: efi::Handle,
     ) -> Self {
-        HidFactory { hid_io_factory, receiver_factory, agent }
+        HidFactory {
+            hid_io_factory,
+            receiver_factory,
+            agent,
+            collection_children: false,
+            max_controllers: usize::MAX,
+            active_controllers: 0,
+            device_policy: DevicePolicy::AllowAll,
+        }
+    }
+
+    /// Limits the number of controllers this factory manages at once. Attempts to start additional controllers fail
+    /// with `OUT_OF_RESOURCES`. Unlimited by default.
+    pub fn set_max_controllers(&mut self, max_controllers: usize) {
+        self.max_controllers = max_controllers;
+    }
+
+    /// Restricts which devices this factory will start, by USB vendor/product ID. Attempts to start devices that are
+    /// not permitted fail with `ACCESS_DENIED`. All devices are allowed by default.
+    pub fn set_device_policy(&mut self, device_policy: DevicePolicy) {
+        self.device_policy = device_policy;
+    }
+
+    /// Enables a separate set of receivers for each top-level collection of devices that declare more than one
+    /// top-level collection with the same usage (e.g. a dock presenting two keyboards). Each set of receivers is
+    /// installed on its own child handle of the controller, so that state such as pressed keys is not shared between
+    /// the logical devices. Disabled by default.
+    pub fn set_collection_children(&mut self, enabled: bool) {
+        self.collection_children = enabled;
     }
 }
 
@@ -160,28 +541,80 @@ impl DriverBinding for HidFactory {
     /// structure is created and associated with the controller to own these
     /// objects as long as the instance is "running"  - i.e. until
     /// [`Self::driver_binding_stop`] is invoked for the controller.
+    ///
+    /// Returns `OUT_OF_RESOURCES` if the limit set with
+    /// [`HidFactory::set_max_controllers`] has been reached, or
+    /// `ACCESS_DENIED` if the device is not permitted by the policy set with
+    /// [`HidFactory::set_device_policy`].
     fn driver_binding_start(
         &mut self,
         boot_services: &'static dyn UefiBootServices,
         controller: r_efi::efi::Handle,
     ) -> Result<(), efi::Status> {
+        if self.active_controllers >= self.max_controllers {
+            debugln!(
+                DEBUG_WARN,
+                "hid::driver_binding_start: not starting controller {:?}: limit of {:} controllers reached.",
+                controller,
+                self.max_controllers
+            );
+            return Err(efi::Status::OUT_OF_RESOURCES);
+        }
+
+        if self.device_policy != DevicePolicy::AllowAll {
+            let device_id = usb_device_id(boot_services, controller, self.agent);
+            if !self.device_policy.permits(device_id) {
+                debugln!(
+                    DEBUG_WARN,
+                    "hid::driver_binding_start: not starting controller {:?}: device {:x?} not permitted by policy.",
+                    controller,
+                    device_id
+                );
+                return Err(efi::Status::ACCESS_DENIED);
+            }
+        }
+
         let mut hid_io = self.hid_io_factory.new_hid_io(controller, true)?;
 
-        let mut hid_splitter = Box::new(HidSplitter { receivers: Vec::new() });
+        let mut children = Vec::new();
+        let mut receivers = HidReceivers::default();
 
-        for mut receiver in self.receiver_factory.new_hid_receiver_list(controller)? {
-            if receiver.initialize(controller, hid_io.as_mut()).is_ok() {
-                hid_splitter.receivers.push(receiver);
+        let collections = match self.collection_children {
+            true => collection_descriptors(&hid_io.get_report_descriptor()?),
+            false => Vec::new(),
+        };
+
+        if collections.is_empty() {
+            for mut receiver in self.receiver_factory.new_hid_receiver_list(controller)? {
+                if receiver.initialize(controller, hid_io.as_mut()).is_ok() {
+                    receivers.0.push((controller, receiver));
+                }
             }
         }
 
-        if hid_splitter.receivers.is_empty() {
+        for (controller_number, descriptor) in collections.into_iter().enumerate() {
+            let child = HidChild::new(boot_services, controller, self.agent, controller_number as u32)?;
+            let collection_hid_io = CollectionHidIo { hid_io: hid_io.as_ref(), descriptor };
+            let receiver_count = receivers.0.len();
+            for mut receiver in self.receiver_factory.new_hid_receiver_list(child.handle)? {
+                if receiver.initialize(child.handle, &collection_hid_io).is_ok() {
+                    receivers.0.push((child.handle, receiver));
+                }
+            }
+            // children without receivers are destroyed on drop.
+            if receivers.0.len() > receiver_count {
+                children.push(child);
+            }
+        }
+
+        if receivers.0.is_empty() {
             return Err(efi::Status::UNSUPPORTED);
         }
 
-        hid_io.set_report_receiver(hid_splitter)?;
+        let receivers = Rc::new(RefCell::new(receivers));
+        hid_io.set_report_receiver(Box::new(HidSplitter { receivers: receivers.clone() }))?;
 
-        let hid_instance = Box::into_raw(Box::new(HidInstance::new(hid_io)));
+        let hid_instance = Box::into_raw(Box::new(HidInstance::new(hid_io, receivers, children)));
 
         let mut handle = controller;
         let status = boot_services.install_protocol_interface(
@@ -194,6 +627,7 @@ impl DriverBinding for HidFactory {
             drop(unsafe { Box::from_raw(hid_instance) });
             return Err(status);
         }
+        self.active_controllers += 1;
         Ok(())
     }
 
@@ -201,10 +635,24 @@ impl DriverBinding for HidFactory {
     ///
     /// Stops Hid support for the given controller. The private "HidInstance"
     /// created by [`Self::driver_binding_start`] is reclaimed and dropped.
+    /// Report callbacks are stopped and the receivers taken at TPL_NOTIFY, so
+    /// that no report is delivered while the receivers are removed. The
+    /// receivers are then dropped (which uninstalls their protocols and
+    /// closes their events), and then the HidIo protocol on the controller is
+    /// closed.
+    ///
+    /// If the private context cannot be uninstalled, the instance is left in
+    /// place (and the error returned) rather than freeing memory that is
+    /// still reachable through the controller handle.
+    ///
+    /// If `child_handles` is not empty, only the receivers installed on the
+    /// given collection children are stopped and the children destroyed; the
+    /// instance itself keeps running until it is stopped with no children.
     fn driver_binding_stop(
         &mut self,
         boot_services: &'static dyn UefiBootServices,
         controller: r_efi::efi::Handle,
+        child_handles: &[efi::Handle],
     ) -> Result<(), efi::Status> {
         let mut hid_instance: *mut HidInstance = core::ptr::null_mut();
 
@@ -220,6 +668,10 @@ impl DriverBinding for HidFactory {
             return Err(status);
         }
 
+        if !child_handles.is_empty() {
+            return unsafe { &mut *hid_instance }.stop_children(boot_services, child_handles);
+        }
+
         let status = boot_services.uninstall_protocol_interface(
             controller,
             &HidInstance::PRIVATE_HID_CONTEXT_GUID as *const efi::Guid as *mut efi::Guid,
@@ -227,26 +679,164 @@ impl DriverBinding for HidFactory {
         );
         if status != efi::Status::SUCCESS {
             debugln!(DEBUG_ERROR, "hid::driver_binding_stop: unexpected failure return: {:x?}", status);
+            return Err(status);
         }
 
-        drop(unsafe { Box::from_raw(hid_instance) });
+        let mut hid_instance = unsafe { Box::from_raw(hid_instance) };
+
+        let old_tpl = boot_services.raise_tpl(efi::TPL_NOTIFY);
+        let receiver = hid_instance.hid_io.take_report_receiver();
+        boot_services.restore_tpl(old_tpl);
+
+        drop(receiver);
+        drop(hid_instance.receivers.take());
+        drop(hid_instance);
+        self.active_controllers = self.active_controllers.saturating_sub(1);
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use core::ffi::c_void;
+    use alloc::rc::Rc;
+    use core::{
+        cell::RefCell,
+        ffi::c_void,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use std::{collections::BTreeMap, sync::Mutex};
 
-    use r_efi::efi;
+    use hidparser::ReportDescriptor;
+    use r_efi::{efi, protocols};
 
     use crate::{
         boot_services::MockUefiBootServices,
         driver_binding::DriverBinding,
-        hid_io::{HidReportReceiver, MockHidIo, MockHidIoFactory, MockHidReportReceiver},
+        hid_io::{HidIo, HidReportReceiver, MockHidIo, MockHidIoFactory, MockHidReportReceiver},
+        keyboard::KeyboardHidHandler,
+        pointer::PointerHidHandler,
+    };
+
+    use super::{
+        DeviceId, DevicePolicy, HidChild, HidFactory, HidInstance, HidReceivers, HidSplitter, MockHidReceiverFactory,
+        UsbDeviceDescriptor, UsbIoProtocol, USB_IO_PROTOCOL_GUID,
     };
 
-    use super::{HidFactory, HidSplitter, MockHidReceiverFactory};
+    static KEYBOARD_AND_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x06, // USAGE (Keyboard)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x85, 0x01, //    REPORT_ID (1)
+        0x75, 0x01, //    REPORT_SIZE (1)
+        0x95, 0x08, //    REPORT_COUNT (8)
+        0x05, 0x07, //    USAGE_PAGE (Key Codes)
+        0x19, 0xE0, //    USAGE_MINIMUM (224)
+        0x29, 0xE7, //    USAGE_MAXIMUM (231)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x01, //    LOGICAL_MAXIMUM (1)
+        0x81, 0x02, //    INPUT (Data, Var, Abs) (Modifier Byte)
+        0x95, 0x06, //    REPORT_COUNT (6)
+        0x75, 0x08, //    REPORT_SIZE (8)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x65, //    LOGICAL_MAXIMUM (101)
+        0x19, 0x00, //    USAGE_MINIMUM (0)
+        0x29, 0x65, //    USAGE_MAXIMUM (101)
+        0x81, 0x00, //    INPUT (Data, Array)
+        0xc0, // END_COLLECTION
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x02, // USAGE (Mouse)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x85, 0x02, //   REPORT_ID (2)
+        0x09, 0x01, //   USAGE(Pointer)
+        0xa1, 0x00, //   COLLECTION (Physical)
+        0x05, 0x09, //     USAGE_PAGE (Button)
+        0x19, 0x01, //     USAGE_MINIMUM(1)
+        0x29, 0x08, //     USAGE_MAXIMUM(8)
+        0x15, 0x00, //     LOGICAL_MINIMUM(0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM(1)
+        0x95, 0x08, //     REPORT_COUNT(8)
+        0x75, 0x01, //     REPORT_SIZE(1)
+        0x81, 0x02, //     INPUT(Data, Variable, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x15, 0x81, //     LOGICAL_MINIMUM (-127)
+        0x25, 0x7f, //     LOGICAL_MAXIMUM (127)
+        0x75, 0x08, //     REPORT_SIZE (8)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x06, //     INPUT(Data, Variable, Relative)
+        0xc0, //   END_COLLECTION
+        0xc0, // END_COLLECTION
+    ];
+
+    static TWO_KEYBOARDS_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x06, // USAGE (Keyboard)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x85, 0x01, //    REPORT_ID (1)
+        0x75, 0x01, //    REPORT_SIZE (1)
+        0x95, 0x08, //    REPORT_COUNT (8)
+        0x05, 0x07, //    USAGE_PAGE (Key Codes)
+        0x19, 0xE0, //    USAGE_MINIMUM (224)
+        0x29, 0xE7, //    USAGE_MAXIMUM (231)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x01, //    LOGICAL_MAXIMUM (1)
+        0x81, 0x02, //    INPUT (Data, Var, Abs) (Modifier Byte)
+        0x95, 0x06, //    REPORT_COUNT (6)
+        0x75, 0x08, //    REPORT_SIZE (8)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x65, //    LOGICAL_MAXIMUM (101)
+        0x19, 0x00, //    USAGE_MINIMUM (0)
+        0x29, 0x65, //    USAGE_MAXIMUM (101)
+        0x81, 0x00, //    INPUT (Data, Array)
+        0xc0, // END_COLLECTION
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x06, // USAGE (Keyboard)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x85, 0x02, //    REPORT_ID (2)
+        0x75, 0x01, //    REPORT_SIZE (1)
+        0x95, 0x08, //    REPORT_COUNT (8)
+        0x05, 0x07, //    USAGE_PAGE (Key Codes)
+        0x19, 0xE0, //    USAGE_MINIMUM (224)
+        0x29, 0xE7, //    USAGE_MAXIMUM (231)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x01, //    LOGICAL_MAXIMUM (1)
+        0x81, 0x02, //    INPUT (Data, Var, Abs) (Modifier Byte)
+        0x95, 0x06, //    REPORT_COUNT (6)
+        0x75, 0x08, //    REPORT_SIZE (8)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x65, //    LOGICAL_MAXIMUM (101)
+        0x19, 0x00, //    USAGE_MINIMUM (0)
+        0x29, 0x65, //    USAGE_MAXIMUM (101)
+        0x81, 0x00, //    INPUT (Data, Array)
+        0xc0, // END_COLLECTION
+    ];
+
+    // HidIo implementation that owns the receiver in the same manner as the UEFI HidIo implementation, so that the
+    // receivers are torn down when the HidIo instance is dropped.
+    struct FakeHidIo {
+        receiver: Option<Box<dyn HidReportReceiver>>,
+        descriptor: &'static [u8],
+    }
+
+    impl HidIo for FakeHidIo {
+        fn get_report_descriptor(&self) -> Result<ReportDescriptor, efi::Status> {
+            Ok(hidparser::parse_report_descriptor(self.descriptor).unwrap())
+        }
+        fn set_output_report(&self, _id: Option<u8>, _report: &[u8]) -> Result<(), efi::Status> {
+            Ok(())
+        }
+        fn get_input_report(&self, _id: Option<u8>, _report: &mut [u8]) -> Result<(), efi::Status> {
+            Err(efi::Status::UNSUPPORTED)
+        }
+        fn set_report_receiver(&mut self, receiver: Box<dyn HidReportReceiver>) -> Result<(), efi::Status> {
+            self.receiver = Some(receiver);
+            Ok(())
+        }
+        fn take_report_receiver(&mut self) -> Option<Box<dyn HidReportReceiver>> {
+            self.receiver.take()
+        }
+    }
 
     // In this module, the usage model for boot_services is global static, and so &'static dyn UefiBootServices is used
     // throughout the API. For testing, each test will have a different set of expectations on the UefiBootServices mock
@@ -373,6 +963,7 @@ mod test {
         hid_io_factory.expect_new_hid_io().returning(|_, _| {
             let mut hid_io = MockHidIo::new();
             hid_io.expect_set_report_receiver().returning(|_| Ok(()));
+            hid_io.expect_take_report_receiver().returning(|| None);
             Ok(Box::new(hid_io))
         });
 
@@ -395,6 +986,8 @@ mod test {
         });
 
         boot_services.expect_uninstall_protocol_interface().returning(|_, _, _| efi::Status::SUCCESS);
+        boot_services.expect_raise_tpl().returning(|_| efi::TPL_APPLICATION);
+        boot_services.expect_restore_tpl().returning(|_| ());
 
         let mut hid_factory = HidFactory::new(hid_io_factory, receiver_factory, agent);
         let controller = 0x02 as efi::Handle;
@@ -402,7 +995,615 @@ mod test {
 
         assert_ne!(unsafe { HID_INSTANCE_PTR }, core::ptr::null_mut());
 
-        hid_factory.driver_binding_stop(boot_services, controller).unwrap();
+        hid_factory.driver_binding_stop(boot_services, controller, &[]).unwrap();
+    }
+
+    #[test]
+    fn driver_binding_start_should_reject_controllers_beyond_limit() {
+        let boot_services = create_fake_static_boot_service();
+        let agent = 0x1 as efi::Handle;
+
+        // no HidIo instance should be created for the rejected controller.
+        let mut hid_io_factory = Box::new(MockHidIoFactory::new());
+        hid_io_factory.expect_new_hid_io().times(2).returning(|_, _| {
+            let mut hid_io = MockHidIo::new();
+            hid_io.expect_set_report_receiver().returning(|_| Ok(()));
+            hid_io.expect_take_report_receiver().returning(|| None);
+            Ok(Box::new(hid_io))
+        });
+
+        let mut receiver_factory = Box::new(MockHidReceiverFactory::new());
+        receiver_factory.expect_new_hid_receiver_list().returning(|_| {
+            let mut hid_receiver = MockHidReportReceiver::new();
+            hid_receiver.expect_initialize().returning(|_, _| Ok(()));
+            Ok(vec![Box::new(hid_receiver)])
+        });
+
+        static mut HID_INSTANCE_PTR: *mut c_void = core::ptr::null_mut();
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, instance| {
+            unsafe { HID_INSTANCE_PTR = instance };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_open_protocol().returning(|_, _, interface, _, _, _| {
+            unsafe { *interface = HID_INSTANCE_PTR };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_uninstall_protocol_interface().returning(|_, _, _| efi::Status::SUCCESS);
+        boot_services.expect_raise_tpl().returning(|_| efi::TPL_APPLICATION);
+        boot_services.expect_restore_tpl().returning(|_| ());
+
+        let mut hid_factory = HidFactory::new(hid_io_factory, receiver_factory, agent);
+        hid_factory.set_max_controllers(1);
+
+        let controller = 0x02 as efi::Handle;
+        hid_factory.driver_binding_start(boot_services, controller).unwrap();
+
+        let second_controller = 0x03 as efi::Handle;
+        assert_eq!(
+            hid_factory.driver_binding_start(boot_services, second_controller),
+            Err(efi::Status::OUT_OF_RESOURCES)
+        );
+
+        // stopping a controller frees up a slot.
+        hid_factory.driver_binding_stop(boot_services, controller, &[]).unwrap();
+        hid_factory.driver_binding_start(boot_services, second_controller).unwrap();
+
+        //test note: this will leak a HidInstance.
+    }
+
+    #[test]
+    fn driver_binding_start_should_respect_device_policy() {
+        let boot_services = create_fake_static_boot_service();
+        let agent = 0x1 as efi::Handle;
+
+        extern "efiapi" fn get_listed_device_descriptor(
+            _this: *mut UsbIoProtocol,
+            descriptor: *mut UsbDeviceDescriptor,
+        ) -> efi::Status {
+            unsafe {
+                *descriptor = UsbDeviceDescriptor { id_vendor: 0x045e, id_product: 0x0001, ..Default::default() }
+            };
+            efi::Status::SUCCESS
+        }
+        extern "efiapi" fn get_unlisted_device_descriptor(
+            _this: *mut UsbIoProtocol,
+            descriptor: *mut UsbDeviceDescriptor,
+        ) -> efi::Status {
+            unsafe {
+                *descriptor = UsbDeviceDescriptor { id_vendor: 0x1234, id_product: 0x5678, ..Default::default() }
+            };
+            efi::Status::SUCCESS
+        }
+
+        // controller 2 is a listed USB device, controller 3 an unlisted USB device, and controller 4 is not USB.
+        static mut HID_INSTANCE_PTR: *mut c_void = core::ptr::null_mut();
+        boot_services.expect_open_protocol().returning(|controller, guid, interface, _, _, _| {
+            if unsafe { *guid } != USB_IO_PROTOCOL_GUID {
+                unsafe { *interface = HID_INSTANCE_PTR };
+                return efi::Status::SUCCESS;
+            }
+            let usb_get_device_descriptor: super::UsbGetDeviceDescriptor = match controller as usize {
+                0x2 => get_listed_device_descriptor,
+                0x3 => get_unlisted_device_descriptor,
+                _ => return efi::Status::UNSUPPORTED,
+            };
+            let usb_io = UsbIoProtocol { transfers: [core::ptr::null(); 6], usb_get_device_descriptor };
+            unsafe { *interface = Box::into_raw(Box::new(usb_io)) as *mut c_void };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, instance| {
+            unsafe { HID_INSTANCE_PTR = instance };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_uninstall_protocol_interface().returning(|_, _, _| efi::Status::SUCCESS);
+
+        let new_hid_factory = |hid_io_count: usize| {
+            let mut hid_io_factory = Box::new(MockHidIoFactory::new());
+            hid_io_factory.expect_new_hid_io().times(hid_io_count).returning(|_, _| {
+                let mut hid_io = MockHidIo::new();
+                hid_io.expect_set_report_receiver().returning(|_| Ok(()));
+                Ok(Box::new(hid_io))
+            });
+
+            let mut receiver_factory = Box::new(MockHidReceiverFactory::new());
+            receiver_factory.expect_new_hid_receiver_list().returning(|_| {
+                let mut hid_receiver = MockHidReportReceiver::new();
+                hid_receiver.expect_initialize().returning(|_, _| Ok(()));
+                Ok(vec![Box::new(hid_receiver)])
+            });
+            HidFactory::new(hid_io_factory, receiver_factory, agent)
+        };
+        let listed = vec![DeviceId { vendor_id: 0x045e, product_id: 0x0001 }];
+
+        // with an allowlist, only the listed device is started; no HidIo instance is created for the others.
+        let mut hid_factory = new_hid_factory(1);
+        hid_factory.set_device_policy(DevicePolicy::AllowList(listed.clone()));
+        hid_factory.driver_binding_start(boot_services, 0x2 as efi::Handle).unwrap();
+        assert_eq!(
+            hid_factory.driver_binding_start(boot_services, 0x3 as efi::Handle),
+            Err(efi::Status::ACCESS_DENIED)
+        );
+        assert_eq!(
+            hid_factory.driver_binding_start(boot_services, 0x4 as efi::Handle),
+            Err(efi::Status::ACCESS_DENIED)
+        );
+
+        // with a denylist, the listed device is refused and the others are started.
+        let mut hid_factory = new_hid_factory(2);
+        hid_factory.set_device_policy(DevicePolicy::DenyList(listed));
+        assert_eq!(
+            hid_factory.driver_binding_start(boot_services, 0x2 as efi::Handle),
+            Err(efi::Status::ACCESS_DENIED)
+        );
+        hid_factory.driver_binding_start(boot_services, 0x3 as efi::Handle).unwrap();
+        hid_factory.driver_binding_start(boot_services, 0x4 as efi::Handle).unwrap();
+
+        //test note: this will leak HidInstances and UsbIo instances.
+    }
+
+    #[test]
+    fn driver_binding_stop_should_uninstall_interfaces_and_close_events_installed_by_start() {
+        // tracks interfaces currently installed, by guid.
+        static INSTALLED_INTERFACES: Mutex<BTreeMap<[u8; 16], usize>> = Mutex::new(BTreeMap::new());
+        static INSTALL_COUNT: AtomicUsize = AtomicUsize::new(0);
+        static UNINSTALL_COUNT: AtomicUsize = AtomicUsize::new(0);
+        // tracks events currently open.
+        static NEXT_EVENT: AtomicUsize = AtomicUsize::new(0x1000);
+        static OPEN_EVENTS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+        static CREATE_EVENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+        static CLOSE_EVENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        fn create_event(event: *mut efi::Event) -> efi::Status {
+            let new_event = NEXT_EVENT.fetch_add(1, Ordering::SeqCst);
+            OPEN_EVENTS.lock().unwrap().push(new_event);
+            CREATE_EVENT_COUNT.fetch_add(1, Ordering::SeqCst);
+            unsafe { event.write(new_event as efi::Event) };
+            efi::Status::SUCCESS
+        }
+
+        let boot_services = create_fake_static_boot_service();
+        boot_services.expect_raise_tpl().returning(|_| efi::TPL_APPLICATION);
+        boot_services.expect_restore_tpl().returning(|_| ());
+        boot_services.expect_signal_event().returning(|_| efi::Status::SUCCESS);
+        boot_services.expect_locate_protocol().returning(|_, _, _| efi::Status::NOT_FOUND);
+        boot_services.expect_create_event().returning(|_, _, _, _, event| create_event(event));
+        boot_services.expect_create_event_ex().returning(|_, _, _, _, _, event| create_event(event));
+        boot_services.expect_close_event().returning(|event| {
+            let mut open_events = OPEN_EVENTS.lock().unwrap();
+            let Some(index) = open_events.iter().position(|x| *x == event as usize) else {
+                panic!("close of unknown event {:?}", event);
+            };
+            open_events.remove(index);
+            CLOSE_EVENT_COUNT.fetch_add(1, Ordering::SeqCst);
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_install_protocol_interface().returning(|_, guid, _, interface| {
+            let guid = unsafe { *(*guid).as_bytes() };
+            assert!(INSTALLED_INTERFACES.lock().unwrap().insert(guid, interface as usize).is_none());
+            INSTALL_COUNT.fetch_add(1, Ordering::SeqCst);
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_uninstall_protocol_interface().returning(|_, guid, interface| {
+            let guid = unsafe { *(*guid).as_bytes() };
+            assert_eq!(INSTALLED_INTERFACES.lock().unwrap().remove(&guid), Some(interface as usize));
+            UNINSTALL_COUNT.fetch_add(1, Ordering::SeqCst);
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_open_protocol().returning(|_, guid, interface, _, _, _| {
+            let guid = unsafe { *(*guid).as_bytes() };
+            match INSTALLED_INTERFACES.lock().unwrap().get(&guid) {
+                Some(installed) => {
+                    unsafe { *interface = *installed as *mut c_void };
+                    efi::Status::SUCCESS
+                }
+                None => efi::Status::NOT_FOUND,
+            }
+        });
+
+        // expectations are complete; the handlers only need shared access from here on.
+        let boot_services: &'static MockUefiBootServices = boot_services;
+
+        let mut hid_io_factory = Box::new(MockHidIoFactory::new());
+        hid_io_factory.expect_new_hid_io().returning(|_, _| {
+            Ok(Box::new(FakeHidIo { receiver: None, descriptor: KEYBOARD_AND_MOUSE_REPORT_DESCRIPTOR }))
+        });
+
+        let agent = 0x1 as efi::Handle;
+        let mut receiver_factory = Box::new(MockHidReceiverFactory::new());
+        receiver_factory.expect_new_hid_receiver_list().returning_st(move |_| {
+            let mut keyboard_handler = KeyboardHidHandler::new(boot_services, agent);
+            keyboard_handler.set_layout(Some(hii_keyboard_layout::get_default_keyboard_layout()));
+            let receivers: Vec<Box<dyn HidReportReceiver>> =
+                vec![Box::new(PointerHidHandler::new(boot_services, agent)), Box::new(keyboard_handler)];
+            Ok(receivers)
+        });
+
+        let mut hid_factory = HidFactory::new(hid_io_factory, receiver_factory, agent);
+        let controller = 0x02 as efi::Handle;
+        hid_factory.driver_binding_start(boot_services, controller).unwrap();
+
+        // absolute pointer, simple text in, simple text in ex and the private context should be installed.
+        assert_eq!(INSTALL_COUNT.load(Ordering::SeqCst), 4);
+        assert_eq!(UNINSTALL_COUNT.load(Ordering::SeqCst), 0);
+        assert!(CREATE_EVENT_COUNT.load(Ordering::SeqCst) > 0);
+        assert_eq!(CLOSE_EVENT_COUNT.load(Ordering::SeqCst), 0);
+
+        hid_factory.driver_binding_stop(boot_services, controller, &[]).unwrap();
+
+        // every install should be matched by an uninstall, and every created event should be closed.
+        assert!(INSTALLED_INTERFACES.lock().unwrap().is_empty());
+        assert_eq!(UNINSTALL_COUNT.load(Ordering::SeqCst), INSTALL_COUNT.load(Ordering::SeqCst));
+        assert!(OPEN_EVENTS.lock().unwrap().is_empty());
+        assert_eq!(CLOSE_EVENT_COUNT.load(Ordering::SeqCst), CREATE_EVENT_COUNT.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn driver_binding_stop_should_not_free_instance_if_uninstall_fails() {
+        let boot_services = create_fake_static_boot_service();
+        let agent = 0x1 as efi::Handle;
+
+        let mut hid_io_factory = Box::new(MockHidIoFactory::new());
+        hid_io_factory.expect_new_hid_io().returning(|_, _| {
+            let mut hid_io = MockHidIo::new();
+            hid_io.expect_set_report_receiver().returning(|_| Ok(()));
+            Ok(Box::new(hid_io))
+        });
+
+        let mut receiver_factory = Box::new(MockHidReceiverFactory::new());
+        receiver_factory.expect_new_hid_receiver_list().returning(|_| {
+            let mut hid_receiver = MockHidReportReceiver::new();
+            hid_receiver.expect_initialize().returning(|_, _| Ok(()));
+            Ok(vec![Box::new(hid_receiver)])
+        });
+
+        static mut HID_INSTANCE_PTR: *mut c_void = core::ptr::null_mut();
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, instance| {
+            unsafe { HID_INSTANCE_PTR = instance };
+            efi::Status::SUCCESS
+        });
+
+        boot_services.expect_open_protocol().returning(|_, _, interface, _, _, _| {
+            unsafe { *interface = HID_INSTANCE_PTR };
+            efi::Status::SUCCESS
+        });
+
+        boot_services.expect_uninstall_protocol_interface().returning(|_, _, _| efi::Status::ACCESS_DENIED);
+
+        let mut hid_factory = HidFactory::new(hid_io_factory, receiver_factory, agent);
+        let controller = 0x02 as efi::Handle;
+        hid_factory.driver_binding_start(boot_services, controller).unwrap();
+
+        assert_eq!(hid_factory.driver_binding_stop(boot_services, controller, &[]), Err(efi::Status::ACCESS_DENIED));
+
+        //test note: this will leak a HidInstance.
+    }
+
+    #[test]
+    fn driver_binding_stop_should_take_receiver_at_tpl_notify() {
+        static CURRENT_TPL: AtomicUsize = AtomicUsize::new(efi::TPL_APPLICATION);
+        static RECEIVER_TAKEN: AtomicUsize = AtomicUsize::new(0);
+
+        let boot_services = create_fake_static_boot_service();
+        let agent = 0x1 as efi::Handle;
+
+        let mut hid_io_factory = Box::new(MockHidIoFactory::new());
+        hid_io_factory.expect_new_hid_io().returning(|_, _| {
+            let mut hid_io = MockHidIo::new();
+            hid_io.expect_set_report_receiver().returning(|_| Ok(()));
+            hid_io.expect_take_report_receiver().times(1).returning(|| {
+                assert_eq!(CURRENT_TPL.load(Ordering::SeqCst), efi::TPL_NOTIFY);
+                RECEIVER_TAKEN.fetch_add(1, Ordering::SeqCst);
+                None
+            });
+            Ok(Box::new(hid_io))
+        });
+
+        let mut receiver_factory = Box::new(MockHidReceiverFactory::new());
+        receiver_factory.expect_new_hid_receiver_list().returning(|_| {
+            let mut hid_receiver = MockHidReportReceiver::new();
+            hid_receiver.expect_initialize().returning(|_, _| Ok(()));
+            Ok(vec![Box::new(hid_receiver)])
+        });
+
+        static mut HID_INSTANCE_PTR: *mut c_void = core::ptr::null_mut();
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, instance| {
+            unsafe { HID_INSTANCE_PTR = instance };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_open_protocol().returning(|_, _, interface, _, _, _| {
+            unsafe { *interface = HID_INSTANCE_PTR };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_uninstall_protocol_interface().returning(|_, _, _| efi::Status::SUCCESS);
+        boot_services.expect_raise_tpl().returning(|new_tpl| CURRENT_TPL.swap(new_tpl, Ordering::SeqCst));
+        boot_services.expect_restore_tpl().returning(|old_tpl| CURRENT_TPL.store(old_tpl, Ordering::SeqCst));
+
+        let mut hid_factory = HidFactory::new(hid_io_factory, receiver_factory, agent);
+        let controller = 0x02 as efi::Handle;
+        hid_factory.driver_binding_start(boot_services, controller).unwrap();
+
+        assert_eq!(hid_factory.driver_binding_stop(boot_services, controller, &[]), Ok(()));
+        assert_eq!(RECEIVER_TAKEN.load(Ordering::SeqCst), 1);
+        assert_eq!(CURRENT_TPL.load(Ordering::SeqCst), efi::TPL_APPLICATION);
+    }
+
+    // Handle database for the composite device tests, which create child handles for each collection. Each test uses
+    // its own instance, so that the tests can run in parallel.
+    struct FakeHandleDatabase {
+        // interfaces currently installed, by handle and guid.
+        installed_interfaces: Mutex<BTreeMap<(usize, [u8; 16]), usize>>,
+        // child handles that have the parent HidIo open BY_CHILD_CONTROLLER.
+        child_opens: Mutex<Vec<usize>>,
+        next_handle: AtomicUsize,
+        next_event: AtomicUsize,
+    }
+
+    // Creates a boot services instance backed by a new handle database, with a device path installed on the controller.
+    fn create_fake_composite_boot_service(
+        controller: efi::Handle,
+    ) -> (&'static MockUefiBootServices, &'static FakeHandleDatabase) {
+        // controller node followed by an end node.
+        static PARENT_DEVICE_PATH: [u8; 12] = [0x01, 0x05, 0x08, 0x00, 0x07, 0x00, 0x00, 0x00, 0x7f, 0xff, 0x04, 0x00];
+
+        let database: &'static FakeHandleDatabase = Box::leak(Box::new(FakeHandleDatabase {
+            installed_interfaces: Mutex::new(BTreeMap::new()),
+            child_opens: Mutex::new(Vec::new()),
+            next_handle: AtomicUsize::new(0x100),
+            next_event: AtomicUsize::new(0x1000),
+        }));
+        database.installed_interfaces.lock().unwrap().insert(
+            (controller as usize, *protocols::device_path::PROTOCOL_GUID.as_bytes()),
+            PARENT_DEVICE_PATH.as_ptr() as usize,
+        );
+
+        let parent = controller as usize;
+        let boot_services = create_fake_static_boot_service();
+        boot_services.expect_raise_tpl().returning(|_| efi::TPL_APPLICATION);
+        boot_services.expect_restore_tpl().returning(|_| ());
+        boot_services.expect_signal_event().returning(|_| efi::Status::SUCCESS);
+        boot_services.expect_locate_protocol().returning(|_, _, _| efi::Status::NOT_FOUND);
+        boot_services.expect_create_event().returning(move |_, _, _, _, event| {
+            unsafe { event.write(database.next_event.fetch_add(1, Ordering::SeqCst) as efi::Event) };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_create_event_ex().returning(move |_, _, _, _, _, event| {
+            unsafe { event.write(database.next_event.fetch_add(1, Ordering::SeqCst) as efi::Event) };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_close_event().returning(|_| efi::Status::SUCCESS);
+        boot_services.expect_install_protocol_interface().returning(move |handle, guid, _, interface| {
+            let guid = unsafe { *(*guid).as_bytes() };
+            let handle = unsafe {
+                if (*handle).is_null() {
+                    handle.write(database.next_handle.fetch_add(1, Ordering::SeqCst) as efi::Handle);
+                }
+                *handle as usize
+            };
+            let mut installed_interfaces = database.installed_interfaces.lock().unwrap();
+            assert!(installed_interfaces.insert((handle, guid), interface as usize).is_none());
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_uninstall_protocol_interface().returning(move |handle, guid, interface| {
+            let guid = unsafe { *(*guid).as_bytes() };
+            let mut installed_interfaces = database.installed_interfaces.lock().unwrap();
+            assert_eq!(installed_interfaces.remove(&(handle as usize, guid)), Some(interface as usize));
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_close_protocol().returning(move |handle, guid, _, controller_handle| {
+            assert_eq!(handle as usize, parent);
+            assert_eq!(unsafe { *guid }, hid_io::protocol::GUID);
+            let mut child_opens = database.child_opens.lock().unwrap();
+            let index = child_opens.iter().position(|child| *child == controller_handle as usize).unwrap();
+            child_opens.remove(index);
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_open_protocol().returning(
+            move |handle, guid, interface, _, controller_handle, attributes| {
+                if attributes == efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER {
+                    assert_eq!(handle as usize, parent);
+                    assert_eq!(unsafe { *guid }, hid_io::protocol::GUID);
+                    database.child_opens.lock().unwrap().push(controller_handle as usize);
+                    return efi::Status::SUCCESS;
+                }
+                let guid = unsafe { *(*guid).as_bytes() };
+                match database.installed_interfaces.lock().unwrap().get(&(handle as usize, guid)) {
+                    Some(installed) => {
+                        unsafe { *interface = *installed as *mut c_void };
+                        efi::Status::SUCCESS
+                    }
+                    None => efi::Status::NOT_FOUND,
+                }
+            },
+        );
+
+        (boot_services, database)
+    }
+
+    // Creates a factory that splits TWO_KEYBOARDS_REPORT_DESCRIPTOR into a keyboard handler per collection.
+    fn create_composite_hid_factory(boot_services: &'static MockUefiBootServices) -> HidFactory {
+        let mut hid_io_factory = Box::new(MockHidIoFactory::new());
+        hid_io_factory
+            .expect_new_hid_io()
+            .returning(|_, _| Ok(Box::new(FakeHidIo { receiver: None, descriptor: TWO_KEYBOARDS_REPORT_DESCRIPTOR })));
+
+        let agent = 0x1 as efi::Handle;
+        let mut receiver_factory = Box::new(MockHidReceiverFactory::new());
+        receiver_factory.expect_new_hid_receiver_list().returning_st(move |_| {
+            let mut keyboard_handler = KeyboardHidHandler::new(boot_services, agent);
+            keyboard_handler.set_layout(Some(hii_keyboard_layout::get_default_keyboard_layout()));
+            let receivers: Vec<Box<dyn HidReportReceiver>> = vec![Box::new(keyboard_handler)];
+            Ok(receivers)
+        });
+
+        let mut hid_factory = HidFactory::new(hid_io_factory, receiver_factory, agent);
+        hid_factory.set_collection_children(true);
+        hid_factory
+    }
+
+    // Returns the (handle, interface) of each simple text in ex instance installed in the database.
+    fn installed_keyboards(database: &FakeHandleDatabase) -> Vec<(usize, usize)> {
+        let text_in_ex_guid = *protocols::simple_text_input_ex::PROTOCOL_GUID.as_bytes();
+        database
+            .installed_interfaces
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((_, guid), _)| *guid == text_in_ex_guid)
+            .map(|((handle, _), interface)| (*handle, *interface))
+            .collect()
+    }
+
+    // Delivers a report to the receivers of the HID instance started on the given controller.
+    fn send_report(database: &FakeHandleDatabase, controller: efi::Handle, report: &[u8]) {
+        let key = (controller as usize, *HidInstance::PRIVATE_HID_CONTEXT_GUID.as_bytes());
+        let hid_instance = database.installed_interfaces.lock().unwrap()[&key];
+        let hid_io = unsafe { &mut (*(hid_instance as *mut HidInstance)).hid_io };
+        let mut receiver = hid_io.take_report_receiver().unwrap();
+        receiver.receive_report(report, hid_io.as_ref());
+        hid_io.set_report_receiver(receiver).unwrap();
+    }
+
+    // Reads a key from the simple text in ex instance at the given interface.
+    fn read_key(interface: usize) -> (efi::Status, u16) {
+        let protocol = interface as *mut protocols::simple_text_input_ex::Protocol;
+        let mut key_data: protocols::simple_text_input_ex::KeyData = Default::default();
+        let status = unsafe { ((*protocol).read_key_stroke_ex)(protocol, &mut key_data) };
+        (status, key_data.key.unicode_char)
+    }
+
+    #[test]
+    fn driver_binding_start_should_create_a_child_per_collection_for_composite_devices() {
+        let controller = 0x02 as efi::Handle;
+        let device_path_guid = *protocols::device_path::PROTOCOL_GUID.as_bytes();
+        let (boot_services, database) = create_fake_composite_boot_service(controller);
+
+        let mut hid_factory = create_composite_hid_factory(boot_services);
+        hid_factory.driver_binding_start(boot_services, controller).unwrap();
+
+        // each keyboard collection should have its own simple text in ex instance on a child handle with a device path.
+        let keyboards = installed_keyboards(database);
+        assert_eq!(keyboards.len(), 2);
+        assert_ne!(keyboards[0].0, keyboards[1].0);
+        for (handle, _) in &keyboards {
+            assert_ne!(*handle, controller as usize);
+            assert!(database.installed_interfaces.lock().unwrap().contains_key(&(*handle, device_path_guid)));
+            assert!(database.child_opens.lock().unwrap().contains(handle));
+        }
+
+        // a key press on the first keyboard should only be reported by the first instance.
+        send_report(database, controller, &[0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(read_key(keyboards[0].1), (efi::Status::SUCCESS, 'a' as u16));
+        assert_eq!(read_key(keyboards[1].1).0, efi::Status::NOT_READY);
+
+        hid_factory.driver_binding_stop(boot_services, controller, &[]).unwrap();
+
+        // only the parent device path should remain, and each child should have closed the parent HidIo.
+        let installed = database.installed_interfaces.lock().unwrap();
+        assert_eq!(installed.len(), 1);
+        assert!(installed.contains_key(&(controller as usize, device_path_guid)));
+        assert!(database.child_opens.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn driver_binding_stop_should_stop_children_before_the_controller() {
+        let controller = 0x02 as efi::Handle;
+        let device_path_guid = *protocols::device_path::PROTOCOL_GUID.as_bytes();
+        let (boot_services, database) = create_fake_composite_boot_service(controller);
+
+        let mut hid_factory = create_composite_hid_factory(boot_services);
+        hid_factory.driver_binding_start(boot_services, controller).unwrap();
+        let keyboards = installed_keyboards(database);
+        assert_eq!(keyboards.len(), 2);
+
+        // stopping the first child should leave the second child and the controller running.
+        let first_child = keyboards[0].0 as efi::Handle;
+        hid_factory.driver_binding_stop(boot_services, controller, &[first_child]).unwrap();
+        assert_eq!(installed_keyboards(database), &keyboards[1..]);
+        assert!(!database.installed_interfaces.lock().unwrap().contains_key(&(keyboards[0].0, device_path_guid)));
+        assert_eq!(*database.child_opens.lock().unwrap(), [keyboards[1].0]);
+
+        send_report(database, controller, &[0x02, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(read_key(keyboards[1].1), (efi::Status::SUCCESS, 'b' as u16));
+
+        // stopping a handle that is not a child of the controller should fail.
+        let stopped = hid_factory.driver_binding_stop(boot_services, controller, &[first_child]);
+        assert_eq!(stopped, Err(efi::Status::DEVICE_ERROR));
+
+        // DisconnectController stops all remaining children, and then the controller.
+        let second_child = keyboards[1].0 as efi::Handle;
+        hid_factory.driver_binding_stop(boot_services, controller, &[second_child]).unwrap();
+        assert!(installed_keyboards(database).is_empty());
+        assert!(database.child_opens.lock().unwrap().is_empty());
+
+        hid_factory.driver_binding_stop(boot_services, controller, &[]).unwrap();
+
+        // only the parent device path should remain, and the controller can be started again.
+        assert_eq!(database.installed_interfaces.lock().unwrap().len(), 1);
+        hid_factory.driver_binding_start(boot_services, controller).unwrap();
+        assert_eq!(installed_keyboards(database).len(), 2);
+    }
+
+    #[test]
+    fn hid_child_should_open_parent_hid_io_by_child_controller() {
+        // controller node followed by an end node.
+        static PARENT_DEVICE_PATH: [u8; 12] = [0x01, 0x05, 0x08, 0x00, 0x07, 0x00, 0x00, 0x00, 0x7f, 0xff, 0x04, 0x00];
+        const CHILD: usize = 0x200;
+
+        let controller = 0x02 as efi::Handle;
+        let agent = 0x1 as efi::Handle;
+
+        let boot_services = create_fake_static_boot_service();
+        let mut sequence = mockall::Sequence::new();
+        boot_services
+            .expect_open_protocol()
+            .withf(|_, _, _, _, _, attributes| *attributes == efi::OPEN_PROTOCOL_GET_PROTOCOL)
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|handle, guid, interface, _, _, _| {
+                assert_eq!(handle, 0x02 as efi::Handle);
+                assert_eq!(unsafe { *guid }, protocols::device_path::PROTOCOL_GUID);
+                unsafe { *interface = PARENT_DEVICE_PATH.as_ptr() as *mut c_void };
+                efi::Status::SUCCESS
+            });
+        boot_services.expect_install_protocol_interface().times(1).in_sequence(&mut sequence).returning(
+            |handle, _, _, _| {
+                unsafe { handle.write(CHILD as efi::Handle) };
+                efi::Status::SUCCESS
+            },
+        );
+        boot_services
+            .expect_open_protocol()
+            .withf(|_, _, _, _, _, attributes| *attributes == efi::OPEN_PROTOCOL_BY_CHILD_CONTROLLER)
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|handle, guid, _, agent, controller_handle, _| {
+                assert_eq!(handle, 0x02 as efi::Handle);
+                assert_eq!(unsafe { *guid }, hid_io::protocol::GUID);
+                assert_eq!(agent, 0x1 as efi::Handle);
+                assert_eq!(controller_handle, CHILD as efi::Handle);
+                efi::Status::SUCCESS
+            });
+
+        // the parent HidIo should be closed before the child device path is uninstalled.
+        boot_services.expect_close_protocol().times(1).in_sequence(&mut sequence).returning(
+            |handle, guid, agent, controller_handle| {
+                assert_eq!(handle, 0x02 as efi::Handle);
+                assert_eq!(unsafe { *guid }, hid_io::protocol::GUID);
+                assert_eq!(agent, 0x1 as efi::Handle);
+                assert_eq!(controller_handle, CHILD as efi::Handle);
+                efi::Status::SUCCESS
+            },
+        );
+        boot_services.expect_uninstall_protocol_interface().times(1).in_sequence(&mut sequence).returning(
+            |handle, guid, _| {
+                assert_eq!(handle, CHILD as efi::Handle);
+                assert_eq!(unsafe { *guid }, protocols::device_path::PROTOCOL_GUID);
+                efi::Status::SUCCESS
+            },
+        );
+        let child = HidChild::new(boot_services, controller, agent, 0).unwrap();
+        assert_eq!(child.handle, CHILD as efi::Handle);
+        drop(child);
     }
 
     #[test]
@@ -411,10 +1612,11 @@ mod test {
         mock_hid_receiver1.expect_receive_report().returning(|_, _| ());
         let mut mock_hid_receiver2 = MockHidReportReceiver::new();
         mock_hid_receiver2.expect_receive_report().returning(|_, _| ());
-        let receivers: Vec<Box<dyn HidReportReceiver>> =
-            vec![Box::new(mock_hid_receiver1), Box::new(mock_hid_receiver2)];
+        let controller = 0x02 as efi::Handle;
+        let receivers: Vec<(efi::Handle, Box<dyn HidReportReceiver>)> =
+            vec![(controller, Box::new(mock_hid_receiver1)), (controller, Box::new(mock_hid_receiver2))];
 
-        let mut hid_splitter = HidSplitter { receivers };
+        let mut hid_splitter = HidSplitter { receivers: Rc::new(RefCell::new(HidReceivers(receivers))) };
         let mock_hid_io = MockHidIo::new();
         hid_splitter.receive_report(&[0, 0, 0, 0], &mock_hid_io);
     }
@@ -10,18 +10,26 @@
 //!
 //! SPDX-License-Identifier: BSD-2-Clause-Patent
 //!
-use alloc::{boxed::Box, vec};
-use core::{ffi::c_void, ptr, slice::from_raw_parts_mut};
+mod descriptor_dump;
+mod latency;
+
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{cell::Cell, ffi::c_void, ptr, slice::from_raw_parts_mut};
 
 #[cfg(test)]
 use mockall::automock;
 use r_efi::efi;
 
 use hid_io::protocol::HidReportType;
-use hidparser::ReportDescriptor;
-use rust_advanced_logger_dxe::{debugln, DEBUG_ERROR};
+use hidparser::{ReportDescriptor, ReportField};
+use rust_advanced_logger_dxe::{debugln, timestamp, TimestampSource, DEBUG_ERROR, DEBUG_INFO, DEBUG_WARN};
+
+use self::latency::LatencyHistogram;
+use crate::{boot_services::UefiBootServices, config::HidConfig};
 
-use crate::boot_services::UefiBootServices;
+// Interval at which input reports are polled with GET_REPORT for devices that do not support asynchronous report
+// delivery (10ms, in 100ns units).
+const REPORT_POLL_INTERVAL: u64 = 100_000;
 
 /// Defines an interface to be implemented by logic that wants to receive hid reports.
 #[cfg_attr(test, automock)]
@@ -41,6 +49,9 @@ pub trait HidIo {
     fn get_report_descriptor(&self) -> Result<ReportDescriptor, efi::Status>;
     /// sends an output report to the device.
     fn set_output_report(&self, id: Option<u8>, report: &[u8]) -> Result<(), efi::Status>;
+    /// reads an input report from the device into `report`. If the device uses report IDs, the report includes the
+    /// report ID byte.
+    fn get_input_report(&self, id: Option<u8>, report: &mut [u8]) -> Result<(), efi::Status>;
     /// configures a receiver to receive reports from the device and configures the device to send reports.
     fn set_report_receiver(&mut self, receiver: Box<dyn HidReportReceiver>) -> Result<(), efi::Status>;
     /// removes the receiver and stops the device from sending reports.
@@ -77,6 +88,15 @@ impl HidIoFactory for UefiHidIoFactory {
     }
 }
 
+// An input report that is polled with GET_REPORT, with the last value read for it.
+struct PolledReport {
+    id: Option<u8>,
+    size: usize,
+    // set if the report carries relative values (e.g. mouse movement), for which a repeated report is repeated motion.
+    relative: bool,
+    last: Vec<u8>,
+}
+
 /// Implements the HidIo interface on top of the HidIo protocol.
 pub struct UefiHidIo {
     hid_io: &'static mut hid_io::protocol::Protocol,
@@ -85,6 +105,15 @@ pub struct UefiHidIo {
     agent: efi::Handle,
     receiver: Option<Box<dyn HidReportReceiver>>,
     owned: bool,
+    poll_event: efi::Event,
+    poll_reports: Vec<PolledReport>,
+    poll_buffer: Vec<u8>,
+    descriptor_dumped: Cell<bool>,
+    latency: LatencyHistogram,
+    tick_source: TimestampSource,
+    ready_to_boot_event: efi::Event,
+    config: &'static HidConfig,
+    capture_log: fn(controller: efi::Handle, ticks: u64, report: &[u8]),
 }
 
 impl UefiHidIo {
@@ -120,7 +149,36 @@ impl UefiHidIo {
         }
 
         let hid_io = unsafe { hid_io_ptr.as_mut().expect("bad hid_io ptr") };
-        Ok(Self { hid_io, boot_services, controller, agent, receiver: None, owned })
+        Ok(Self {
+            hid_io,
+            boot_services,
+            controller,
+            agent,
+            receiver: None,
+            owned,
+            poll_event: ptr::null_mut(),
+            poll_reports: Vec::new(),
+            poll_buffer: Vec::new(),
+            descriptor_dumped: Cell::new(false),
+            latency: LatencyHistogram::default(),
+            tick_source: timestamp,
+            ready_to_boot_event: ptr::null_mut(),
+            config: &crate::HID_CONFIG,
+            capture_log: log_captured_report,
+        })
+    }
+
+    // passes a report to the receiver, recording the time taken to process it in the latency histogram. Reports from a
+    // controller selected for verbose capture through the configuration protocol are also logged.
+    fn dispatch_report(&mut self, receiver: &mut dyn HidReportReceiver, report: &[u8]) {
+        let start = (self.tick_source)();
+        receiver.receive_report(report, self);
+        let end = (self.tick_source)();
+        self.latency.record(end.saturating_sub(start));
+
+        if self.config.take_verbose_report(self.controller) {
+            (self.capture_log)(self.controller, end.saturating_sub(start), report);
+        }
     }
 
     // the report callback FFI interface that is submitted to the HidIo instance to receive callbacks for reports.
@@ -128,15 +186,139 @@ impl UefiHidIo {
         let hid_io = unsafe { (context as *mut Self).as_mut().expect("bad context") };
         if let Some(mut receiver) = hid_io.receiver.take() {
             let report = unsafe { from_raw_parts_mut(report_buffer as *mut u8, report_buffer_size as usize) };
-            receiver.receive_report(report, hid_io);
+            hid_io.dispatch_report(receiver.as_mut(), report);
             hid_io.receiver = Some(receiver);
         }
     }
+
+    // timer callback that polls each input report with GET_REPORT and passes the results to the receiver. GET_REPORT
+    // returns the current report whether or not it changed, so a report identical to the last one polled for the same
+    // report ID is not passed on; this matches asynchronous delivery, where devices only report changes. Reports with
+    // relative values are always passed on, since a device that keeps moving repeats the same report.
+    extern "efiapi" fn poll_callback(_event: efi::Event, context: *mut c_void) {
+        let hid_io = unsafe { (context as *mut Self).as_mut().expect("bad context") };
+        let Some(mut receiver) = hid_io.receiver.take() else {
+            return;
+        };
+        let mut buffer = core::mem::take(&mut hid_io.poll_buffer);
+        for index in 0..hid_io.poll_reports.len() {
+            let report = &mut buffer[..hid_io.poll_reports[index].size];
+            if hid_io.get_input_report(hid_io.poll_reports[index].id, report).is_err() {
+                continue;
+            }
+            let polled = &mut hid_io.poll_reports[index];
+            if !polled.relative {
+                if polled.last == report {
+                    continue;
+                }
+                polled.last.clear();
+                polled.last.extend_from_slice(report);
+            }
+            hid_io.dispatch_report(receiver.as_mut(), report);
+        }
+        hid_io.poll_buffer = buffer;
+        hid_io.receiver = Some(receiver);
+    }
+
+    // starts a periodic timer that polls the device for input reports. Used for devices that do not support
+    // asynchronous report delivery.
+    fn start_polling(&mut self) -> Result<(), efi::Status> {
+        let descriptor = self.get_report_descriptor()?;
+        self.poll_reports = descriptor
+            .input_reports
+            .iter()
+            .map(|report| {
+                let id = report.report_id.map(|x| u32::from(x) as u8);
+                let size = report.size_in_bits.div_ceil(8) + id.map_or(0, |_| 1);
+                let relative = report.fields.iter().any(|field| match field {
+                    ReportField::Variable(field) => field.attributes.relative,
+                    _ => false,
+                });
+                PolledReport { id, size, relative, last: Vec::new() }
+            })
+            .collect();
+        // the reports are read into a single buffer that is reused on every poll.
+        self.poll_buffer = vec![0u8; self.poll_reports.iter().map(|report| report.size).max().unwrap_or(0)];
+
+        let mut poll_event: efi::Event = ptr::null_mut();
+        let status = self.boot_services.create_event(
+            efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            Some(Self::poll_callback),
+            self as *mut Self as *mut c_void,
+            ptr::addr_of_mut!(poll_event),
+        );
+        if status.is_error() {
+            return Err(status);
+        }
+
+        let status = self.boot_services.set_timer(poll_event, efi::TIMER_PERIODIC, REPORT_POLL_INTERVAL);
+        if status.is_error() {
+            let _ = self.boot_services.close_event(poll_event);
+            return Err(status);
+        }
+        self.poll_event = poll_event;
+        Ok(())
+    }
+
+    // ReadyToBoot callback that writes the report latency histogram to the debug log.
+    extern "efiapi" fn ready_to_boot_callback(_event: efi::Event, context: *mut c_void) {
+        let hid_io = unsafe { (context as *mut Self).as_ref().expect("bad context") };
+        hid_io.latency.log(hid_io.controller);
+    }
+
+    // creates an event to log the report latency histogram at ReadyToBoot, if not already created.
+    fn install_ready_to_boot_event(&mut self) -> Result<(), efi::Status> {
+        if !self.ready_to_boot_event.is_null() {
+            return Ok(());
+        }
+        let mut ready_to_boot_event: efi::Event = ptr::null_mut();
+        let status = self.boot_services.create_event_ex(
+            efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            Some(Self::ready_to_boot_callback),
+            self as *mut Self as *mut c_void,
+            &efi::EVENT_GROUP_READY_TO_BOOT,
+            ptr::addr_of_mut!(ready_to_boot_event),
+        );
+        if status.is_error() {
+            return Err(status);
+        }
+        self.ready_to_boot_event = ready_to_boot_event;
+        Ok(())
+    }
+
+    // stops polling the device for input reports, if active.
+    fn stop_polling(&mut self) {
+        if !self.poll_event.is_null() {
+            let status = self.boot_services.close_event(self.poll_event);
+            if status.is_error() {
+                debugln!(DEBUG_ERROR, "Unexpected error closing report poll event: {:x?}", status);
+            }
+            self.poll_event = ptr::null_mut();
+        }
+    }
+}
+
+// writes a report captured through the configuration protocol to the debug log.
+fn log_captured_report(controller: efi::Handle, ticks: u64, report: &[u8]) {
+    debugln!(DEBUG_INFO, "HidIo: controller {:?} report ({} ticks): {:x?}", controller, ticks, report);
 }
 
 impl Drop for UefiHidIo {
     // Closes the HidIo interface if owned.
     fn drop(&mut self) {
+        // the poll and ReadyToBoot events reference this instance, so they must not outlive it.
+        self.stop_polling();
+        if !self.ready_to_boot_event.is_null() {
+            let status = self.boot_services.close_event(self.ready_to_boot_event);
+            if status.is_error() {
+                debugln!(DEBUG_ERROR, "Unexpected error closing ReadyToBoot event: {:x?}", status);
+            }
+        }
+        if self.latency.count() != 0 {
+            self.latency.log(self.controller);
+        }
         if self.owned {
             let _ = self.take_report_receiver();
             let status = self.boot_services.close_protocol(
@@ -177,6 +359,11 @@ impl HidIo for UefiHidIo {
             err => return Err(err),
         }
 
+        // the descriptor is read by each receiver that is offered the device; dump it for bring-up only the first time.
+        if !self.descriptor_dumped.replace(true) {
+            descriptor_dump::dump_report_descriptor(&report_descriptor_buffer);
+        }
+
         hidparser::parse_report_descriptor(&report_descriptor_buffer).map_err(|_| efi::Status::DEVICE_ERROR)
     }
 
@@ -193,6 +380,19 @@ impl HidIo for UefiHidIo {
         }
     }
 
+    fn get_input_report(&self, id: Option<u8>, report: &mut [u8]) -> Result<(), efi::Status> {
+        match (self.hid_io.get_report)(
+            self.hid_io,
+            id.unwrap_or(0),
+            HidReportType::InputReport,
+            report.len(),
+            report.as_mut_ptr() as *mut c_void,
+        ) {
+            efi::Status::SUCCESS => Ok(()),
+            err => Err(err),
+        }
+    }
+
     fn set_report_receiver(&mut self, receiver: Box<dyn HidReportReceiver>) -> Result<(), efi::Status> {
         if !self.owned {
             return Err(efi::Status::ACCESS_DENIED);
@@ -202,13 +402,24 @@ impl HidIo for UefiHidIo {
         //always attempt uninstall. Failure is ok if not already installed. This shuts down report callback generation
         //(if any) so that callbacks are not occurring while the new receiver is installed.
         let _ = (self.hid_io.unregister_report_callback)(self.hid_io, Self::report_callback);
+        self.stop_polling();
 
         match (self.hid_io.register_report_callback)(self.hid_io, Self::report_callback, self_ptr as *mut c_void) {
             efi::Status::SUCCESS => (),
+            efi::Status::UNSUPPORTED => {
+                // the device does not deliver reports asynchronously, so poll for them instead.
+                debugln!(DEBUG_INFO, "HidIo: asynchronous reports unsupported, polling with GET_REPORT.");
+                self.start_polling()?;
+            }
             err => return Err(err),
         }
         self.receiver = Some(receiver);
 
+        // without the event, the latency histogram is still logged when the device is stopped.
+        if let Err(status) = self.install_ready_to_boot_event() {
+            debugln!(DEBUG_WARN, "HidIo: failed to install ReadyToBoot event: {:x?}", status);
+        }
+
         Ok(())
     }
     fn take_report_receiver(&mut self) -> Option<Box<dyn HidReportReceiver>> {
@@ -218,6 +429,7 @@ impl HidIo for UefiHidIo {
         //always attempt uninstall. Failure is ok if not already installed. This shuts down report callback generation
         //(if any) so that callbacks are not occurring before the receiver is removed.
         let _ = (self.hid_io.unregister_report_callback)(self.hid_io, Self::report_callback);
+        self.stop_polling();
         self.receiver.take()
     }
 }
@@ -228,11 +440,12 @@ mod test {
         ffi::c_void,
         ptr,
         slice::{from_raw_parts, from_raw_parts_mut},
+        sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
     };
 
-    use super::{HidIo, MockHidReportReceiver, UefiHidIo};
+    use super::{latency::LATENCY_BUCKETS, HidIo, MockHidReportReceiver, UefiHidIo};
 
-    use crate::boot_services::MockUefiBootServices;
+    use crate::{boot_services::MockUefiBootServices, config::HidConfig};
 
     use r_efi::efi;
 
@@ -441,6 +654,7 @@ mod test {
         });
 
         boot_services.expect_close_protocol().returning(|_, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_create_event_ex().returning(|_, _, _, _, _, _| efi::Status::SUCCESS);
 
         let mut uefi_hid_io = UefiHidIo::new(boot_services, agent, controller, true).unwrap();
 
@@ -457,4 +671,288 @@ mod test {
 
         drop(uefi_hid_io);
     }
+
+    #[test]
+    fn verbose_capture_should_count_reports_from_the_captured_controller() {
+        static CAPTURED_REPORTS: AtomicUsize = AtomicUsize::new(0);
+        fn count_captured_report(controller: efi::Handle, _ticks: u64, report: &[u8]) {
+            assert_eq!(controller, 0x1234 as efi::Handle);
+            assert_eq!(report, TEST_REPORT1);
+            CAPTURED_REPORTS.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let boot_services = create_fake_static_boot_service();
+        let controller: efi::Handle = 0x1234 as efi::Handle;
+        let agent: efi::Handle = 0x4321 as efi::Handle;
+
+        boot_services.expect_open_protocol().returning(|_, _, interface, _, _, _| {
+            let hid_io = mock_hid_io();
+            unsafe { *interface = Box::into_raw(Box::new(hid_io)) as *mut c_void };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_close_protocol().returning(|_, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_create_event_ex().returning(|_, _, _, _, _, _| efi::Status::SUCCESS);
+
+        // use a dedicated config rather than the global one so that other tests are not affected.
+        let config: &'static HidConfig = Box::leak(Box::new(HidConfig::new()));
+        let mut uefi_hid_io = UefiHidIo::new(boot_services, agent, controller, true).unwrap();
+        uefi_hid_io.config = config;
+        uefi_hid_io.capture_log = count_captured_report;
+
+        let mut mock_receiver = MockHidReportReceiver::new();
+        mock_receiver.expect_receive_report().returning(|_, _| ());
+        uefi_hid_io.set_report_receiver(Box::new(mock_receiver)).unwrap();
+
+        // capture four reports, then deliver three: each is logged, and exactly one capture should remain.
+        config.set_verbose_capture(controller, 4).unwrap();
+        let context = ptr::addr_of_mut!(uefi_hid_io) as *mut c_void;
+        for _ in 0..3 {
+            UefiHidIo::report_callback(TEST_REPORT1.len() as u16, TEST_REPORT1.as_ptr() as *mut c_void, context);
+        }
+        assert_eq!(CAPTURED_REPORTS.load(Ordering::SeqCst), 3);
+        assert!(config.take_verbose_report(controller));
+        assert!(!config.take_verbose_report(controller));
+
+        // reports beyond the capture are not counted, and capture for another controller is unaffected.
+        config.set_verbose_capture(0x5678 as efi::Handle, 1).unwrap();
+        UefiHidIo::report_callback(TEST_REPORT1.len() as u16, TEST_REPORT1.as_ptr() as *mut c_void, context);
+        assert_eq!(CAPTURED_REPORTS.load(Ordering::SeqCst), 3);
+        assert!(config.take_verbose_report(0x5678 as efi::Handle));
+
+        drop(uefi_hid_io);
+    }
+
+    #[test]
+    fn report_processing_latency_should_be_recorded_in_histogram() {
+        // start/end ticks for each report: 0, 3 and 1024 ticks of processing time.
+        static TICKS: [u64; 6] = [100, 100, 200, 203, 1000, 2024];
+        static TICK_INDEX: AtomicUsize = AtomicUsize::new(0);
+        fn mock_ticks() -> u64 {
+            TICKS[TICK_INDEX.fetch_add(1, Ordering::SeqCst)]
+        }
+        const READY_TO_BOOT_EVENT: usize = 0x5678;
+
+        let boot_services = create_fake_static_boot_service();
+        let controller: efi::Handle = 0x1234 as efi::Handle;
+        let agent: efi::Handle = 0x4321 as efi::Handle;
+
+        boot_services.expect_open_protocol().returning(|_, _, interface, _, _, _| {
+            let hid_io = mock_hid_io();
+            unsafe { *interface = Box::into_raw(Box::new(hid_io)) as *mut c_void };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_close_protocol().returning(|_, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_create_event_ex().times(1).returning(|r#type, _, notify, _, event_group, event| {
+            assert_eq!(r#type, efi::EVT_NOTIFY_SIGNAL);
+            assert!(notify.is_some());
+            assert_eq!(unsafe { *event_group }, efi::EVENT_GROUP_READY_TO_BOOT);
+            unsafe { event.write(READY_TO_BOOT_EVENT as efi::Event) };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_close_event().times(1).returning(|event| {
+            assert_eq!(event, READY_TO_BOOT_EVENT as efi::Event);
+            efi::Status::SUCCESS
+        });
+
+        let mut uefi_hid_io = UefiHidIo::new(boot_services, agent, controller, true).unwrap();
+        uefi_hid_io.tick_source = mock_ticks;
+
+        let mut mock_receiver = MockHidReportReceiver::new();
+        mock_receiver.expect_receive_report().returning(|_, _| ());
+        uefi_hid_io.set_report_receiver(Box::new(mock_receiver)).unwrap();
+
+        let context = ptr::addr_of_mut!(uefi_hid_io) as *mut c_void;
+        for _ in 0..3 {
+            UefiHidIo::report_callback(TEST_REPORT1.len() as u16, TEST_REPORT1.as_ptr() as *mut c_void, context);
+        }
+
+        let mut expected = [0u32; LATENCY_BUCKETS];
+        expected[0] = 1;
+        expected[2] = 1;
+        expected[11] = 1;
+        assert_eq!(uefi_hid_io.latency.buckets(), &expected);
+
+        // the histogram is logged at ReadyToBoot; this must not disturb it.
+        UefiHidIo::ready_to_boot_callback(READY_TO_BOOT_EVENT as efi::Event, context);
+        assert_eq!(uefi_hid_io.latency.buckets(), &expected);
+
+        drop(uefi_hid_io);
+    }
+
+    #[test]
+    fn set_receiver_should_poll_when_async_reports_unsupported() {
+        static GET_REPORT_CALLS: AtomicUsize = AtomicUsize::new(0);
+        static POLL_CONTEXT: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+        const POLL_EVENT: usize = 0x5678;
+
+        extern "efiapi" fn mock_get_report(
+            this: *const hid_io::protocol::Protocol,
+            report_id: u8,
+            report_type: hid_io::protocol::HidReportType,
+            report_buffer_size: usize,
+            report_buffer: *mut c_void,
+        ) -> efi::Status {
+            assert_ne!(this, ptr::null());
+            assert_eq!(report_id, 0);
+            assert_eq!(report_type, hid_io::protocol::HidReportType::InputReport);
+            assert_eq!(report_buffer_size, 1);
+            // each report is returned twice, as a device would when its state has not changed between polls.
+            let count = GET_REPORT_CALLS.fetch_add(1, Ordering::SeqCst);
+            unsafe { *(report_buffer as *mut u8) = (count / 2) as u8 };
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn mock_register_report_callback(
+            _this: *const hid_io::protocol::Protocol,
+            _callback: hid_io::protocol::HidIoReportCallback,
+            _context: *mut c_void,
+        ) -> efi::Status {
+            efi::Status::UNSUPPORTED
+        }
+
+        let boot_services = create_fake_static_boot_service();
+        let controller: efi::Handle = 0x1234 as efi::Handle;
+        let agent: efi::Handle = 0x4321 as efi::Handle;
+
+        boot_services.expect_open_protocol().returning(|_, _, interface, _, _, _| {
+            let mut hid_io = mock_hid_io();
+            hid_io.get_report = mock_get_report;
+            hid_io.register_report_callback = mock_register_report_callback;
+            unsafe { *interface = Box::into_raw(Box::new(hid_io)) as *mut c_void };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_close_protocol().returning(|_, _, _, _| efi::Status::SUCCESS);
+
+        boot_services.expect_create_event().times(1).returning(|r#type, tpl, notify, context, event| {
+            assert_eq!(r#type, efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL);
+            assert_eq!(tpl, efi::TPL_CALLBACK);
+            assert!(notify == Some(UefiHidIo::poll_callback));
+            POLL_CONTEXT.store(context, Ordering::SeqCst);
+            unsafe { event.write(POLL_EVENT as efi::Event) };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_set_timer().times(1).returning(|event, r#type, trigger_time| {
+            assert_eq!(event, POLL_EVENT as efi::Event);
+            assert_eq!(r#type, efi::TIMER_PERIODIC);
+            assert_ne!(trigger_time, 0);
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_close_event().times(1).returning(|event| {
+            assert_eq!(event, POLL_EVENT as efi::Event);
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_create_event_ex().returning(|_, _, _, _, _, _| efi::Status::SUCCESS);
+
+        let mut uefi_hid_io = UefiHidIo::new(boot_services, agent, controller, true).unwrap();
+
+        let mut mock_receiver = MockHidReportReceiver::new();
+        let mut expected_report = 0;
+        mock_receiver.expect_receive_report().times(2).returning(move |report, _| {
+            assert_eq!(report, &[expected_report]);
+            expected_report += 1;
+        });
+
+        uefi_hid_io.set_report_receiver(Box::new(mock_receiver)).unwrap();
+        assert_eq!(GET_REPORT_CALLS.load(Ordering::SeqCst), 0);
+
+        // each timer tick should read the input report and pass it to the receiver if it changed since the last tick.
+        for _ in 0..4 {
+            UefiHidIo::poll_callback(POLL_EVENT as efi::Event, POLL_CONTEXT.load(Ordering::SeqCst));
+        }
+        assert_eq!(GET_REPORT_CALLS.load(Ordering::SeqCst), 4);
+
+        // removing the receiver should stop polling.
+        assert!(uefi_hid_io.take_report_receiver().is_some());
+
+        drop(uefi_hid_io);
+    }
+
+    #[test]
+    fn poll_should_pass_on_repeated_relative_reports() {
+        static RELATIVE_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+            0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+            0x09, 0x02, // USAGE (Mouse)
+            0xa1, 0x01, // COLLECTION (Application)
+            0x09, 0x30, //   USAGE (X)
+            0x15, 0x81, //   LOGICAL_MINIMUM (-127)
+            0x25, 0x7f, //   LOGICAL_MAXIMUM (127)
+            0x75, 0x08, //   REPORT_SIZE (8)
+            0x95, 0x01, //   REPORT_COUNT (1)
+            0x81, 0x06, //   INPUT (Data,Var,Rel)
+            0xc0, // END_COLLECTION
+        ];
+        static POLL_CONTEXT: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+        const POLL_EVENT: usize = 0x5678;
+
+        extern "efiapi" fn mock_get_report_descriptor(
+            _this: *const hid_io::protocol::Protocol,
+            report_descriptor_size: *mut usize,
+            report_descriptor_buffer: *mut c_void,
+        ) -> efi::Status {
+            let descriptor = RELATIVE_MOUSE_REPORT_DESCRIPTOR;
+            unsafe {
+                if *report_descriptor_size < descriptor.len() {
+                    *report_descriptor_size = descriptor.len();
+                    return efi::Status::BUFFER_TOO_SMALL;
+                }
+                *report_descriptor_size = descriptor.len();
+                from_raw_parts_mut(report_descriptor_buffer as *mut u8, descriptor.len()).copy_from_slice(descriptor);
+            }
+            efi::Status::SUCCESS
+        }
+
+        // the mouse keeps moving right at a constant speed, so every report is the same.
+        extern "efiapi" fn mock_get_report(
+            _this: *const hid_io::protocol::Protocol,
+            _report_id: u8,
+            _report_type: hid_io::protocol::HidReportType,
+            report_buffer_size: usize,
+            report_buffer: *mut c_void,
+        ) -> efi::Status {
+            assert_eq!(report_buffer_size, 1);
+            unsafe { *(report_buffer as *mut u8) = 0x01 };
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn mock_register_report_callback(
+            _this: *const hid_io::protocol::Protocol,
+            _callback: hid_io::protocol::HidIoReportCallback,
+            _context: *mut c_void,
+        ) -> efi::Status {
+            efi::Status::UNSUPPORTED
+        }
+
+        let boot_services = create_fake_static_boot_service();
+        boot_services.expect_open_protocol().returning(|_, _, interface, _, _, _| {
+            let mut hid_io = mock_hid_io();
+            hid_io.get_report_descriptor = mock_get_report_descriptor;
+            hid_io.get_report = mock_get_report;
+            hid_io.register_report_callback = mock_register_report_callback;
+            unsafe { *interface = Box::into_raw(Box::new(hid_io)) as *mut c_void };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_close_protocol().returning(|_, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_create_event().returning(|_, _, _, context, event| {
+            POLL_CONTEXT.store(context, Ordering::SeqCst);
+            unsafe { event.write(POLL_EVENT as efi::Event) };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_set_timer().returning(|_, _, _| efi::Status::SUCCESS);
+        boot_services.expect_close_event().returning(|_| efi::Status::SUCCESS);
+        boot_services.expect_create_event_ex().returning(|_, _, _, _, _, _| efi::Status::SUCCESS);
+
+        let mut uefi_hid_io =
+            UefiHidIo::new(boot_services, 0x4321 as efi::Handle, 0x1234 as efi::Handle, true).unwrap();
+
+        let mut mock_receiver = MockHidReportReceiver::new();
+        mock_receiver.expect_receive_report().times(4).returning(|report, _| assert_eq!(report, &[0x01]));
+        uefi_hid_io.set_report_receiver(Box::new(mock_receiver)).unwrap();
+
+        // each identical report is motion, so each timer tick should pass the report on.
+        for _ in 0..4 {
+            UefiHidIo::poll_callback(POLL_EVENT as efi::Event, POLL_CONTEXT.load(Ordering::SeqCst));
+        }
+
+        drop(uefi_hid_io);
+    }
 }
@@ -0,0 +1,201 @@
+//! Report descriptor dump.
+//!
+//! This module decodes raw report descriptors item-by-item and writes them to the debug log, to help with bring-up of
+//! new HID devices.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation. All rights reserved.
+//!
+//! SPDX-License-Identifier: BSD-2-Clause-Patent
+//!
+use rust_advanced_logger_dxe::{debugln, get_debug_level, DEBUG_VERBOSE};
+
+// item types (bits 2-3 of the item prefix).
+const ITEM_TYPE_MAIN: u8 = 0;
+const ITEM_TYPE_GLOBAL: u8 = 1;
+const ITEM_TYPE_LOCAL: u8 = 2;
+
+// prefix of a long item; long items are not used by any defined items, but may be present.
+const LONG_ITEM_PREFIX: u8 = 0xFE;
+
+/// A single item from a report descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DescriptorItem {
+    /// Offset of the item prefix within the descriptor.
+    pub(crate) offset: usize,
+    /// The item prefix (tag, type and size).
+    pub(crate) prefix: u8,
+    /// The item data, zero-extended (zero for long items).
+    pub(crate) data: u32,
+}
+
+impl DescriptorItem {
+    /// Returns the name of the item, per the HID specification.
+    pub(crate) fn name(&self) -> &'static str {
+        if self.prefix == LONG_ITEM_PREFIX {
+            return "Long Item";
+        }
+        match ((self.prefix >> 2) & 0x3, self.prefix >> 4) {
+            (ITEM_TYPE_MAIN, 0x8) => "Input",
+            (ITEM_TYPE_MAIN, 0x9) => "Output",
+            (ITEM_TYPE_MAIN, 0xA) => "Collection",
+            (ITEM_TYPE_MAIN, 0xB) => "Feature",
+            (ITEM_TYPE_MAIN, 0xC) => "End Collection",
+            (ITEM_TYPE_GLOBAL, 0x0) => "Usage Page",
+            (ITEM_TYPE_GLOBAL, 0x1) => "Logical Minimum",
+            (ITEM_TYPE_GLOBAL, 0x2) => "Logical Maximum",
+            (ITEM_TYPE_GLOBAL, 0x3) => "Physical Minimum",
+            (ITEM_TYPE_GLOBAL, 0x4) => "Physical Maximum",
+            (ITEM_TYPE_GLOBAL, 0x5) => "Unit Exponent",
+            (ITEM_TYPE_GLOBAL, 0x6) => "Unit",
+            (ITEM_TYPE_GLOBAL, 0x7) => "Report Size",
+            (ITEM_TYPE_GLOBAL, 0x8) => "Report ID",
+            (ITEM_TYPE_GLOBAL, 0x9) => "Report Count",
+            (ITEM_TYPE_GLOBAL, 0xA) => "Push",
+            (ITEM_TYPE_GLOBAL, 0xB) => "Pop",
+            (ITEM_TYPE_LOCAL, 0x0) => "Usage",
+            (ITEM_TYPE_LOCAL, 0x1) => "Usage Minimum",
+            (ITEM_TYPE_LOCAL, 0x2) => "Usage Maximum",
+            (ITEM_TYPE_LOCAL, 0x3) => "Designator Index",
+            (ITEM_TYPE_LOCAL, 0x4) => "Designator Minimum",
+            (ITEM_TYPE_LOCAL, 0x5) => "Designator Maximum",
+            (ITEM_TYPE_LOCAL, 0x7) => "String Index",
+            (ITEM_TYPE_LOCAL, 0x8) => "String Minimum",
+            (ITEM_TYPE_LOCAL, 0x9) => "String Maximum",
+            (ITEM_TYPE_LOCAL, 0xA) => "Delimiter",
+            _ => "Reserved",
+        }
+    }
+}
+
+/// Iterates over the items of a report descriptor. Iteration stops at the end of the descriptor, or at an item that
+/// is truncated.
+pub(crate) struct DescriptorItems<'a> {
+    descriptor: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for DescriptorItems<'a> {
+    type Item = DescriptorItem;
+
+    fn next(&mut self) -> Option<DescriptorItem> {
+        let offset = self.offset;
+        let prefix = *self.descriptor.get(offset)?;
+        let (header_size, data_size) = match prefix {
+            // long items carry their data size in the following byte (and their tag in the byte after).
+            LONG_ITEM_PREFIX => (3, *self.descriptor.get(offset + 1)? as usize),
+            _ => (1, [0, 1, 2, 4][(prefix & 0x3) as usize]),
+        };
+        let data = self.descriptor.get(offset + header_size..offset + header_size + data_size)?;
+        self.offset += header_size + data_size;
+
+        let data = match prefix {
+            LONG_ITEM_PREFIX => 0,
+            _ => data.iter().rev().fold(0, |value, byte| value << 8 | *byte as u32),
+        };
+        Some(DescriptorItem { offset, prefix, data })
+    }
+}
+
+/// Returns an iterator over the items of the given report descriptor.
+pub(crate) fn descriptor_items(descriptor: &[u8]) -> DescriptorItems<'_> {
+    DescriptorItems { descriptor, offset: 0 }
+}
+
+/// Writes the raw bytes of the given report descriptor, followed by a decoded item-by-item listing, to the debug log at
+/// DEBUG_VERBOSE. Does nothing (not even decoding) if DEBUG_VERBOSE output is disabled.
+pub(crate) fn dump_report_descriptor(descriptor: &[u8]) {
+    if get_debug_level() & DEBUG_VERBOSE == 0 {
+        return;
+    }
+
+    debugln!(DEBUG_VERBOSE, "HidIo: report descriptor ({:} bytes):", descriptor.len());
+    for (index, chunk) in descriptor.chunks(16).enumerate() {
+        debugln!(DEBUG_VERBOSE, "  {:04x}: {:02x?}", index * 16, chunk);
+    }
+
+    let mut depth = 0usize;
+    for item in descriptor_items(descriptor) {
+        if item.name() == "End Collection" {
+            depth = depth.saturating_sub(1);
+        }
+        debugln!(
+            DEBUG_VERBOSE,
+            "  {:04x}: {:indent$}{:} ({:#x})",
+            item.offset,
+            "",
+            item.name(),
+            item.data,
+            indent = depth * 2
+        );
+        if item.name() == "Collection" {
+            depth += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{descriptor_items, dump_report_descriptor, DescriptorItem};
+    use alloc::vec::Vec;
+
+    static MOUSE_WITH_REPORT_ID_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x02, // USAGE (Mouse)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x85, 0x02, //   REPORT_ID (2)
+        0x05, 0x09, //   USAGE_PAGE (Button)
+        0x19, 0x01, //   USAGE_MINIMUM(1)
+        0x29, 0x03, //   USAGE_MAXIMUM(3)
+        0x15, 0x00, //   LOGICAL_MINIMUM(0)
+        0x25, 0x01, //   LOGICAL_MAXIMUM(1)
+        0x95, 0x03, //   REPORT_COUNT(3)
+        0x75, 0x01, //   REPORT_SIZE(1)
+        0x81, 0x02, //   INPUT(Data, Variable, Absolute)
+        0x95, 0x01, //   REPORT_COUNT(1)
+        0x75, 0x05, //   REPORT_SIZE(5)
+        0x81, 0x01, //   INPUT(Constant, Array, Absolute)
+        0x05, 0x01, //   USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //   USAGE (X)
+        0x09, 0x31, //   USAGE (Y)
+        0x16, 0x01, 0x80, //   LOGICAL_MINIMUM (-32767)
+        0x26, 0xff, 0x7f, //   LOGICAL_MAXIMUM (32767)
+        0x75, 0x10, //   REPORT_SIZE (16)
+        0x95, 0x02, //   REPORT_COUNT (2)
+        0x81, 0x06, //   INPUT(Data, Variable, Relative)
+        0xc0, // END_COLLECTION
+    ];
+
+    #[test]
+    fn descriptor_items_should_decode_each_item() {
+        let items: Vec<DescriptorItem> = descriptor_items(MOUSE_WITH_REPORT_ID_REPORT_DESCRIPTOR).collect();
+        assert_eq!(items.len(), 24);
+
+        let report_id = items.iter().find(|item| item.name() == "Report ID").unwrap();
+        assert_eq!(*report_id, DescriptorItem { offset: 6, prefix: 0x85, data: 2 });
+
+        // multi-byte data is little-endian.
+        let logical_maximum = items.iter().rfind(|item| item.name() == "Logical Maximum").unwrap();
+        assert_eq!(logical_maximum.data, 0x7fff);
+
+        assert_eq!(items.first().unwrap().name(), "Usage Page");
+        assert_eq!(items.last().unwrap().name(), "End Collection");
+
+        // dumping is purely diagnostic, and must not fail.
+        dump_report_descriptor(MOUSE_WITH_REPORT_ID_REPORT_DESCRIPTOR);
+    }
+
+    #[test]
+    fn descriptor_items_should_handle_long_and_truncated_items() {
+        // a long item with two data bytes, followed by an item that is missing its data.
+        let descriptor: &[u8] = &[0xFE, 0x02, 0xF0, 0xAA, 0xBB, 0x09, 0x02, 0x26, 0xff];
+        let items: Vec<DescriptorItem> = descriptor_items(descriptor).collect();
+        assert_eq!(
+            items,
+            [DescriptorItem { offset: 0, prefix: 0xFE, data: 0 }, DescriptorItem { offset: 5, prefix: 0x09, data: 2 }]
+        );
+        assert_eq!(items[0].name(), "Long Item");
+        assert_eq!(items[1].name(), "Usage");
+    }
+}
@@ -0,0 +1,89 @@
+//! Report processing latency histogram.
+//!
+//! This module accumulates the time taken to process each input report into a fixed-size histogram, so that slow
+//! devices or parsing hot spots can be identified from the debug log.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation. All rights reserved.
+//!
+//! SPDX-License-Identifier: BSD-2-Clause-Patent
+//!
+use r_efi::efi;
+
+use rust_advanced_logger_dxe::{debugln, DEBUG_INFO};
+
+/// Number of buckets in a [`LatencyHistogram`].
+pub(crate) const LATENCY_BUCKETS: usize = 32;
+
+/// Histogram of report processing times, in ticks of the logger timestamp source (see
+/// [`rust_advanced_logger_dxe::timestamp`]) so that they can be compared with timestamped log output. Bucket 0 counts
+/// reports processed in zero ticks, and bucket `n` counts reports processed in `2^(n-1)` to `2^n - 1` ticks. The last
+/// bucket also counts all longer times.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct LatencyHistogram {
+    buckets: [u32; LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    /// Records a report that took `ticks` to process.
+    pub(crate) fn record(&mut self, ticks: u64) {
+        let bucket = ((u64::BITS - ticks.leading_zeros()) as usize).min(LATENCY_BUCKETS - 1);
+        self.buckets[bucket] = self.buckets[bucket].saturating_add(1);
+    }
+
+    /// Returns the report count in each bucket.
+    pub(crate) fn buckets(&self) -> &[u32; LATENCY_BUCKETS] {
+        &self.buckets
+    }
+
+    /// Returns the total number of reports recorded.
+    pub(crate) fn count(&self) -> u64 {
+        self.buckets().iter().map(|&count| count as u64).sum()
+    }
+
+    /// Writes the non-empty buckets to the debug log.
+    pub(crate) fn log(&self, controller: efi::Handle) {
+        debugln!(DEBUG_INFO, "HidIo: report latency for controller {:?} ({} reports):", controller, self.count());
+        for (bucket, &count) in self.buckets().iter().enumerate().filter(|(_, &count)| count != 0) {
+            let min_ticks = if bucket == 0 { 0 } else { 1u64 << (bucket - 1) };
+            let suffix = if bucket == LATENCY_BUCKETS - 1 { "+" } else { "" };
+            debugln!(DEBUG_INFO, "  >= {}{} ticks: {}", min_ticks, suffix, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LatencyHistogram, LATENCY_BUCKETS};
+
+    #[test]
+    fn record_should_bucket_by_power_of_two() {
+        let mut histogram = LatencyHistogram::default();
+        for ticks in [0, 1, 2, 3, 4, 7, 8, 1000, 1023, 1024] {
+            histogram.record(ticks);
+        }
+
+        let mut expected = [0u32; LATENCY_BUCKETS];
+        expected[0] = 1; // 0
+        expected[1] = 1; // 1
+        expected[2] = 2; // 2-3
+        expected[3] = 2; // 4-7
+        expected[4] = 1; // 8-15
+        expected[10] = 2; // 512-1023
+        expected[11] = 1; // 1024-2047
+        assert_eq!(histogram.buckets(), &expected);
+        assert_eq!(histogram.count(), 10);
+    }
+
+    #[test]
+    fn record_should_saturate_in_last_bucket() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(1 << (LATENCY_BUCKETS - 2));
+        histogram.record(1 << (LATENCY_BUCKETS - 1));
+        histogram.record(u64::MAX);
+
+        assert_eq!(histogram.buckets()[LATENCY_BUCKETS - 1], 3);
+        assert_eq!(histogram.count(), 3);
+    }
+}
@@ -31,18 +31,36 @@ use rust_advanced_logger_dxe::{debugln, function, DEBUG_ERROR, DEBUG_VERBOSE, DE
 
 use crate::{
     boot_services::UefiBootServices,
-    hid_io::{HidIo, HidReportReceiver},
+    config::HidConfig,
+    hid_io::{HidIo, HidIoFactory, HidReportReceiver, UefiHidIoFactory},
     keyboard::key_queue::OrdKeyData,
 };
 
 // usages supported by this module
+const KEYBOARD_USAGE_PAGE: u32 = 0x00070000;
 const KEYBOARD_MODIFIER_USAGE_MIN: u32 = 0x000700E0;
 const KEYBOARD_MODIFIER_USAGE_MAX: u32 = 0x000700E7;
 const KEYBOARD_USAGE_MIN: u32 = 0x00070001;
 const KEYBOARD_USAGE_MAX: u32 = 0x00070065;
+// reported in every key slot when more keys are pressed than the device can report (or when it detects phantom keys).
+const KEYBOARD_ERROR_ROLLOVER_USAGE: u32 = 0x00070001;
+// lock keys change toggle state on every press, so they are not repeated while held.
+const KEYBOARD_LOCK_USAGES: [u32; 3] = [0x00070039, 0x00070047, 0x00070053];
 const LED_USAGE_MIN: u32 = 0x00080001;
 const LED_USAGE_MAX: u32 = 0x00080005;
 
+// size of a boot keyboard report (HID 1.11 Appendix B.1): a modifier byte, a reserved byte and six key slots.
+const BOOT_KEYBOARD_REPORT_SIZE: usize = 8;
+
+// number of attempts made to send an LED output report before giving up (e.g. if the device is busy).
+const LED_REPORT_ATTEMPTS: usize = 3;
+
+// delay between attempts to send an LED output report, in milliseconds.
+const LED_REPORT_RETRY_DELAY: u32 = 1;
+
+// timer periods are in units of 100ns.
+const TIMER_TICKS_PER_MS: u64 = 10_000;
+
 // maps a given field to a routine that handles input from it.
 #[derive(Debug, Clone)]
 struct ReportFieldWithHandler<T> {
@@ -90,6 +108,7 @@ pub struct KeyboardHidHandler {
     report_id_present: bool,
     last_keys: BTreeSet<Usage>,
     current_keys: BTreeSet<Usage>,
+    boot_protocol: bool,
     led_state: BTreeSet<Usage>,
     key_queue: key_queue::KeyQueue,
     notification_callbacks: BTreeMap<usize, (OrdKeyData, protocols::simple_text_input_ex::KeyNotifyFunction)>,
@@ -97,6 +116,14 @@ pub struct KeyboardHidHandler {
     key_notify_event: efi::Event,
     layout_change_event: efi::Event,
     layout_context: *mut LayoutChangeContext,
+    led_report_failed: bool,
+    led_retry_event: efi::Event,
+    pending_led_reports: BTreeMap<Option<ReportId>, (Vec<u8>, usize)>,
+    config: &'static HidConfig,
+    repeat_event: efi::Event,
+    repeat_key: Option<Usage>,
+    debounce_event: efi::Event,
+    debounced_keys: BTreeSet<Usage>,
 }
 
 impl KeyboardHidHandler {
@@ -111,6 +138,7 @@ impl KeyboardHidHandler {
             report_id_present: false,
             last_keys: BTreeSet::new(),
             current_keys: BTreeSet::new(),
+            boot_protocol: false,
             led_state: BTreeSet::new(),
             key_queue: Default::default(),
             notification_callbacks: BTreeMap::new(),
@@ -118,6 +146,14 @@ impl KeyboardHidHandler {
             key_notify_event: core::ptr::null_mut(),
             layout_change_event: core::ptr::null_mut(),
             layout_context: core::ptr::null_mut(),
+            led_report_failed: false,
+            led_retry_event: core::ptr::null_mut(),
+            pending_led_reports: BTreeMap::new(),
+            config: &crate::HID_CONFIG,
+            repeat_event: core::ptr::null_mut(),
+            repeat_key: None,
+            debounce_event: core::ptr::null_mut(),
+            debounced_keys: BTreeSet::new(),
         }
     }
 
@@ -226,15 +262,19 @@ impl KeyboardHidHandler {
         match field.field_value(report) {
             Some(index) if index != 0 => {
                 let mut index = (index as u32 - u32::from(field.logical_minimum)) as usize;
+                // usages are declared as a list of Usage Minimum/Maximum ranges; walk the ranges to find the one that
+                // contains the index.
                 let usage = field.usage_list.iter().find_map(|x| {
-                    let range_size = (x.end() - x.start()) as usize;
-                    if index <= range_size {
+                    let range_size = (x.end() - x.start()) as usize + 1;
+                    if index < range_size {
                         x.range().nth(index)
                     } else {
                         index -= range_size;
                         None
                     }
                 });
+                // some keyboards also report modifiers from the modifier byte in the array; current_keys is a set, so
+                // such a modifier is only counted once.
                 if let Some(usage) = usage {
                     self.current_keys.insert(Usage::from(usage));
                 }
@@ -243,6 +283,153 @@ impl KeyboardHidHandler {
         }
     }
 
+    // Helper routine to handle input reports in the boot keyboard format.
+    fn handle_boot_report(&mut self, report: &[u8]) {
+        for bit in 0..8 {
+            if report[0] & (1 << bit) != 0 {
+                self.current_keys.insert(Usage::from(KEYBOARD_MODIFIER_USAGE_MIN + bit));
+            }
+        }
+        for &key in report[2..BOOT_KEYBOARD_REPORT_SIZE].iter().filter(|&&key| key != 0) {
+            self.current_keys.insert(Usage::from(KEYBOARD_USAGE_PAGE | key as u32));
+        }
+    }
+
+    // Returns true if the given report is in the boot keyboard format rather than the format in the report descriptor.
+    // Devices may revert to the boot protocol mid-session (e.g. if they are reset), after which they send boot reports
+    // regardless of the report descriptor. This is only detectable for devices without report IDs whose reports are
+    // longer than a boot report, where it shows up as a shorter report. Devices with shorter reports may pad them to
+    // the boot report size, so an 8-byte report from such a device is parsed with the report descriptor.
+    fn is_boot_report(&self, report_data: &KeyboardReportData, report: &[u8]) -> bool {
+        !self.report_id_present
+            && report_data.report_size > BOOT_KEYBOARD_REPORT_SIZE
+            && report.len() == BOOT_KEYBOARD_REPORT_SIZE
+    }
+
+    // Replaces the keys reported by array fields in current_keys with those from last_keys, keeping the keys reported
+    // by variable fields (the modifier byte, for boot reports).
+    fn restore_array_keys(&mut self, report_id: Option<ReportId>, boot_report: bool) {
+        let variable_keys: BTreeSet<Usage> = match (boot_report, self.input_reports.get(&report_id)) {
+            (false, Some(report_data)) => report_data.relevant_variable_fields.iter().map(|x| x.field.usage).collect(),
+            _ => (KEYBOARD_MODIFIER_USAGE_MIN..=KEYBOARD_MODIFIER_USAGE_MAX).map(Usage::from).collect(),
+        };
+        self.current_keys.retain(|key| variable_keys.contains(key));
+        self.current_keys.extend(self.last_keys.difference(&variable_keys));
+    }
+
+    // Releases all held keys. Used when the key state can no longer be tracked across reports.
+    fn release_held_keys(&mut self) {
+        self.debounced_keys.clear();
+        self.repeat_key = None;
+        for key in core::mem::take(&mut self.last_keys).into_iter().rev() {
+            self.key_queue.keystroke(key, key_queue::KeyAction::KeyUp);
+        }
+    }
+
+    // Creates a timer event that invokes `notify_function` with this handler as its context.
+    fn create_timer_event(&mut self, notify_function: efi::EventNotify) -> Result<efi::Event, efi::Status> {
+        let mut event: efi::Event = ptr::null_mut();
+        let status = self.boot_services.create_event(
+            efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            Some(notify_function),
+            self as *mut Self as *mut c_void,
+            ptr::addr_of_mut!(event),
+        );
+        if status.is_error() {
+            Err(status)?;
+        }
+        Ok(event)
+    }
+
+    // Arms `event` to fire once, `milliseconds` from now.
+    fn start_timer(&self, event: efi::Event, milliseconds: u32) -> Result<(), efi::Status> {
+        let status =
+            self.boot_services.set_timer(event, efi::TIMER_RELATIVE, u64::from(milliseconds) * TIMER_TICKS_PER_MS);
+        if status.is_error() {
+            debugln!(DEBUG_WARN, "{:?}: Failed to set timer: {:?}", function!(), status);
+            Err(status)?;
+        }
+        Ok(())
+    }
+
+    // With debounce enabled, a released key is still treated as held until it has stayed released for the debounce
+    // time, so that a key that bounces (is released and pressed again within the debounce time) does not produce
+    // additional keystrokes. The release is processed when the debounce timer fires.
+    fn debounce_released_keys(&mut self) {
+        // keys that were pressed again before the debounce time expired are simply still held.
+        self.debounced_keys.retain(|key| !self.current_keys.contains(key));
+
+        let debounce_time = self.config.debounce_time();
+        if debounce_time != 0 && !self.debounce_event.is_null() {
+            let released_keys: Vec<Usage> = self
+                .last_keys
+                .difference(&self.current_keys)
+                .filter(|key| !self.debounced_keys.contains(key))
+                .copied()
+                .collect();
+            if !released_keys.is_empty() && self.start_timer(self.debounce_event, debounce_time).is_ok() {
+                self.debounced_keys.extend(released_keys);
+            }
+        }
+        self.current_keys.extend(self.debounced_keys.iter().copied());
+    }
+
+    // Processes the releases held back by debounce_released_keys once the debounce time has expired.
+    fn release_debounced_keys(&mut self) {
+        let old_tpl = self.boot_services.raise_tpl(efi::TPL_NOTIFY);
+        for key in core::mem::take(&mut self.debounced_keys).into_iter().rev() {
+            self.last_keys.remove(&key);
+            self.key_queue.keystroke(key, key_queue::KeyAction::KeyUp);
+            if self.repeat_key == Some(key) {
+                self.repeat_key = None;
+            }
+        }
+        self.boot_services.restore_tpl(old_tpl);
+    }
+
+    // Returns true if `key` repeats while it is held. Modifier and lock keys do not repeat.
+    fn is_repeatable_key(key: Usage) -> bool {
+        let key = u32::from(key);
+        (KEYBOARD_USAGE_MIN..=KEYBOARD_USAGE_MAX).contains(&key)
+            && key != KEYBOARD_ERROR_ROLLOVER_USAGE
+            && !KEYBOARD_LOCK_USAGES.contains(&key)
+    }
+
+    // Tracks the key to repeat after a change in key state: the last repeatable key pressed repeats until it is
+    // released (or another repeatable key is pressed).
+    fn update_key_repeat(&mut self, pressed_keys: &[Usage]) {
+        let delay = self.config.key_repeat_delay();
+        if delay == 0 || self.repeat_event.is_null() {
+            self.repeat_key = None;
+            return;
+        }
+        if let Some(key) = pressed_keys.iter().rev().find(|key| Self::is_repeatable_key(**key)) {
+            self.repeat_key = self.start_timer(self.repeat_event, delay).ok().map(|_| *key);
+        } else if self.repeat_key.is_some_and(|key| !self.current_keys.contains(&key)) {
+            // the timer is left to expire; it does nothing without a key to repeat.
+            self.repeat_key = None;
+        }
+    }
+
+    // Repeats the held key (if any) and re-arms the repeat timer for the next repeat.
+    fn repeat_held_key(&mut self) {
+        let old_tpl = self.boot_services.raise_tpl(efi::TPL_NOTIFY);
+        if let Some(key) = self.repeat_key {
+            self.key_queue.keystroke(key, key_queue::KeyAction::KeyDown);
+            if self.key_queue.peek_notify_key().is_some() {
+                self.boot_services.signal_event(self.key_notify_event);
+            }
+            // key repeat may have been disabled since the key was pressed.
+            if self.config.key_repeat_delay() == 0
+                || self.start_timer(self.repeat_event, self.config.key_repeat_interval()).is_err()
+            {
+                self.repeat_key = None;
+            }
+        }
+        self.boot_services.restore_tpl(old_tpl);
+    }
+
     // Helper routine that updates the fields in the given report buffer for the given field (called for each field for
     // every LED usage that was discovered in the output report descriptor).
     fn build_led_report(&mut self, field: VariableField, report: &mut [u8]) {
@@ -399,6 +586,8 @@ impl KeyboardHidHandler {
     pub fn reset(&mut self, hid_io: &dyn HidIo, extended_verification: bool) -> Result<(), efi::Status> {
         self.last_keys.clear();
         self.current_keys.clear();
+        self.debounced_keys.clear();
+        self.repeat_key = None;
         self.key_queue.reset(extended_verification);
         if extended_verification {
             self.update_leds(hid_io)?;
@@ -409,15 +598,70 @@ impl KeyboardHidHandler {
     /// Called to send LED state to the device if there has been a change in LEDs.
     pub fn update_leds(&mut self, hid_io: &dyn HidIo) -> Result<(), efi::Status> {
         for (id, output_report) in self.generate_led_output_reports() {
-            let result = hid_io.set_output_report(id.map(|x| u32::from(x) as u8), &output_report);
-            if let Err(result) = result {
-                debugln!(DEBUG_ERROR, "unexpected error sending output report: {:?}", result);
-                return Err(result);
-            }
+            self.send_led_report(hid_io, id, &output_report, 0)?;
         }
         Ok(())
     }
 
+    // Sends an LED output report to the device. Devices may transiently fail the request (e.g. if busy), so transient
+    // failures are retried from the LED retry timer (rather than stalling the caller, which may be processing a report
+    // at TPL_NOTIFY) a bounded number of times before the failure is reported. `attempt` is the number of attempts
+    // already made to send the report. Only the first of consecutive failures is logged as an error, so that a device
+    // that never accepts output reports does not log on every LED change.
+    fn send_led_report(
+        &mut self,
+        hid_io: &dyn HidIo,
+        id: Option<ReportId>,
+        report: &[u8],
+        attempt: usize,
+    ) -> Result<(), efi::Status> {
+        // a new report supersedes any pending retry of an older one.
+        if attempt == 0 {
+            self.pending_led_reports.remove(&id);
+        }
+        let result = hid_io.set_output_report(id.map(|x| u32::from(x) as u8), report);
+        match result {
+            Err(efi::Status::DEVICE_ERROR) | Err(efi::Status::NOT_READY) | Err(efi::Status::TIMEOUT)
+                if attempt + 1 < LED_REPORT_ATTEMPTS
+                    && !self.led_retry_event.is_null()
+                    && self.start_timer(self.led_retry_event, LED_REPORT_RETRY_DELAY).is_ok() =>
+            {
+                self.pending_led_reports.insert(id, (report.to_vec(), attempt + 1));
+                return Ok(());
+            }
+            Ok(()) => self.led_report_failed = false,
+            Err(status) => {
+                let level = if self.led_report_failed { DEBUG_VERBOSE } else { DEBUG_ERROR };
+                debugln!(level, "{:}: failed to sync LED state: {:?}", function!(), status);
+                self.led_report_failed = true;
+            }
+        }
+        result
+    }
+
+    // Retries the LED output reports that transiently failed once the retry delay has expired.
+    fn send_pending_led_reports(&mut self, hid_io: &dyn HidIo) {
+        for (id, (report, attempt)) in core::mem::take(&mut self.pending_led_reports) {
+            let _ = self.send_led_report(hid_io, id, &report, attempt);
+        }
+    }
+
+    // Handles the LED retry timer: the HidIo instance is only available while a report is being processed, so a
+    // transient instance is opened on the controller to send the pending reports.
+    fn retry_led_reports(&mut self) {
+        let old_tpl = self.boot_services.raise_tpl(efi::TPL_NOTIFY);
+        if let Some(controller) = self.controller {
+            match UefiHidIoFactory::new(self.boot_services, self.agent).new_hid_io(controller, false) {
+                Ok(hid_io) => self.send_pending_led_reports(hid_io.as_ref()),
+                Err(status) => {
+                    debugln!(DEBUG_ERROR, "{:}: failed to open HidIo to sync LED state: {:?}", function!(), status);
+                    self.pending_led_reports.clear();
+                }
+            }
+        }
+        self.boot_services.restore_tpl(old_tpl);
+    }
+
     /// Returns a clone of the keystroke at the front of the keystroke queue.
     pub fn peek_key(&mut self) -> Option<protocols::simple_text_input_ex::KeyData> {
         self.key_queue.peek_key()
@@ -494,6 +738,17 @@ impl KeyboardHidHandler {
         }
     }
 
+    /// Configures whether Caps Lock acts as an additional left Ctrl key instead of toggling Caps Lock. Default is off.
+    pub fn set_caps_lock_as_ctrl(&mut self, enabled: bool) {
+        self.key_queue.set_caps_lock_as_ctrl(enabled);
+    }
+
+    /// Sets the driver configuration that this handler reads its runtime settings (e.g. key repeat) from. By default,
+    /// the global [`crate::HID_CONFIG`] is used.
+    pub fn set_config(&mut self, config: &'static HidConfig) {
+        self.config = config;
+    }
+
     /// Returns the agent associated with this KeyboardHidHandler
     pub fn agent(&self) -> efi::Handle {
         self.agent
@@ -525,6 +780,21 @@ impl HidReportReceiver for KeyboardHidHandler {
         self.process_descriptor(descriptor)?;
         self.install_protocol_interfaces(controller)?;
         self.initialize_keyboard_layout()?;
+
+        // key repeat, debounce and LED report retries are not essential, so the keyboard remains usable if their timers
+        // cannot be created.
+        match self.create_timer_event(on_repeat_timer) {
+            Ok(event) => self.repeat_event = event,
+            Err(status) => debugln!(DEBUG_WARN, "{:?}: Failed to create key repeat event: {:?}", function!(), status),
+        }
+        match self.create_timer_event(on_debounce_timer) {
+            Ok(event) => self.debounce_event = event,
+            Err(status) => debugln!(DEBUG_WARN, "{:?}: Failed to create debounce event: {:?}", function!(), status),
+        }
+        match self.create_timer_event(on_led_retry_timer) {
+            Ok(event) => self.led_retry_event = event,
+            Err(status) => debugln!(DEBUG_WARN, "{:?}: Failed to create LED retry event: {:?}", function!(), status),
+        }
         Ok(())
     }
 
@@ -547,7 +817,22 @@ impl HidReportReceiver for KeyboardHidHandler {
             }
 
             if let Some(report_data) = self.input_reports.get(&report_id).cloned() {
-                if report.len() != report_data.report_size {
+                // held keys cannot be tracked across a change in report format, so release them and start over with the
+                // key state in the new format.
+                let boot_report = self.is_boot_report(&report_data, report);
+                if boot_report != self.boot_protocol {
+                    debugln!(
+                        DEBUG_WARN,
+                        "{:?}: controller {:?} switched to {} protocol, releasing held keys.",
+                        function!(),
+                        self.controller,
+                        if boot_report { "boot" } else { "report" }
+                    );
+                    self.boot_protocol = boot_report;
+                    self.release_held_keys();
+                }
+
+                if report.len() != report_data.report_size && !boot_report {
                     //Some devices report extra bytes in their reports. Warn about this, but try and process anyway.
                     debugln!(
                         DEBUG_VERBOSE,
@@ -558,21 +843,44 @@ impl HidReportReceiver for KeyboardHidHandler {
                         report_data.report_size,
                         report.len()
                     );
-                    debugln!(DEBUG_VERBOSE, "report: {:x?}", report);
+                    if !self.config.secure_input() {
+                        debugln!(DEBUG_VERBOSE, "report: {:x?}", report);
+                    }
                 }
 
                 //reset currently active keys to empty set.
                 self.current_keys.clear();
 
-                // hand the report data to the handler for each relevant field for field-specific processing.
-                for field in report_data.relevant_variable_fields {
-                    (field.report_handler)(self, field.field, report);
+                if boot_report {
+                    self.handle_boot_report(report);
+                } else {
+                    // hand the report data to the handler for each relevant field for field-specific processing.
+                    for field in report_data.relevant_variable_fields {
+                        (field.report_handler)(self, field.field, report);
+                    }
+
+                    for field in report_data.relevant_array_fields {
+                        (field.report_handler)(self, field.field, report);
+                    }
                 }
 
-                for field in report_data.relevant_array_fields {
-                    (field.report_handler)(self, field.field, report);
+                // on rollover the key array does not reflect the actual key state, so keep the last known array keys
+                // until the device reports a valid one. Variable fields (e.g. the modifier byte) are still valid, so
+                // changes to them are processed.
+                if self.current_keys.contains(&Usage::from(KEYBOARD_ERROR_ROLLOVER_USAGE)) {
+                    if !self.config.secure_input() {
+                        debugln!(
+                            DEBUG_VERBOSE,
+                            "{:?}: ignoring key array in rollover report: {:x?}",
+                            function!(),
+                            report
+                        );
+                    }
+                    self.restore_array_keys(report_id, boot_report);
                 }
 
+                self.debounce_released_keys();
+
                 //check if any key state has changed.
                 if self.last_keys != self.current_keys {
                     // process keys that are not in both sets: that is the set of keys that have changed.
@@ -597,9 +905,10 @@ impl HidReportReceiver for KeyboardHidHandler {
                     for key in released_keys {
                         self.key_queue.keystroke(key, key_queue::KeyAction::KeyUp);
                     }
-                    for key in pressed_keys {
-                        self.key_queue.keystroke(key, key_queue::KeyAction::KeyDown);
+                    for key in pressed_keys.iter() {
+                        self.key_queue.keystroke(*key, key_queue::KeyAction::KeyDown);
                     }
+                    self.update_key_repeat(&pressed_keys);
 
                     //after processing all the key strokes, check if any keys were pressed that should trigger the notifier callback
                     //and if so, signal the event to trigger notify processing at the appropriate TPL.
@@ -619,16 +928,21 @@ impl HidReportReceiver for KeyboardHidHandler {
 
         // if any output reports, send them after releasing handler.
         for (id, output_report) in output_reports {
-            let result = hid_io.set_output_report(id.map(|x| u32::from(x) as u8), &output_report);
-            if let Err(result) = result {
-                debugln!(DEBUG_ERROR, "unexpected error sending output report: {:?}", result);
-            }
+            let _ = self.send_led_report(hid_io, id, &output_report, 0);
         }
     }
 }
 
 impl Drop for KeyboardHidHandler {
     fn drop(&mut self) {
+        for event in [self.repeat_event, self.debounce_event, self.led_retry_event] {
+            if !event.is_null() {
+                let status = self.boot_services.close_event(event);
+                if status.is_error() {
+                    debugln!(DEBUG_ERROR, "{:?}: Failed to close timer event: {:?}", function!(), status);
+                }
+            }
+        }
         if let Some(controller) = self.controller {
             if let Err(status) = simple_text_in::SimpleTextInFfi::uninstall(self.boot_services, self.agent, controller)
             {
@@ -646,6 +960,27 @@ impl Drop for KeyboardHidHandler {
     }
 }
 
+// Event callback for the key repeat timer.
+extern "efiapi" fn on_repeat_timer(_event: efi::Event, context: *mut c_void) {
+    if let Some(keyboard_handler) = unsafe { (context as *mut KeyboardHidHandler).as_mut() } {
+        keyboard_handler.repeat_held_key();
+    }
+}
+
+// Event callback for the debounce timer.
+extern "efiapi" fn on_debounce_timer(_event: efi::Event, context: *mut c_void) {
+    if let Some(keyboard_handler) = unsafe { (context as *mut KeyboardHidHandler).as_mut() } {
+        keyboard_handler.release_debounced_keys();
+    }
+}
+
+// Event callback for the LED report retry timer.
+extern "efiapi" fn on_led_retry_timer(_event: efi::Event, context: *mut c_void) {
+    if let Some(keyboard_handler) = unsafe { (context as *mut KeyboardHidHandler).as_mut() } {
+        keyboard_handler.retry_led_reports();
+    }
+}
+
 // handles keyboard layout change event that occurs when a new keyboard layout is set.
 extern "efiapi" fn on_layout_update(_event: efi::Event, context: *mut c_void) {
     let context = unsafe { (context as *mut LayoutChangeContext).as_mut() }.expect("bad context pointer");
@@ -727,16 +1062,26 @@ extern "efiapi" fn on_layout_update(_event: efi::Event, context: *mut c_void) {
 #[cfg(test)]
 mod test {
 
-    use core::{ffi::c_void, mem::MaybeUninit, slice::from_raw_parts_mut};
+    use alloc::collections::BTreeSet;
+    use core::{
+        ffi::c_void,
+        mem::MaybeUninit,
+        slice::from_raw_parts_mut,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
 
+    use hidparser::report_data_types::Usage;
     use hii_keyboard_layout::HiiKeyboardLayout;
     use r_efi::{efi, hii, protocols};
     use scroll::Pwrite;
 
     use crate::{
         boot_services::MockUefiBootServices,
+        config::HidConfig,
         hid_io::{HidReportReceiver, MockHidIo},
-        keyboard::{key_queue::OrdKeyData, on_layout_update, KeyboardHidHandler, LayoutChangeContext},
+        keyboard::{
+            key_queue::OrdKeyData, on_layout_update, KeyboardHidHandler, LayoutChangeContext, LED_REPORT_ATTEMPTS,
+        },
     };
 
     static BOOT_KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
@@ -774,6 +1119,78 @@ mod test {
         0xc0, // END_COLLECTION
     ];
 
+    // report-mode keyboard that places the key slots ahead of the modifier byte, so boot reports do not parse correctly
+    // against it.
+    static KEYS_FIRST_KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x06, // USAGE (Keyboard)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x95, 0x08, //    REPORT_COUNT (8)
+        0x75, 0x08, //    REPORT_SIZE (8)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x26, 0xff, 00, //    LOGICAL_MAXIMUM (255)
+        0x05, 0x07, //    USAGE_PAGE (Key Codes)
+        0x19, 0x00, //    USAGE_MINIMUM (0)
+        0x2a, 0xff, 00, //    USAGE_MAXIMUM (255)
+        0x81, 0x00, //    INPUT (Data, Array)
+        0x75, 0x01, //    REPORT_SIZE (1)
+        0x95, 0x08, //    REPORT_COUNT (8)
+        0x19, 0xE0, //    USAGE_MINIMUM (224)
+        0x29, 0xE7, //    USAGE_MAXIMUM (231)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x01, //    LOGICAL_MAXIMUM (1)
+        0x81, 0x02, //    INPUT (Data, Var, Abs) (Modifier Byte)
+        0xc0, // END_COLLECTION
+    ];
+
+    static NO_RESERVED_BYTE_KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x06, // USAGE (Keyboard)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x75, 0x01, //    REPORT_SIZE (1)
+        0x95, 0x08, //    REPORT_COUNT (8)
+        0x05, 0x07, //    USAGE_PAGE (Key Codes)
+        0x19, 0xE0, //    USAGE_MINIMUM (224)
+        0x29, 0xE7, //    USAGE_MAXIMUM (231)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x01, //    LOGICAL_MAXIMUM (1)
+        0x81, 0x02, //    INPUT (Data, Var, Abs) (Modifier Byte)
+        0x95, 0x06, //    REPORT_COUNT (6)
+        0x75, 0x08, //    REPORT_SIZE (8)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x65, //    LOGICAL_MAXIMUM (101)
+        0x19, 0x00, //    USAGE_MINIMUM (0)
+        0x29, 0x65, //    USAGE_MAXIMUM (101)
+        0x81, 0x00, //    INPUT (Data, Array)
+        0xc0, // END_COLLECTION
+    ];
+
+    static REPORT_ID_KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x06, // USAGE (Keyboard)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x85, 0x01, //    REPORT_ID (1)
+        0x75, 0x01, //    REPORT_SIZE (1)
+        0x95, 0x08, //    REPORT_COUNT (8)
+        0x05, 0x07, //    USAGE_PAGE (Key Codes)
+        0x19, 0xE0, //    USAGE_MINIMUM (224)
+        0x29, 0xE7, //    USAGE_MAXIMUM (231)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x01, //    LOGICAL_MAXIMUM (1)
+        0x81, 0x02, //    INPUT (Data, Var, Abs) (Modifier Byte)
+        0x95, 0x06, //    REPORT_COUNT (6)
+        0x75, 0x08, //    REPORT_SIZE (8)
+        0x15, 0x00, //    LOGICAL_MINIMUM (0)
+        0x25, 0x65, //    LOGICAL_MAXIMUM (101)
+        0x05, 0x07, //    USAGE_PAGE (Key Codes)
+        0x19, 0x00, //    USAGE_MINIMUM (0)
+        0x29, 0x03, //    USAGE_MAXIMUM (3)
+        0x19, 0x04, //    USAGE_MINIMUM (4)
+        0x29, 0x65, //    USAGE_MAXIMUM (101)
+        0x81, 0x00, //    INPUT (Data, Array)
+        0xc0, // END_COLLECTION
+    ];
+
     static MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
         0x05, 0x01, // USAGE_PAGE (Generic Desktop)
         0x09, 0x02, // USAGE (Mouse)
@@ -813,6 +1230,36 @@ mod test {
         unsafe { Box::into_raw(Box::new(MockUefiBootServices::new())).as_mut().unwrap() }
     }
 
+    // Returns a handler with the default layout, initialized with the given report descriptor, along with the HidIo it
+    // was initialized with. The boot services mock accepts the calls made by initialize() and receive_report(), and the
+    // timers armed for key repeat and debounce (which tests fire by calling the timer handlers directly).
+    fn initialized_keyboard(descriptor: &'static [u8]) -> (KeyboardHidHandler, MockHidIo) {
+        let boot_services = create_fake_static_boot_service();
+        boot_services.expect_create_event().returning(|_, _, _, _, event| {
+            unsafe { event.write(0x100 as efi::Event) };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_set_timer().returning(|_, _, _| efi::Status::SUCCESS);
+        boot_services.expect_close_event().returning(|_| efi::Status::SUCCESS);
+        boot_services.expect_create_event_ex().returning(|_, _, _, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_locate_protocol().returning(|_, _, _| efi::Status::NOT_FOUND);
+        boot_services.expect_signal_event().returning(|_| efi::Status::SUCCESS);
+        boot_services.expect_open_protocol().returning(|_, _, _, _, _, _| efi::Status::NOT_FOUND);
+        boot_services.expect_raise_tpl().returning(|_| efi::TPL_APPLICATION);
+        boot_services.expect_restore_tpl().returning(|_| ());
+
+        let mut keyboard_handler = KeyboardHidHandler::new(boot_services, 1 as efi::Handle);
+        let mut hid_io = MockHidIo::new();
+        hid_io
+            .expect_get_report_descriptor()
+            .returning(move || Ok(hidparser::parse_report_descriptor(descriptor).unwrap()));
+
+        keyboard_handler.key_queue.set_layout(Some(hii_keyboard_layout::get_default_keyboard_layout()));
+        keyboard_handler.initialize(2 as efi::Handle, &hid_io).unwrap();
+        (keyboard_handler, hid_io)
+    }
+
     #[test]
     fn keyboard_initialize_should_fail_for_unsupported_descriptors() {
         let boot_services = create_fake_static_boot_service();
@@ -959,6 +1406,324 @@ mod test {
         }
     }
 
+    #[test]
+    fn keyboard_should_process_input_reports_with_report_id_and_usage_ranges() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(REPORT_ID_KEYBOARD_REPORT_DESCRIPTOR);
+        assert!(keyboard_handler.report_id_present);
+
+        // press the 'a' key - the first byte is the report id, and index 4 is the first usage of the second range.
+        let report: &[u8] = &[0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // release 'a', then press 'b' with an unknown report id - the report should be ignored.
+        let report: &[u8] = &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        let report: &[u8] = &[0x02, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // press 'b' with the correct report id.
+        let report: &[u8] = &[0x01, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'b' as u16);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+    }
+
+    #[test]
+    fn keyboard_should_not_double_count_modifiers_reported_in_modifier_byte_and_array() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(BOOT_KEYBOARD_REPORT_DESCRIPTOR);
+
+        // expose partial keystrokes so that modifier presses are queued.
+        keyboard_handler.key_queue.set_key_toggle_state(protocols::simple_text_input_ex::KEY_STATE_EXPOSED);
+
+        // press left shift, reported in both the modifier byte and the key array: only one keystroke is expected.
+        let report: &[u8] = &[0x02, 0x00, 0xE1, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        let key_data = keyboard_handler.key_queue.pop_key().unwrap();
+        assert_eq!(key_data.key.unicode_char, 0);
+        assert_eq!(
+            key_data.key_state.key_shift_state,
+            protocols::simple_text_input_ex::SHIFT_STATE_VALID | protocols::simple_text_input_ex::LEFT_SHIFT_PRESSED
+        );
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // press 'a' with the duplicated shift still held.
+        let report: &[u8] = &[0x02, 0x00, 0xE1, 0x04, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'A' as u16);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // release everything: the shift release should be processed once, and not produce keystrokes.
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // 'a' is unshifted afterwards.
+        let report: &[u8] = &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+    }
+
+    #[test]
+    fn held_keys_should_repeat_if_key_repeat_is_enabled() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(BOOT_KEYBOARD_REPORT_DESCRIPTOR);
+        // use a dedicated config rather than the global one so that other tests are not affected.
+        let hid_config: &'static HidConfig = Box::leak(Box::new(HidConfig::new()));
+        keyboard_handler.set_config(hid_config);
+
+        // no repeat by default.
+        let report: &[u8] = &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+        keyboard_handler.repeat_held_key();
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+
+        hid_config.set_key_repeat(500, 20).unwrap();
+
+        // a held key repeats each time the timer fires, with the current modifier state.
+        let report: &[u8] = &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+        keyboard_handler.repeat_held_key();
+        keyboard_handler.repeat_held_key();
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+        let report: &[u8] = &[0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        keyboard_handler.repeat_held_key();
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'A' as u16);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // the last key pressed repeats.
+        let report: &[u8] = &[0x00, 0x00, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'b' as u16);
+        keyboard_handler.repeat_held_key();
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'b' as u16);
+
+        // releasing the repeating key stops the repeat, even if other keys are still held.
+        let report: &[u8] = &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        keyboard_handler.repeat_held_key();
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // modifiers do not repeat.
+        let report: &[u8] = &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        keyboard_handler.repeat_held_key();
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+    }
+
+    #[test]
+    fn bouncing_keys_should_not_produce_extra_keystrokes_if_debounce_is_enabled() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(BOOT_KEYBOARD_REPORT_DESCRIPTOR);
+        // use a dedicated config rather than the global one so that other tests are not affected.
+        let hid_config: &'static HidConfig = Box::leak(Box::new(HidConfig::new()));
+        keyboard_handler.set_config(hid_config);
+        hid_config.set_debounce_time(10).unwrap();
+
+        let pressed: &[u8] = &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let released: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+        // a release followed by a press within the debounce time is a bounce, and does not produce a second keystroke.
+        keyboard_handler.receive_report(pressed, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+        keyboard_handler.receive_report(released, &hid_io);
+        keyboard_handler.receive_report(pressed, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // the release is processed once the debounce time expires, after which the next press is a new keystroke.
+        keyboard_handler.receive_report(released, &hid_io);
+        assert!(keyboard_handler.last_keys.contains(&Usage::from(0x00070004)));
+        keyboard_handler.release_debounced_keys();
+        assert!(keyboard_handler.last_keys.is_empty());
+        keyboard_handler.receive_report(pressed, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+    }
+
+    #[test]
+    fn keyboard_should_release_all_keys_on_all_zero_report() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(BOOT_KEYBOARD_REPORT_DESCRIPTOR);
+
+        // hold left shift with 'a', 'b' and 'c' (keys pressed in the same report are processed in reverse usage order).
+        let held: &[u8] = &[0x02, 0x00, 0x04, 0x05, 0x06, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(held, &hid_io);
+        for expected in ['C', 'B', 'A'] {
+            assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, expected as u16);
+        }
+        assert_eq!(keyboard_handler.last_keys.len(), 4);
+
+        // an all-zero report releases every held key.
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.last_keys.is_empty());
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+        assert_eq!(
+            keyboard_handler.key_queue.init_key_state().key_shift_state,
+            protocols::simple_text_input_ex::SHIFT_STATE_VALID
+        );
+
+        // so the same keys are pressed afresh by the next report.
+        keyboard_handler.receive_report(held, &hid_io);
+        for expected in ['C', 'B', 'A'] {
+            assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, expected as u16);
+        }
+    }
+
+    #[test]
+    fn keyboard_should_ignore_rollover_reports() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(BOOT_KEYBOARD_REPORT_DESCRIPTOR);
+
+        // press 'a' and 'b'.
+        let report: &[u8] = &[0x00, 0x00, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'b' as u16);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+
+        // rollover should neither release the held keys nor press any new ones from the key array; shift in the
+        // modifier byte is still applied.
+        let report: &[u8] = &[0x02, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+        assert_eq!(
+            keyboard_handler.last_keys,
+            BTreeSet::from([Usage::from(0x000700E1), Usage::from(0x00070004), Usage::from(0x00070005)])
+        );
+
+        // once the rollover clears, only the difference from the last valid report is processed: 'a' and 'b' remain
+        // held, so only 'c' is pressed.
+        let report: &[u8] = &[0x00, 0x00, 0x04, 0x05, 0x06, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'c' as u16);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+    }
+
+    #[test]
+    fn keyboard_should_process_modifier_changes_in_rollover_reports() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(BOOT_KEYBOARD_REPORT_DESCRIPTOR);
+
+        let key_a = Usage::from(0x00070004);
+        let key_b = Usage::from(0x00070005);
+
+        // hold shift, 'a' and 'b'.
+        let report: &[u8] = &[0x02, 0x00, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'B' as u16);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'A' as u16);
+
+        // shift is released while the keyboard is in rollover: the release is processed, and 'a' and 'b' stay held.
+        let report: &[u8] = &[0x00, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+        assert_eq!(keyboard_handler.last_keys, BTreeSet::from([key_a, key_b]));
+
+        // control is pressed while still in rollover; it applies to the next key pressed once the rollover clears.
+        let report: &[u8] = &[0x01, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.last_keys, BTreeSet::from([Usage::from(0x000700E0), key_a, key_b]));
+
+        let report: &[u8] = &[0x01, 0x00, 0x04, 0x05, 0x06, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        let key = keyboard_handler.key_queue.pop_key().unwrap();
+        assert_eq!(key.key.unicode_char, 'c' as u16);
+        assert_ne!(key.key_state.key_shift_state & protocols::simple_text_input_ex::LEFT_CONTROL_PRESSED, 0);
+        assert_eq!(key.key_state.key_shift_state & protocols::simple_text_input_ex::LEFT_SHIFT_PRESSED, 0);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+    }
+
+    #[test]
+    fn keyboard_should_only_process_changed_keys_while_others_are_held() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(BOOT_KEYBOARD_REPORT_DESCRIPTOR);
+
+        // hold 'a' and 'b'.
+        let report: &[u8] = &[0x00, 0x00, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'b' as u16);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'a' as u16);
+
+        // add 'c' in a different slot: exactly one key down.
+        let report: &[u8] = &[0x00, 0x00, 0x06, 0x04, 0x05, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'c' as u16);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // repeating the same report (with the slots reordered) produces no events.
+        let report: &[u8] = &[0x00, 0x00, 0x05, 0x06, 0x04, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+
+        // release 'c' while 'a' and 'b' remain held: no new key down, and only 'c' is released.
+        let report: &[u8] = &[0x00, 0x00, 0x05, 0x04, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+        assert_eq!(keyboard_handler.last_keys, BTreeSet::from([Usage::from(0x00070004), Usage::from(0x00070005)]));
+    }
+
+    #[test]
+    fn keyboard_should_release_held_keys_on_protocol_change() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(KEYS_FIRST_KEYBOARD_REPORT_DESCRIPTOR);
+
+        let left_shift = Usage::from(0x000700E1);
+        let key_a = Usage::from(0x00070004);
+
+        // hold 'shift' and 'a' in report protocol.
+        let report: &[u8] = &[0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'A' as u16);
+        assert_eq!(keyboard_handler.last_keys, BTreeSet::from([key_a, left_shift]));
+
+        // the device reverts to boot protocol with the same keys held; parsed against the report descriptor, the
+        // modifier byte would be taken as a key. The held keys are released and the boot report is parsed instead.
+        let report: &[u8] = &[0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'A' as u16);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+        assert_eq!(keyboard_handler.last_keys, BTreeSet::from([key_a, left_shift]));
+
+        // releasing the keys in boot protocol leaves nothing stuck.
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+        assert!(keyboard_handler.last_keys.is_empty());
+
+        // hold 'b' in boot protocol, then switch back to report protocol with 'b' released.
+        let report: &[u8] = &[0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'b' as u16);
+
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+        assert!(keyboard_handler.last_keys.is_empty());
+    }
+
+    #[test]
+    fn keyboard_should_parse_padded_reports_with_the_report_descriptor() {
+        let (mut keyboard_handler, hid_io) = initialized_keyboard(NO_RESERVED_BYTE_KEYBOARD_REPORT_DESCRIPTOR);
+
+        let left_shift = Usage::from(0x000700E1);
+        let key_a = Usage::from(0x00070004);
+
+        // the device declares a 7-byte report but pads it to 8 bytes; it should not be mistaken for a boot report,
+        // which would take the first key as the reserved byte.
+        let report: &[u8] = &[0x02, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(keyboard_handler.key_queue.pop_key().unwrap().key.unicode_char, 'A' as u16);
+        assert_eq!(keyboard_handler.last_keys, BTreeSet::from([key_a, left_shift]));
+        assert!(!keyboard_handler.boot_protocol);
+
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert!(keyboard_handler.key_queue.peek_key().is_none());
+        assert!(keyboard_handler.last_keys.is_empty());
+    }
+
     #[test]
     fn keyboard_should_install_layout_if_not_already_present() {
         let boot_services = create_fake_static_boot_service();
@@ -1142,6 +1907,94 @@ mod test {
         assert!(keyboard_handler.led_state.is_empty());
     }
 
+    #[test]
+    fn led_reports_should_be_retried() {
+        let (mut keyboard_handler, _) = initialized_keyboard(BOOT_KEYBOARD_REPORT_DESCRIPTOR);
+
+        // device is busy for the first two attempts, then accepts the report.
+        static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+        let mut hid_io = MockHidIo::new();
+        hid_io.expect_set_output_report().returning(|_, _| {
+            if ATTEMPTS.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(efi::Status::NOT_READY)
+            } else {
+                Ok(())
+            }
+        });
+
+        // press CapsLock to turn on the CapsLock LED. Failed attempts are retried when the retry timer fires (simulated
+        // here by sending the pending reports directly), rather than by stalling report processing.
+        let report: &[u8] = &[0x00, 0x00, 0x39, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 1);
+        assert_eq!(keyboard_handler.pending_led_reports.len(), 1);
+        keyboard_handler.send_pending_led_reports(&hid_io);
+        assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 2);
+        keyboard_handler.send_pending_led_reports(&hid_io);
+        assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 3);
+        assert!(keyboard_handler.pending_led_reports.is_empty());
+        assert!(!keyboard_handler.led_report_failed);
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 3);
+
+        // device fails every attempt - the failure is reported after a bounded number of attempts.
+        static FAILED_ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+        let mut hid_io = MockHidIo::new();
+        hid_io.expect_set_output_report().returning(|_, _| {
+            FAILED_ATTEMPTS.fetch_add(1, Ordering::SeqCst);
+            Err(efi::Status::DEVICE_ERROR)
+        });
+
+        // press CapsLock again to turn off the CapsLock LED.
+        let report: &[u8] = &[0x00, 0x00, 0x39, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        while !keyboard_handler.pending_led_reports.is_empty() {
+            keyboard_handler.send_pending_led_reports(&hid_io);
+        }
+        assert_eq!(FAILED_ATTEMPTS.load(Ordering::SeqCst), LED_REPORT_ATTEMPTS);
+        assert!(keyboard_handler.led_report_failed);
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+
+        // a change in LED state replaces the pending retry of the previous state, so stale state is never sent.
+        static LAST_REPORT: AtomicUsize = AtomicUsize::new(0);
+        let mut hid_io = MockHidIo::new();
+        hid_io.expect_set_output_report().returning(|_, report| {
+            LAST_REPORT.store(report[0] as usize, Ordering::SeqCst);
+            Err(efi::Status::NOT_READY)
+        });
+        let report: &[u8] = &[0x00, 0x00, 0x39, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        let caps_lock_on = LAST_REPORT.load(Ordering::SeqCst);
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        let report: &[u8] = &[0x00, 0x00, 0x39, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        let caps_lock_off = LAST_REPORT.load(Ordering::SeqCst);
+        assert_ne!(caps_lock_on, caps_lock_off);
+        assert_eq!(keyboard_handler.pending_led_reports.len(), 1);
+        keyboard_handler.send_pending_led_reports(&hid_io);
+        assert_eq!(LAST_REPORT.load(Ordering::SeqCst), caps_lock_off);
+        keyboard_handler.pending_led_reports.clear();
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+
+        // device does not support output reports - the failure is not retried.
+        static UNSUPPORTED_ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+        let mut hid_io = MockHidIo::new();
+        hid_io.expect_set_output_report().returning(|_, _| {
+            UNSUPPORTED_ATTEMPTS.fetch_add(1, Ordering::SeqCst);
+            Err(efi::Status::UNSUPPORTED)
+        });
+
+        let report: &[u8] = &[0x00, 0x00, 0x39, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(UNSUPPORTED_ATTEMPTS.load(Ordering::SeqCst), 1);
+        assert!(keyboard_handler.pending_led_reports.is_empty());
+        assert!(keyboard_handler.led_report_failed);
+    }
+
     #[test]
     fn misc_functions_test() {
         let boot_services = create_fake_static_boot_service();
@@ -1340,4 +2193,94 @@ mod test {
         assert!(callback_key_data.is_none());
         assert!(callbacks.is_empty());
     }
+
+    #[test]
+    fn key_notifies_should_fire_only_on_matching_modifiers() {
+        static NOTIFY_SIGNALS: AtomicUsize = AtomicUsize::new(0);
+        let boot_services = create_fake_static_boot_service();
+        boot_services.expect_create_event().returning(|_, _, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_create_event_ex().returning(|_, _, _, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_locate_protocol().returning(|_, _, _| efi::Status::NOT_FOUND);
+        boot_services.expect_signal_event().returning(|_| {
+            NOTIFY_SIGNALS.fetch_add(1, Ordering::SeqCst);
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_open_protocol().returning(|_, _, _, _, _, _| efi::Status::NOT_FOUND);
+        boot_services.expect_raise_tpl().returning(|_| efi::TPL_APPLICATION);
+        boot_services.expect_restore_tpl().returning(|_| ());
+
+        let mut keyboard_handler = KeyboardHidHandler::new(boot_services, 1 as efi::Handle);
+        let mut hid_io = MockHidIo::new();
+        hid_io
+            .expect_get_report_descriptor()
+            .returning(|| Ok(hidparser::parse_report_descriptor(&BOOT_KEYBOARD_REPORT_DESCRIPTOR).unwrap()));
+
+        keyboard_handler.key_queue.set_layout(Some(hii_keyboard_layout::get_default_keyboard_layout()));
+        keyboard_handler.initialize(2 as efi::Handle, &hid_io).unwrap();
+        NOTIFY_SIGNALS.store(0, Ordering::SeqCst);
+
+        extern "efiapi" fn ctrl_b_callback(_key_data: *mut protocols::simple_text_input_ex::KeyData) -> efi::Status {
+            efi::Status::SUCCESS
+        }
+
+        extern "efiapi" fn any_b_callback(_key_data: *mut protocols::simple_text_input_ex::KeyData) -> efi::Status {
+            efi::Status::SUCCESS
+        }
+
+        // register Ctrl+B, and 'b' with zero shift/toggle state (matches 'b' with any modifiers).
+        let mut key_data: protocols::simple_text_input_ex::KeyData = Default::default();
+        key_data.key.unicode_char = 'b' as u16;
+        let any_b_handle = keyboard_handler.insert_key_notify_callback(key_data, any_b_callback);
+        key_data.key_state.key_shift_state =
+            protocols::simple_text_input_ex::SHIFT_STATE_VALID | protocols::simple_text_input_ex::LEFT_CONTROL_PRESSED;
+        keyboard_handler.insert_key_notify_callback(key_data, ctrl_b_callback);
+
+        //press Ctrl+B
+        let report: &[u8] = &[0x01, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(NOTIFY_SIGNALS.load(Ordering::SeqCst), 1);
+
+        let (callback_key_data, callbacks) = keyboard_handler.pending_callbacks();
+        assert_eq!(callback_key_data.unwrap().key.unicode_char, 'b' as u16);
+        assert_eq!(callbacks.len(), 2);
+        assert!(callbacks.contains(
+            &(ctrl_b_callback as extern "efiapi" fn(*mut protocols::simple_text_input_ex::KeyData) -> efi::Status)
+        ));
+        assert!(callbacks.contains(
+            &(any_b_callback as extern "efiapi" fn(*mut protocols::simple_text_input_ex::KeyData) -> efi::Status)
+        ));
+        assert!(keyboard_handler.pending_callbacks().0.is_none());
+
+        //release Ctrl+B - no notify is pending, so the notify event should not be signaled.
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(NOTIFY_SIGNALS.load(Ordering::SeqCst), 1);
+
+        //press Alt+B - only the wildcard registration matches.
+        let report: &[u8] = &[0x04, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(NOTIFY_SIGNALS.load(Ordering::SeqCst), 2);
+
+        let (callback_key_data, callbacks) = keyboard_handler.pending_callbacks();
+        assert_eq!(callback_key_data.unwrap().key.unicode_char, 'b' as u16);
+        assert_eq!(callbacks.len(), 1);
+        assert!(callbacks.contains(
+            &(any_b_callback as extern "efiapi" fn(*mut protocols::simple_text_input_ex::KeyData) -> efi::Status)
+        ));
+
+        //remove the wildcard registration; Alt+B should no longer trigger a notify, and non-matching keys should not
+        //signal the notify event.
+        keyboard_handler.remove_key_notify_callback(any_b_handle).unwrap();
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        let report: &[u8] = &[0x04, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        let report: &[u8] = &[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        keyboard_handler.receive_report(report, &hid_io);
+        assert_eq!(NOTIFY_SIGNALS.load(Ordering::SeqCst), 2);
+        assert!(keyboard_handler.pending_callbacks().0.is_none());
+    }
 }
@@ -13,7 +13,7 @@ use alloc::{
     collections::{BTreeSet, VecDeque},
     vec::Vec,
 };
-use core::{ops::Deref, sync::atomic::Ordering};
+use core::ops::Deref;
 use hidparser::report_data_types::Usage;
 use hii_keyboard_layout::{EfiKey, HiiKey, HiiKeyboardLayout, HiiNsKeyDescriptor};
 use r_efi::{
@@ -135,6 +135,8 @@ pub(crate) struct KeyQueue {
     key_queue: VecDeque<KeyData>,
     registered_keys: BTreeSet<OrdKeyData>,
     notified_key_queue: VecDeque<KeyData>,
+    caps_lock_as_ctrl: bool,
+    caps_ctrl_held: bool,
 }
 
 impl KeyQueue {
@@ -146,6 +148,7 @@ impl KeyQueue {
             let active_leds = self.active_led_modifiers();
             self.active_modifiers.retain(|x| active_leds.contains(x));
         }
+        self.caps_ctrl_held = false;
         self.active_ns_key = None;
         self.partial_key_support_active = false;
         self.key_queue.clear();
@@ -159,6 +162,19 @@ impl KeyQueue {
             return;
         };
 
+        // F13-F24 have no EfiKey in the HII keyboard layout, but are used by newer keyboards for dedicated keys (e.g. the
+        // assistant/Copilot key is reported as LeftShift+LeftGUI+F23), so queue them directly as scan codes.
+        if let Some(scan_code) = usage_to_extended_function_key_scan(key) {
+            if action == KeyAction::KeyDown {
+                let key_data = protocols::simple_text_input_ex::KeyData {
+                    key: InputKey { unicode_char: 0, scan_code },
+                    key_state: self.init_key_state(),
+                };
+                self.enqueue_key(key_data);
+            }
+            return;
+        }
+
         let Some(efi_key) = usage_to_efi_key(key) else {
             //unsupported key usage, nothing to do.
             return;
@@ -197,12 +213,17 @@ impl KeyQueue {
             }
         }
 
-        let Some(current_descriptor) = current_descriptor else {
+        let Some(mut current_descriptor) = current_descriptor else {
             return; //could not find descriptor, nothing to do.
         };
 
-        //handle modifiers that are active as long as they are pressed
-        if KEYBOARD_MODIFIERS.contains(&current_descriptor.modifier) {
+        // if remapped, caps lock acts as an additional left control key rather than toggling caps lock. Its state is
+        // tracked separately from the physical left control key so that releasing one does not release the other.
+        if self.caps_lock_as_ctrl && current_descriptor.modifier == CAPS_LOCK_MODIFIER {
+            current_descriptor.modifier = LEFT_CONTROL_MODIFIER;
+            self.caps_ctrl_held = action == KeyAction::KeyDown;
+        } else if KEYBOARD_MODIFIERS.contains(&current_descriptor.modifier) {
+            //handle modifiers that are active as long as they are pressed
             match action {
                 KeyAction::KeyDown => {
                     self.active_modifiers.insert(current_descriptor.modifier);
@@ -223,13 +244,13 @@ impl KeyQueue {
         }
 
         //handle ctrl-alt-delete
-        if CTRL_MODIFIERS.iter().any(|x| self.active_modifiers.contains(x))
+        if (self.caps_ctrl_held || CTRL_MODIFIERS.iter().any(|x| self.active_modifiers.contains(x)))
             && ALT_MODIFIERS.iter().any(|x| self.active_modifiers.contains(x))
             && current_descriptor.modifier == DELETE_MODIFIER
         {
             debugln!(DEBUG_WARN, "Ctrl-Alt-Del pressed, resetting system.");
-            if let Some(runtime_services) = unsafe { RUNTIME_SERVICES.load(Ordering::SeqCst).as_mut() } {
-                (runtime_services.reset_system)(efi::RESET_WARM, efi::Status::SUCCESS, 0, core::ptr::null_mut());
+            if let Err(status) = RUNTIME_SERVICES.reset_system(efi::RESET_WARM, efi::Status::SUCCESS) {
+                debugln!(DEBUG_WARN, "Ctrl-Alt-Del reset unavailable: {:x?}", status);
             }
             panic!("Reset failed.");
         }
@@ -311,6 +332,11 @@ impl KeyQueue {
             key_data.key_state.key_shift_state &= !(LEFT_SHIFT_PRESSED | RIGHT_SHIFT_PRESSED);
         }
 
+        self.enqueue_key(key_data);
+    }
+
+    // Adds the given key data to the key queue, and to the notify queue if it matches a registered notify key.
+    fn enqueue_key(&mut self, key_data: KeyData) {
         // if a callback has been registered matching this key, enqueue it in the callback queue.
         if self.is_registered_key(key_data) {
             self.notified_key_queue.push_back(key_data);
@@ -338,6 +364,10 @@ impl KeyQueue {
             key_toggle_state |= KEY_STATE_EXPOSED;
         }
 
+        if self.caps_ctrl_held {
+            key_shift_state |= LEFT_CONTROL_PRESSED;
+        }
+
         for modifier in &self.active_modifiers {
             match *modifier {
                 LEFT_CONTROL_MODIFIER => key_shift_state |= LEFT_CONTROL_PRESSED,
@@ -376,7 +406,7 @@ impl KeyQueue {
 
     // returns a copy of the key at the front of the notify queue
     pub(crate) fn peek_notify_key(&self) -> Option<KeyData> {
-        self.key_queue.front().cloned()
+        self.notified_key_queue.front().cloned()
     }
 
     // set the key toggle state. This allows control of scroll/caps/num locks, as well as whether partial key state is
@@ -421,6 +451,12 @@ impl KeyQueue {
         self.layout = new_layout;
     }
 
+    // Sets whether caps lock should act as left control instead of toggling caps lock.
+    pub(crate) fn set_caps_lock_as_ctrl(&mut self, enabled: bool) {
+        self.caps_lock_as_ctrl = enabled;
+        self.caps_ctrl_held &= enabled;
+    }
+
     // Add a registration key for notifications; if a keystroke matches this key data, it will be added to the notify
     // queue in addition to the normal key queue.
     pub(crate) fn add_notify_key(&mut self, key_data: OrdKeyData) {
@@ -575,6 +611,18 @@ const SCAN_F10: u16 = 0x0014;
 const SCAN_F11: u16 = 0x0015;
 const SCAN_F12: u16 = 0x0016;
 const SCAN_ESC: u16 = 0x0017;
+const SCAN_F13: u16 = 0x0068;
+const SCAN_F14: u16 = 0x0069;
+const SCAN_F15: u16 = 0x006A;
+const SCAN_F16: u16 = 0x006B;
+const SCAN_F17: u16 = 0x006C;
+const SCAN_F18: u16 = 0x006D;
+const SCAN_F19: u16 = 0x006E;
+const SCAN_F20: u16 = 0x006F;
+const SCAN_F21: u16 = 0x0070;
+const SCAN_F22: u16 = 0x0071;
+const SCAN_F23: u16 = 0x0072;
+const SCAN_F24: u16 = 0x0073;
 const SCAN_PAUSE: u16 = 0x0048;
 
 // helper routine that converts the given modifier to the corresponding SCAN code
@@ -607,6 +655,25 @@ fn modifier_to_scan(modifier: u16) -> u16 {
     }
 }
 
+// helper routine that converts the given HID usage for F13-F24 to the corresponding SCAN code.
+fn usage_to_extended_function_key_scan(usage: Usage) -> Option<u16> {
+    match usage.into() {
+        0x00070068 => Some(SCAN_F13),
+        0x00070069 => Some(SCAN_F14),
+        0x0007006A => Some(SCAN_F15),
+        0x0007006B => Some(SCAN_F16),
+        0x0007006C => Some(SCAN_F17),
+        0x0007006D => Some(SCAN_F18),
+        0x0007006E => Some(SCAN_F19),
+        0x0007006F => Some(SCAN_F20),
+        0x00070070 => Some(SCAN_F21),
+        0x00070071 => Some(SCAN_F22),
+        0x00070072 => Some(SCAN_F23),
+        0x00070073 => Some(SCAN_F24),
+        _ => None,
+    }
+}
+
 // helper routine that converts the given modifier to the corresponding HID Usage.
 fn modifier_to_led_usage(modifier: u16) -> Option<Usage> {
     match modifier {
@@ -625,11 +692,12 @@ mod test {
     use r_efi::protocols::{
         self,
         hii_database::{
-            AFFECTED_BY_CAPS_LOCK, AFFECTED_BY_STANDARD_SHIFT, NS_KEY_DEPENDENCY_MODIFIER, NS_KEY_MODIFIER,
+            AFFECTED_BY_CAPS_LOCK, AFFECTED_BY_STANDARD_SHIFT, ALT_GR_MODIFIER, NS_KEY_DEPENDENCY_MODIFIER,
+            NS_KEY_MODIFIER,
         },
     };
 
-    use crate::keyboard::key_queue::{OrdKeyData, SCAN_DOWN};
+    use crate::keyboard::key_queue::{OrdKeyData, SCAN_DOWN, SCAN_F23, SCAN_HOME, SCAN_NULL};
 
     use super::KeyQueue;
 
@@ -732,4 +800,277 @@ mod test {
         let stroke = key_queue.pop_key().unwrap();
         assert_eq!(stroke.key.unicode_char, '\u{00E2}' as u16);
     }
+
+    #[test]
+    fn test_assistant_keystroke() {
+        let mut key_queue = KeyQueue::default();
+        key_queue.set_layout(Some(hii_keyboard_layout::get_default_keyboard_layout()));
+
+        // register for notification on the assistant (Copilot) key: LeftShift+LeftGUI+F23.
+        let mut assistant_key: protocols::simple_text_input_ex::KeyData = Default::default();
+        assistant_key.key.scan_code = SCAN_F23;
+        assistant_key.key_state.key_shift_state = protocols::simple_text_input_ex::SHIFT_STATE_VALID
+            | protocols::simple_text_input_ex::LEFT_SHIFT_PRESSED
+            | protocols::simple_text_input_ex::LEFT_LOGO_PRESSED;
+        key_queue.add_notify_key(OrdKeyData(assistant_key));
+
+        let left_shift = Usage::from(0x000700E1);
+        let left_gui = Usage::from(0x000700E3);
+        let f23 = Usage::from(0x00070072);
+
+        key_queue.keystroke(left_shift, super::KeyAction::KeyDown);
+        key_queue.keystroke(left_gui, super::KeyAction::KeyDown);
+        key_queue.keystroke(f23, super::KeyAction::KeyDown);
+        key_queue.keystroke(f23, super::KeyAction::KeyUp);
+        key_queue.keystroke(left_gui, super::KeyAction::KeyUp);
+        key_queue.keystroke(left_shift, super::KeyAction::KeyUp);
+
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key.scan_code, SCAN_F23);
+        assert_eq!(key.key.unicode_char, 0);
+        assert_eq!(key.key_state.key_shift_state, assistant_key.key_state.key_shift_state);
+        assert!(key_queue.pop_key().is_none());
+
+        let notify_key = key_queue.pop_notify_key().unwrap();
+        assert_eq!(notify_key.key.scan_code, SCAN_F23);
+        assert!(key_queue.pop_notify_key().is_none());
+
+        // F23 without modifiers is still surfaced, but does not match the registration.
+        key_queue.keystroke(f23, super::KeyAction::KeyDown);
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key.scan_code, SCAN_F23);
+        assert_eq!(key.key_state.key_shift_state, protocols::simple_text_input_ex::SHIFT_STATE_VALID);
+        assert!(key_queue.pop_notify_key().is_none());
+    }
+
+    #[test]
+    fn test_keypad_num_lock_keystroke() {
+        let mut key_queue = KeyQueue::default();
+        key_queue.set_layout(Some(hii_keyboard_layout::get_default_keyboard_layout()));
+
+        let num_lock = Usage::from(0x00070053);
+        let keypad_seven = Usage::from(0x0007005F);
+
+        fn press(key_queue: &mut KeyQueue, usage: Usage) {
+            key_queue.keystroke(usage, super::KeyAction::KeyDown);
+            key_queue.keystroke(usage, super::KeyAction::KeyUp);
+        }
+
+        // num lock is off by default: keypad 7 is Home.
+        press(&mut key_queue, keypad_seven);
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key.unicode_char, 0);
+        assert_eq!(key.key.scan_code, SCAN_HOME);
+
+        press(&mut key_queue, num_lock);
+        press(&mut key_queue, keypad_seven);
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key.unicode_char, '7' as u16);
+        assert_eq!(key.key.scan_code, SCAN_NULL);
+        assert_ne!(key.key_state.key_toggle_state & protocols::simple_text_input_ex::NUM_LOCK_ACTIVE, 0);
+
+        press(&mut key_queue, num_lock);
+        press(&mut key_queue, keypad_seven);
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key.unicode_char, 0);
+        assert_eq!(key.key.scan_code, SCAN_HOME);
+
+        // keypad operators and enter produce their symbols regardless of num lock.
+        let keypad_symbols = [
+            (Usage::from(0x00070054), '/'),
+            (Usage::from(0x00070055), '*'),
+            (Usage::from(0x00070056), '-'),
+            (Usage::from(0x00070057), '+'),
+            (Usage::from(0x00070058), '\r'),
+        ];
+        for _ in 0..2 {
+            for (usage, symbol) in keypad_symbols {
+                press(&mut key_queue, usage);
+                let key = key_queue.pop_key().unwrap();
+                assert_eq!(key.key.unicode_char, symbol as u16);
+                assert_eq!(key.key.scan_code, SCAN_NULL);
+            }
+            press(&mut key_queue, num_lock);
+        }
+        assert!(key_queue.pop_key().is_none());
+    }
+
+    #[test]
+    fn test_caps_lock_as_ctrl_keystroke() {
+        let mut key_queue = KeyQueue::default();
+        key_queue.set_layout(Some(hii_keyboard_layout::get_default_keyboard_layout()));
+        key_queue.set_caps_lock_as_ctrl(true);
+
+        let caps_lock = Usage::from(0x00070039);
+        let a_key = Usage::from(0x00070004);
+
+        key_queue.keystroke(caps_lock, super::KeyAction::KeyDown);
+        key_queue.keystroke(a_key, super::KeyAction::KeyDown);
+        key_queue.keystroke(a_key, super::KeyAction::KeyUp);
+
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key.unicode_char, 'a' as u16);
+        assert_eq!(
+            key.key_state.key_shift_state,
+            protocols::simple_text_input_ex::SHIFT_STATE_VALID | protocols::simple_text_input_ex::LEFT_CONTROL_PRESSED
+        );
+        assert_eq!(key.key_state.key_toggle_state & protocols::simple_text_input_ex::CAPS_LOCK_ACTIVE, 0);
+        assert!(key_queue.active_leds().is_empty());
+
+        // releasing caps lock releases control, and caps lock remains off.
+        key_queue.keystroke(caps_lock, super::KeyAction::KeyUp);
+        key_queue.keystroke(a_key, super::KeyAction::KeyDown);
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key.unicode_char, 'a' as u16);
+        assert_eq!(key.key_state.key_shift_state, protocols::simple_text_input_ex::SHIFT_STATE_VALID);
+        assert_eq!(key.key_state.key_toggle_state & protocols::simple_text_input_ex::CAPS_LOCK_ACTIVE, 0);
+
+        // with the remap disabled, caps lock toggles as usual.
+        key_queue.set_caps_lock_as_ctrl(false);
+        key_queue.keystroke(caps_lock, super::KeyAction::KeyDown);
+        key_queue.keystroke(caps_lock, super::KeyAction::KeyUp);
+        key_queue.keystroke(a_key, super::KeyAction::KeyDown);
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key.unicode_char, 'A' as u16);
+        assert_ne!(key.key_state.key_toggle_state & protocols::simple_text_input_ex::CAPS_LOCK_ACTIVE, 0);
+        assert!(!key_queue.active_leds().is_empty());
+    }
+
+    #[test]
+    fn test_caps_lock_as_ctrl_should_not_release_physical_ctrl() {
+        let mut key_queue = KeyQueue::default();
+        key_queue.set_layout(Some(hii_keyboard_layout::get_default_keyboard_layout()));
+        key_queue.set_caps_lock_as_ctrl(true);
+
+        let left_ctrl = Usage::from(0x000700E0);
+        let caps_lock = Usage::from(0x00070039);
+        let a_key = Usage::from(0x00070004);
+        let ctrl_shift_state =
+            protocols::simple_text_input_ex::SHIFT_STATE_VALID | protocols::simple_text_input_ex::LEFT_CONTROL_PRESSED;
+
+        // hold left control, then press and release caps lock: control is still held.
+        key_queue.keystroke(left_ctrl, super::KeyAction::KeyDown);
+        key_queue.keystroke(caps_lock, super::KeyAction::KeyDown);
+        key_queue.keystroke(caps_lock, super::KeyAction::KeyUp);
+        key_queue.keystroke(a_key, super::KeyAction::KeyDown);
+        key_queue.keystroke(a_key, super::KeyAction::KeyUp);
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key.unicode_char, 'a' as u16);
+        assert_eq!(key.key_state.key_shift_state, ctrl_shift_state);
+
+        // hold caps lock, then release left control: control is still held.
+        key_queue.keystroke(caps_lock, super::KeyAction::KeyDown);
+        key_queue.keystroke(left_ctrl, super::KeyAction::KeyUp);
+        key_queue.keystroke(a_key, super::KeyAction::KeyDown);
+        key_queue.keystroke(a_key, super::KeyAction::KeyUp);
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key_state.key_shift_state, ctrl_shift_state);
+
+        // releasing caps lock as well releases control.
+        key_queue.keystroke(caps_lock, super::KeyAction::KeyUp);
+        key_queue.keystroke(a_key, super::KeyAction::KeyDown);
+        let key = key_queue.pop_key().unwrap();
+        assert_eq!(key.key_state.key_shift_state, protocols::simple_text_input_ex::SHIFT_STATE_VALID);
+    }
+
+    #[test]
+    fn test_non_ascii_bmp_keystroke() {
+        let mut key_queue = KeyQueue::default();
+
+        let mut layout = hii_keyboard_layout::get_default_keyboard_layout();
+
+        let keys = &mut layout.keys;
+
+        let (index, _) = keys
+            .iter()
+            .enumerate()
+            .find(|(_, element)| if let HiiKey::Key(key) = element { key.key == EfiKey::C1 } else { false })
+            .unwrap();
+
+        // map C1 to a-umlaut (shifted: A-umlaut) and C2 to the euro sign - all outside ASCII, but within the BMP.
+        keys[index] = HiiKey::Key(key_descriptor!(
+            EfiKey::C1,
+            '\u{00E4}',
+            '\u{00C4}',
+            '\0',
+            '\0',
+            0,
+            AFFECTED_BY_STANDARD_SHIFT | AFFECTED_BY_CAPS_LOCK
+        ));
+
+        let (index, _) = keys
+            .iter()
+            .enumerate()
+            .find(|(_, element)| if let HiiKey::Key(key) = element { key.key == EfiKey::C2 } else { false })
+            .unwrap();
+        keys[index] = HiiKey::Key(key_descriptor!(EfiKey::C2, '\u{20AC}', '\u{20AC}', '\0', '\0', 0, 0));
+
+        key_queue.set_layout(Some(layout));
+
+        let key = Usage::from(0x00070004); //C1
+        key_queue.keystroke(key, super::KeyAction::KeyDown);
+        key_queue.keystroke(key, super::KeyAction::KeyUp);
+        assert_eq!(key_queue.pop_key().unwrap().key.unicode_char, '\u{00E4}' as u16);
+
+        let shift = Usage::from(0x000700E1); //left shift
+        key_queue.keystroke(shift, super::KeyAction::KeyDown);
+        key_queue.keystroke(key, super::KeyAction::KeyDown);
+        key_queue.keystroke(key, super::KeyAction::KeyUp);
+        key_queue.keystroke(shift, super::KeyAction::KeyUp);
+        assert_eq!(key_queue.pop_key().unwrap().key.unicode_char, '\u{00C4}' as u16);
+
+        let key = Usage::from(0x00070016); //C2
+        key_queue.keystroke(key, super::KeyAction::KeyDown);
+        key_queue.keystroke(key, super::KeyAction::KeyUp);
+        assert_eq!(key_queue.pop_key().unwrap().key.unicode_char, '\u{20AC}' as u16);
+        assert!(key_queue.peek_key().is_none());
+    }
+
+    #[test]
+    fn test_alternate_layout_alt_gr_keystroke() {
+        let mut key_queue = KeyQueue::default();
+
+        // minimal AZERTY-style changes to the default layout: C1 produces 'q', right alt acts as AltGr, and AltGr+D3
+        // produces the euro sign.
+        let mut layout = hii_keyboard_layout::get_default_keyboard_layout();
+        for descriptor in [
+            key_descriptor!(EfiKey::C1, 'q', 'Q', '\0', '\0', 0, AFFECTED_BY_STANDARD_SHIFT | AFFECTED_BY_CAPS_LOCK),
+            key_descriptor!(EfiKey::A2, '\0', '\0', '\0', '\0', ALT_GR_MODIFIER, 0),
+            key_descriptor!(
+                EfiKey::D3,
+                'e',
+                'E',
+                '\u{20AC}',
+                '\0',
+                0,
+                AFFECTED_BY_STANDARD_SHIFT | AFFECTED_BY_CAPS_LOCK
+            ),
+        ] {
+            let index = layout
+                .keys
+                .iter()
+                .position(|element| matches!(element, HiiKey::Key(key) if key.key == descriptor.key))
+                .unwrap();
+            layout.keys[index] = HiiKey::Key(descriptor);
+        }
+        key_queue.set_layout(Some(layout));
+
+        let key = Usage::from(0x00070004); //C1
+        key_queue.keystroke(key, super::KeyAction::KeyDown);
+        key_queue.keystroke(key, super::KeyAction::KeyUp);
+        assert_eq!(key_queue.pop_key().unwrap().key.unicode_char, 'q' as u16);
+
+        let key = Usage::from(0x00070008); //D3
+        key_queue.keystroke(key, super::KeyAction::KeyDown);
+        key_queue.keystroke(key, super::KeyAction::KeyUp);
+        assert_eq!(key_queue.pop_key().unwrap().key.unicode_char, 'e' as u16);
+
+        let alt_gr = Usage::from(0x000700E6); //right alt
+        key_queue.keystroke(alt_gr, super::KeyAction::KeyDown);
+        key_queue.keystroke(key, super::KeyAction::KeyDown);
+        key_queue.keystroke(key, super::KeyAction::KeyUp);
+        key_queue.keystroke(alt_gr, super::KeyAction::KeyUp);
+        assert_eq!(key_queue.pop_key().unwrap().key.unicode_char, '\u{20AC}' as u16);
+        assert!(key_queue.peek_key().is_none());
+    }
 }
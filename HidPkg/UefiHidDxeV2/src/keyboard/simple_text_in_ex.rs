@@ -905,6 +905,26 @@ mod test {
         assert_eq!(status, efi::Status::SUCCESS);
         assert_eq!(notify_handle as usize, 3);
 
+        //registering the same key/callback again should return the existing handle.
+        notify_handle = ptr::null_mut();
+        let status = SimpleTextInExFfi::simple_text_in_ex_register_key_notify(
+            this,
+            ptr::addr_of_mut!(key_data),
+            key_notify_callback_a_and_b,
+            ptr::addr_of_mut!(notify_handle),
+        );
+        assert_eq!(status, efi::Status::SUCCESS);
+        assert_eq!(notify_handle as usize, 3);
+
+        //registering without a handle pointer should fail.
+        let status = SimpleTextInExFfi::simple_text_in_ex_register_key_notify(
+            this,
+            ptr::addr_of_mut!(key_data),
+            key_notify_callback_a_and_b,
+            ptr::null_mut(),
+        );
+        assert_eq!(status, efi::Status::INVALID_PARAMETER);
+
         //send 'b'
         let report: &[u8] = &[0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00];
         keyboard_handler.receive_report(report, &hid_io);
@@ -937,6 +957,12 @@ mod test {
         let status = SimpleTextInExFfi::simple_text_in_ex_unregister_key_notify(this, 1 as *mut c_void);
         assert_eq!(status, efi::Status::SUCCESS);
 
+        //removing a stale or unknown handle should fail.
+        let status = SimpleTextInExFfi::simple_text_in_ex_unregister_key_notify(this, 1 as *mut c_void);
+        assert_eq!(status, efi::Status::INVALID_PARAMETER);
+        let status = SimpleTextInExFfi::simple_text_in_ex_unregister_key_notify(this, 0x1234 as *mut c_void);
+        assert_eq!(status, efi::Status::INVALID_PARAMETER);
+
         //send 'a'
         let report: &[u8] = &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
         keyboard_handler.receive_report(report, &hid_io);
@@ -24,20 +24,52 @@
 extern crate alloc;
 
 pub mod boot_services;
+pub mod config;
 pub mod driver_binding;
 pub mod hid;
 pub mod hid_io;
 pub mod keyboard;
 pub mod pointer;
-
-use core::{ptr, sync::atomic::AtomicPtr};
-
-use r_efi::efi;
+pub mod runtime_services;
 
 use boot_services::StandardUefiBootServices;
+use config::HidConfig;
+use runtime_services::StandardUefiRuntimeServices;
 
 /// Global instance of UEFI Boot Services.
 pub static BOOT_SERVICES: StandardUefiBootServices = StandardUefiBootServices::new();
 
 /// Global instance of UEFI Runtime Services.
-pub static RUNTIME_SERVICES: AtomicPtr<efi::RuntimeServices> = AtomicPtr::new(ptr::null_mut());
+pub static RUNTIME_SERVICES: StandardUefiRuntimeServices = StandardUefiRuntimeServices::new();
+
+/// Global driver configuration, exposed to other modules via the HID Driver Configuration protocol.
+pub static HID_CONFIG: HidConfig = HidConfig::new();
+
+/// Semantic version of this driver, taken from the crate manifest at build time.
+pub const DRIVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Returns the driver version packed as `major << 32 | minor << 16 | patch` (16 bits per component) so that it can be
+/// carried in fixed-width event payloads.
+pub fn packed_driver_version() -> u64 {
+    let component = |value: &str| value.parse::<u64>().unwrap_or(0) & 0xFFFF;
+    (component(env!("CARGO_PKG_VERSION_MAJOR")) << 32)
+        | (component(env!("CARGO_PKG_VERSION_MINOR")) << 16)
+        | component(env!("CARGO_PKG_VERSION_PATCH"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{packed_driver_version, DRIVER_VERSION};
+
+    #[test]
+    fn packed_driver_version_should_match_compiled_version() {
+        let mut components = DRIVER_VERSION.split(['.', '-', '+']).map(|x| x.parse::<u64>().unwrap());
+        let (major, minor, patch) =
+            (components.next().unwrap(), components.next().unwrap(), components.next().unwrap());
+
+        let packed = packed_driver_version();
+        assert_eq!(packed >> 32, major);
+        assert_eq!((packed >> 16) & 0xFFFF, minor);
+        assert_eq!(packed & 0xFFFF, patch);
+    }
+}
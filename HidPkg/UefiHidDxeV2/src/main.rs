@@ -16,11 +16,11 @@
 mod uefi_entry {
     extern crate alloc;
     use alloc::{boxed::Box, vec::Vec};
-    use core::{panic::PanicInfo, sync::atomic::Ordering};
+    use core::panic::PanicInfo;
 
     use r_efi::{efi, system};
 
-    use rust_advanced_logger_dxe::{debugln, init_debug, DEBUG_ERROR};
+    use rust_advanced_logger_dxe::{debugln, get_debug_level, init_debug, DEBUG_ERROR, DEBUG_INFO, DEBUG_WARN};
     use rust_boot_services_allocator_dxe::GLOBAL_ALLOCATOR;
     use uefi_hid_dxe_v2::{
         boot_services::UefiBootServices,
@@ -28,8 +28,9 @@ mod uefi_entry {
         hid::{HidFactory, HidReceiverFactory},
         hid_io::{HidReportReceiver, UefiHidIoFactory},
         keyboard::KeyboardHidHandler,
+        packed_driver_version,
         pointer::PointerHidHandler,
-        BOOT_SERVICES, RUNTIME_SERVICES,
+        BOOT_SERVICES, DRIVER_VERSION, HID_CONFIG, RUNTIME_SERVICES,
     };
 
     struct UefiReceivers {
@@ -42,7 +43,10 @@ mod uefi_entry {
             _controller: efi::Handle,
         ) -> Result<Vec<Box<dyn HidReportReceiver>>, efi::Status> {
             let mut receivers: Vec<Box<dyn HidReportReceiver>> = Vec::new();
-            receivers.push(Box::new(PointerHidHandler::new(self.boot_services, self.agent)));
+            let mut pointer_handler = PointerHidHandler::new(self.boot_services, self.agent);
+            // the detected polling rate is only logged at DEBUG_INFO, so skip its timer if that level is disabled.
+            pointer_handler.set_polling_rate_detection(get_debug_level() & DEBUG_INFO != 0);
+            receivers.push(Box::new(pointer_handler));
             receivers.push(Box::new(KeyboardHidHandler::new(self.boot_services, self.agent)));
             Ok(receivers)
         }
@@ -57,18 +61,26 @@ mod uefi_entry {
         // and because it mutates/accesses the global BOOT_SERVICES static.
         unsafe {
             BOOT_SERVICES.initialize((*system_table).boot_services);
-            RUNTIME_SERVICES.store((*system_table).runtime_services, Ordering::SeqCst);
+            RUNTIME_SERVICES.initialize((*system_table).runtime_services);
             GLOBAL_ALLOCATOR.init((*system_table).boot_services);
             init_debug((*system_table).boot_services);
         }
 
+        debugln!(DEBUG_INFO, "UefiHidDxeV2 version {} ({:#x}) starting.", DRIVER_VERSION, packed_driver_version());
+
         let hid_io_factory = Box::new(UefiHidIoFactory::new(&BOOT_SERVICES, image_handle));
         let receiver_factory = Box::new(UefiReceivers { boot_services: &BOOT_SERVICES, agent: image_handle });
-        let hid_factory = Box::new(HidFactory::new(hid_io_factory, receiver_factory, image_handle));
+        let mut hid_factory = Box::new(HidFactory::new(hid_io_factory, receiver_factory, image_handle));
+        hid_factory.set_collection_children(true);
 
         let hid_binding = UefiDriverBinding::new(&BOOT_SERVICES, hid_factory, image_handle);
         hid_binding.install().expect("failed to install HID driver binding");
 
+        // runtime configuration is optional; the driver works with the default settings without it.
+        if let Err(status) = HID_CONFIG.install(&BOOT_SERVICES, image_handle) {
+            debugln!(DEBUG_WARN, "Failed to install HID config protocol: {:x?}", status);
+        }
+
         efi::Status::SUCCESS
     }
 
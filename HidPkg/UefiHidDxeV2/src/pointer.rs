@@ -10,11 +10,13 @@
 //! SPDX-License-Identifier: BSD-2-Clause-Patent
 //!
 mod absolute_pointer;
+mod gesture;
 
 use alloc::{
     collections::{BTreeMap, BTreeSet},
     vec::Vec,
 };
+use core::{ffi::c_void, ptr};
 
 use r_efi::{efi, protocols};
 
@@ -22,11 +24,16 @@ use hidparser::{
     report_data_types::{ReportId, Usage},
     ReportDescriptor, ReportField, VariableField,
 };
-use rust_advanced_logger_dxe::{debugln, function, DEBUG_ERROR, DEBUG_VERBOSE};
+use rust_advanced_logger_dxe::{debugln, function, DEBUG_ERROR, DEBUG_INFO, DEBUG_VERBOSE, DEBUG_WARN};
 
-use self::absolute_pointer::PointerContext;
+pub use self::gesture::{PinchCallback, PinchDirection, PinchEvent};
+use self::{
+    absolute_pointer::PointerContext,
+    gesture::{PinchRecognizer, ScrollRecognizer},
+};
 use crate::{
     boot_services::UefiBootServices,
+    config::HidConfig,
     hid_io::{HidIo, HidReportReceiver},
 };
 
@@ -37,13 +44,87 @@ const GENERIC_DESKTOP_Z: u32 = 0x00010032;
 const GENERIC_DESKTOP_WHEEL: u32 = 0x00010038;
 const BUTTON_MIN: u32 = 0x00090001;
 const BUTTON_MAX: u32 = 0x00090020; //Per spec, the Absolute Pointer protocol supports a 32-bit button state field.
+const DIGITIZER_TOUCH_PAD: u32 = 0x000d0005;
 const DIGITIZER_SWITCH_MIN: u32 = 0x000d0042;
+const DIGITIZER_TIP_SWITCH: u32 = 0x000d0042;
 const DIGITIZER_SWITCH_MAX: u32 = 0x000d0046;
+const DIGITIZER_CONTACT_IDENTIFIER: u32 = 0x000d0051;
+const DIGITIZER_CONTACT_COUNT: u32 = 0x000d0054;
 
 // number of points on the X/Y axis for this implementation.
 const AXIS_RESOLUTION: u64 = 1024;
+// center of the default axis range (the initial pointer position).
+#[cfg(test)]
 const CENTER: u64 = AXIS_RESOLUTION / 2;
 
+// resolution, in counts per inch, that relative movement is normalized to. Movement from devices that declare their
+// resolution with a linear physical unit is scaled to this resolution; movement from other devices (e.g. a simple
+// -127..127 mouse) is used as reported, as though the device had this resolution.
+const REFERENCE_COUNTS_PER_INCH: i64 = 400;
+// HID unit codes for length in centimeters (SI linear system) and inches (English linear system).
+const UNIT_CENTIMETER: u32 = 0x11;
+const UNIT_INCH: u32 = 0x13;
+
+// window over which reports are counted to detect the device polling rate (1 second, in 100ns units).
+const POLLING_RATE_WINDOW: u64 = 10_000_000;
+
+/// Maximum number of simultaneous contacts tracked for a multi-touch digitizer.
+pub const MAX_CONTACTS: usize = 10;
+
+/// An active contact on a multi-touch digitizer. X/Y are on the same scale as the Absolute Pointer state.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Contact {
+    pub contact_id: u32,
+    pub x: u64,
+    pub y: u64,
+}
+
+/// The range of values reported through the Absolute Pointer protocol for an axis (e.g. to match the resolution of the
+/// display), and whether absolute input on that axis is inverted (e.g. for a panel that is mounted upside down).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AxisRange {
+    pub min: u64,
+    pub max: u64,
+    pub invert: bool,
+}
+
+impl Default for AxisRange {
+    fn default() -> Self {
+        Self { min: 0, max: AXIS_RESOLUTION, invert: false }
+    }
+}
+
+impl AxisRange {
+    // Returns true if the range is usable: min must not exceed max, and both must be representable as i64.
+    fn is_valid(&self) -> bool {
+        self.min <= self.max && self.max <= i64::MAX as u64
+    }
+
+    // Returns the midpoint of the range.
+    fn center(&self) -> u64 {
+        self.min + (self.max - self.min) / 2
+    }
+
+    // Clamps the given value to the range.
+    fn clamp(&self, value: i64) -> u64 {
+        value.clamp(self.min as i64, self.max as i64) as u64
+    }
+
+    // Projects an absolute value within the logical range of the field onto the range, inverting it if configured.
+    fn project(&self, field: &VariableField, value: i64) -> Option<u64> {
+        let value = match field.field_range() {
+            Some(logical_range) if logical_range != 0 => {
+                let offset = (value as i128).checked_sub(i32::from(field.logical_minimum) as i128)?;
+                let scaled = offset * (self.max - self.min) as i128 / logical_range as i128;
+                self.min as i128 + scaled.clamp(0, (self.max - self.min) as i128)
+            }
+            // a degenerate axis (logical minimum equals logical maximum) cannot be scaled, so pass the value through.
+            _ => self.clamp(value) as i128,
+        } as u64;
+        Some(if self.invert { self.max - (value - self.min) } else { value })
+    }
+}
+
 // Maps a given field to a routine that handles input from it.
 #[derive(Debug, Clone)]
 struct ReportFieldWithHandler {
@@ -51,12 +132,45 @@ struct ReportFieldWithHandler {
     report_handler: fn(&mut PointerHidHandler, field: VariableField, report: &[u8]),
 }
 
+// The fields that describe a single contact within a multi-touch report.
+#[derive(Debug, Clone)]
+struct ContactFields {
+    tip_switch: VariableField,
+    contact_id: VariableField,
+    x: VariableField,
+    y: VariableField,
+}
+
+impl ContactFields {
+    // Returns the position of the given usage in the array passed to `from_fields`, if it is a per-contact usage.
+    fn field_index(usage: u32) -> Option<usize> {
+        match usage {
+            DIGITIZER_TIP_SWITCH => Some(0),
+            DIGITIZER_CONTACT_IDENTIFIER => Some(1),
+            GENERIC_DESKTOP_X => Some(2),
+            GENERIC_DESKTOP_Y => Some(3),
+            _ => None,
+        }
+    }
+
+    // Builds the contact fields if all of them are present.
+    fn from_fields(fields: [Option<VariableField>; 4]) -> Option<Self> {
+        let [Some(tip_switch), Some(contact_id), Some(x), Some(y)] = fields else {
+            return None;
+        };
+        Some(Self { tip_switch, contact_id, x, y })
+    }
+}
+
 // Defines a report and the fields of interest within it.
 #[derive(Debug, Default, Clone)]
 struct PointerReportData {
     report_id: Option<ReportId>,
     report_size: usize,
     relevant_fields: Vec<ReportFieldWithHandler>,
+    tip_switch: Option<VariableField>,
+    contacts: Vec<ContactFields>,
+    contact_count: Option<VariableField>,
 }
 
 /// Pointer HID Handler
@@ -69,6 +183,26 @@ pub struct PointerHidHandler {
     report_id_present: bool,
     state_changed: bool,
     current_state: protocols::absolute_pointer::State,
+    polling_rate_detection: bool,
+    polling_rate_event: efi::Event,
+    report_count: usize,
+    polling_rate: usize,
+    swap_buttons: bool,
+    contacts: [Contact; MAX_CONTACTS],
+    active_contact_count: usize,
+    pending_contact_count: usize,
+    pinch_callback: Option<PinchCallback>,
+    pinch_recognizer: PinchRecognizer,
+    two_finger_scroll: bool,
+    scroll_recognizer: ScrollRecognizer,
+    x_remainder: i64,
+    y_remainder: i64,
+    x_counter: Option<i64>,
+    y_counter: Option<i64>,
+    x_range: AxisRange,
+    y_range: AxisRange,
+    relative_z: bool,
+    config: &'static HidConfig,
 }
 
 impl PointerHidHandler {
@@ -83,6 +217,26 @@ impl PointerHidHandler {
             report_id_present: false,
             state_changed: false,
             current_state: Default::default(),
+            polling_rate_detection: false,
+            polling_rate_event: ptr::null_mut(),
+            report_count: 0,
+            polling_rate: 0,
+            swap_buttons: false,
+            contacts: [Contact::default(); MAX_CONTACTS],
+            active_contact_count: 0,
+            pending_contact_count: 0,
+            pinch_callback: None,
+            pinch_recognizer: PinchRecognizer::default(),
+            two_finger_scroll: false,
+            scroll_recognizer: ScrollRecognizer::default(),
+            x_remainder: 0,
+            y_remainder: 0,
+            x_counter: None,
+            y_counter: None,
+            x_range: AxisRange::default(),
+            y_range: AxisRange::default(),
+            relative_z: false,
+            config: &crate::HID_CONFIG,
         };
         handler.reset_state();
         handler
@@ -125,6 +279,7 @@ impl PointerHidHandler {
                                 ReportFieldWithHandler { field: field.clone(), report_handler: Self::z_axis_handler };
                             report_data.relevant_fields.push(field_handler);
                             self.supported_usages.insert(field.usage);
+                            self.relative_z |= field.attributes.relative;
                         }
                         BUTTON_MIN..=BUTTON_MAX => {
                             let field_handler =
@@ -137,47 +292,203 @@ impl PointerHidHandler {
                                 ReportFieldWithHandler { field: field.clone(), report_handler: Self::button_handler };
                             report_data.relevant_fields.push(field_handler);
                             self.supported_usages.insert(field.usage);
+                            if u32::from(field.usage) == DIGITIZER_TIP_SWITCH {
+                                report_data.tip_switch = Some(field.clone());
+                            }
                         }
                         _ => (), //other usages irrelevant
                     }
                 }
             }
 
-            if !report_data.relevant_fields.is_empty() {
+            // Multi-touch digitizers report several contacts, each with its own tip switch and X/Y. Those fields are
+            // processed per contact rather than by the individual field handlers.
+            report_data.contacts = Self::contact_fields(&report.fields);
+            if !report_data.contacts.is_empty() {
+                report_data.relevant_fields.retain(|field| {
+                    !matches!(
+                        u32::from(field.field.usage),
+                        DIGITIZER_TIP_SWITCH | GENERIC_DESKTOP_X | GENERIC_DESKTOP_Y
+                    )
+                });
+                report_data.tip_switch = None;
+                report_data.contact_count = report.fields.iter().find_map(|field| match field {
+                    ReportField::Variable(field) if u32::from(field.usage) == DIGITIZER_CONTACT_COUNT => {
+                        Some(field.clone())
+                    }
+                    _ => None,
+                });
+
+                // Touch pads scroll with two contacts moving together, which is reported as a relative wheel.
+                let collection = report_data.contacts[0].contact_id.member_of.first();
+                if collection.is_some_and(|collection| u32::from(collection.usage) == DIGITIZER_TOUCH_PAD) {
+                    self.two_finger_scroll = true;
+                    self.relative_z = true;
+                    self.supported_usages.insert(Usage::from(GENERIC_DESKTOP_WHEEL));
+                }
+            } else {
+                Self::remove_duplicate_axes(&mut report_data);
+            }
+
+            if !report_data.relevant_fields.is_empty() || !report_data.contacts.is_empty() {
                 self.input_reports.insert(report_data.report_id, report_data);
             }
         }
         if !self.input_reports.is_empty() {
+            // relative Z starts from the center of the axis range, so the initial state depends on the descriptor.
+            self.reset_state();
             Ok(())
         } else {
             Err(efi::Status::UNSUPPORTED)
         }
     }
 
-    // Helper routine that handles projecting relative and absolute axis reports onto the fixed
-    // absolute report axis that this driver produces.
-    fn resolve_axis(current_value: u64, field: VariableField, report: &[u8]) -> Option<u64> {
+    // Some descriptors declare more than one X or Y usage in a report that is not multi-touch. Only the first of each is
+    // used, so that the position does not depend on how the duplicates would combine.
+    fn remove_duplicate_axes(report_data: &mut PointerReportData) {
+        for (usage, name) in [(GENERIC_DESKTOP_X, "X"), (GENERIC_DESKTOP_Y, "Y")] {
+            let count =
+                report_data.relevant_fields.iter().filter(|field| u32::from(field.field.usage) == usage).count();
+            if count <= 1 {
+                continue;
+            }
+            debugln!(
+                DEBUG_WARN,
+                "{:}: report {:?} declares {:} {:} usages; only the first is used.",
+                function!(),
+                report_data.report_id,
+                count,
+                name
+            );
+            let mut first = true;
+            report_data
+                .relevant_fields
+                .retain(|field| u32::from(field.field.usage) != usage || core::mem::replace(&mut first, false));
+        }
+    }
+
+    // Groups the fields of a multi-touch report into contacts. Each contact has one tip switch, contact identifier, X and
+    // Y field. A usage already seen for the current contact starts the next one. Returns an empty Vec if the report has
+    // no complete contacts (i.e. it is not a multi-touch report).
+    fn contact_fields(fields: &[ReportField]) -> Vec<ContactFields> {
+        let mut contacts = Vec::new();
+        let mut current: [Option<VariableField>; 4] = Default::default();
+        for field in fields {
+            let ReportField::Variable(field) = field else {
+                continue;
+            };
+            let Some(index) = ContactFields::field_index(field.usage.into()) else {
+                continue;
+            };
+            if current[index].is_some() {
+                contacts.extend(ContactFields::from_fields(core::mem::take(&mut current)));
+            }
+            current[index] = Some(field.clone());
+        }
+        contacts.extend(ContactFields::from_fields(current));
+        contacts
+    }
+
+    // Helper routine that handles projecting relative and absolute axis reports onto the absolute report axis range
+    // that this driver produces.
+    fn resolve_axis(current_value: u64, field: VariableField, report: &[u8], range: AxisRange) -> Option<u64> {
         if field.attributes.relative {
             //for relative, just update and clamp the current state.
             let new_value = current_value as i64 + field.field_value(report)?;
-            Some(new_value.clamp(0, AXIS_RESOLUTION as i64) as u64)
+            Some(range.clamp(new_value))
+        } else {
+            //for absolute, project the logical range onto the axis range.
+            range.project(&field, field.field_value(report)?)
+        }
+    }
+
+    // Returns the ratio (numerator, denominator) that converts relative movement in the logical units of the field into
+    // counts at REFERENCE_COUNTS_PER_INCH, or None if no conversion is required. The resolution of the field is derived
+    // from its logical and physical ranges, which is only possible if the field declares a length unit in centimeters
+    // or inches.
+    fn movement_scale(field: &VariableField) -> Option<(i64, i64)> {
+        let physical_range = i32::from(field.physical_maximum?) as i64 - i32::from(field.physical_minimum?) as i64;
+        let logical_range = field.field_range()? as i64;
+        if physical_range <= 0 || logical_range <= 0 {
+            return None;
+        }
+
+        // the physical range in inches is physical_range * 10^exponent (further divided by 2.54 for centimeters), so
+        // the scale is REFERENCE_COUNTS_PER_INCH * physical inches / logical_range.
+        let (numerator, denominator) = match field.unit.map(u32::from)? {
+            UNIT_INCH => (physical_range, logical_range),
+            UNIT_CENTIMETER => (physical_range * 100, logical_range * 254),
+            _ => return None,
+        };
+        let (mut numerator, mut denominator) = (numerator.checked_mul(REFERENCE_COUNTS_PER_INCH)?, denominator);
+
+        // unit exponent is encoded as a 4-bit two's complement value (e.g. 0x0E is -2).
+        let exponent = match field.unit_exponent.map_or(0, i32::from) {
+            exponent @ 0x8..=0xF => exponent - 0x10,
+            exponent => exponent,
+        };
+        if exponent >= 0 {
+            numerator = numerator.checked_mul(10i64.checked_pow(exponent as u32)?)?;
         } else {
-            //for absolute, project onto 0..AXIS_RESOLUTION
-            let mut new_value = field.field_value(report)?;
+            denominator = denominator.checked_mul(10i64.checked_pow(exponent.unsigned_abs())?)?;
+        }
 
-            //translate to zero.
-            new_value = new_value.checked_sub(i32::from(field.logical_minimum) as i64)?;
+        (numerator != denominator).then_some((numerator, denominator))
+    }
 
-            //scale to AXIS_RESOLUTION
-            new_value = (new_value * AXIS_RESOLUTION as i64 * 1000) / (field.field_range()? as i64 * 1000);
+    // Returns the movement reported by an absolute counter field (an absolute field with the wrap attribute) as the
+    // difference from the `previous` counter value. A difference of more than half the logical range is taken to have
+    // wrapped around. The first value only establishes the baseline.
+    fn counter_delta(field: &VariableField, report: &[u8], previous: &mut Option<i64>) -> Option<i64> {
+        let value = field.field_value(report)?;
+        let range = field.field_range()? as i64 + 1;
+        let Some(previous) = previous.replace(value) else {
+            return Some(0);
+        };
+        let delta = (value - previous).rem_euclid(range);
+        Some(if delta > range / 2 { delta - range } else { delta })
+    }
 
-            Some(new_value.clamp(0, AXIS_RESOLUTION as i64) as u64)
+    // Helper routine that applies relative X/Y movement to the current value, normalized to REFERENCE_COUNTS_PER_INCH
+    // so that cursor speed does not depend on the resolution of the device, and then scaled by `scale_percent`.
+    // `remainder` carries movement smaller than one scaled unit over to the next report. Absolute counters are
+    // differenced against `counter` to derive the movement; other absolute inputs are handled by resolve_axis.
+    fn resolve_movement(
+        current_value: u64,
+        field: VariableField,
+        report: &[u8],
+        remainder: &mut i64,
+        counter: &mut Option<i64>,
+        range: AxisRange,
+        scale_percent: u32,
+    ) -> Option<u64> {
+        let mut movement = match (field.attributes.relative, field.attributes.wrap) {
+            (true, _) => field.field_value(report)?,
+            (false, true) => Self::counter_delta(&field, report, counter)?,
+            (false, false) => return Self::resolve_axis(current_value, field, report, range),
+        };
+        let (numerator, denominator) = Self::movement_scale(&field).unwrap_or((1, 1));
+        let (numerator, denominator) = (numerator.checked_mul(scale_percent as i64)?, denominator.checked_mul(100)?);
+        if numerator != denominator {
+            let scaled = movement.checked_mul(numerator)?.checked_add(*remainder)?;
+            *remainder = scaled % denominator;
+            movement = scaled / denominator;
         }
+        let new_value = current_value as i64 + movement;
+        Some(range.clamp(new_value))
     }
 
     // handles x_axis inputs
     fn x_axis_handler(&mut self, field: VariableField, report: &[u8]) {
-        if let Some(x_value) = Self::resolve_axis(self.current_state.current_x, field, report) {
+        if let Some(x_value) = Self::resolve_movement(
+            self.current_state.current_x,
+            field,
+            report,
+            &mut self.x_remainder,
+            &mut self.x_counter,
+            self.x_range,
+            self.config.pointer_scale(),
+        ) {
             if self.current_state.current_x != x_value {
                 self.current_state.current_x = x_value;
                 self.state_changed = true;
@@ -187,7 +498,15 @@ impl PointerHidHandler {
 
     // handles y_axis inputs
     fn y_axis_handler(&mut self, field: VariableField, report: &[u8]) {
-        if let Some(y_value) = Self::resolve_axis(self.current_state.current_y, field, report) {
+        if let Some(y_value) = Self::resolve_movement(
+            self.current_state.current_y,
+            field,
+            report,
+            &mut self.y_remainder,
+            &mut self.y_counter,
+            self.y_range,
+            self.config.pointer_scale(),
+        ) {
             if self.current_state.current_y != y_value {
                 self.current_state.current_y = y_value;
                 self.state_changed = true;
@@ -197,7 +516,13 @@ impl PointerHidHandler {
 
     // handles z_axis inputs
     fn z_axis_handler(&mut self, field: VariableField, report: &[u8]) {
-        if let Some(z_value) = Self::resolve_axis(self.current_state.current_z, field, report) {
+        if field.attributes.relative {
+            if let Some(delta) = field.field_value(report) {
+                self.scroll(delta);
+            }
+        } else if let Some(z_value) =
+            Self::resolve_axis(self.current_state.current_z, field, report, AxisRange::default())
+        {
             if self.current_state.current_z != z_value {
                 self.current_state.current_z = z_value;
                 self.state_changed = true;
@@ -205,9 +530,54 @@ impl PointerHidHandler {
         }
     }
 
+    // Applies relative Z movement (e.g. from a wheel or a two-finger scroll). Relative Z starts from the center of the
+    // axis range and wraps around at its ends, rather than clamping, so that scrolling in either direction always
+    // changes the state.
+    fn scroll(&mut self, delta: i64) {
+        let z_value = (self.current_state.current_z as i64 + delta).rem_euclid(AXIS_RESOLUTION as i64 + 1) as u64;
+        if self.current_state.current_z != z_value {
+            self.current_state.current_z = z_value;
+            self.state_changed = true;
+        }
+    }
+
+    /// Configures the range of X and Y values reported through the Absolute Pointer protocol (by default, 0 to 1024),
+    /// and whether absolute input on each axis is inverted. Absolute input is scaled from the logical range declared
+    /// by the device onto the configured range. Must be called before the handler is initialized so that the published
+    /// Absolute Pointer mode matches. Returns `INVALID_PARAMETER` if the minimum of either range exceeds its maximum.
+    pub fn set_axis_ranges(&mut self, x_range: AxisRange, y_range: AxisRange) -> Result<(), efi::Status> {
+        if !x_range.is_valid() || !y_range.is_valid() {
+            Err(efi::Status::INVALID_PARAMETER)?;
+        }
+        self.x_range = x_range;
+        self.y_range = y_range;
+        self.reset_state();
+        Ok(())
+    }
+
+    /// Sets the driver configuration that this handler reads its runtime settings (e.g. pointer scale) from. By default,
+    /// the global [`crate::HID_CONFIG`] is used.
+    pub fn set_config(&mut self, config: &'static HidConfig) {
+        self.config = config;
+    }
+
+    /// Configures whether the polling rate of the device is detected and logged at DEBUG_INFO. Detection runs a
+    /// periodic timer for the life of the handler, so it is disabled by default. Must be called before the handler is
+    /// initialized.
+    pub fn set_polling_rate_detection(&mut self, enabled: bool) {
+        self.polling_rate_detection = enabled;
+    }
+
+    /// Configures whether the primary (left) and secondary (right) buttons are swapped, e.g. for left-handed users.
+    pub fn set_button_swap(&mut self, swap_buttons: bool) {
+        self.swap_buttons = swap_buttons;
+    }
+
     // handles button inputs
     fn button_handler(&mut self, field: VariableField, report: &[u8]) {
         let shift = match field.usage.into() {
+            // Button 1 and Button 2 occupy bits 0 and 1; flipping the low bit exchanges them.
+            x @ BUTTON_MIN..=BUTTON_MAX if self.swap_buttons && x <= BUTTON_MIN + 1 => (x - BUTTON_MIN) ^ 1,
             x @ BUTTON_MIN..=BUTTON_MAX => x - BUTTON_MIN,
             x @ DIGITIZER_SWITCH_MIN..=DIGITIZER_SWITCH_MAX => x - DIGITIZER_SWITCH_MIN,
             _ => return,
@@ -232,12 +602,161 @@ impl PointerHidHandler {
         }
     }
 
+    /// Returns the active contacts of a multi-touch digitizer in the order they went down. The first is the primary
+    /// contact, which is the one reported through the Absolute Pointer protocol.
+    pub fn active_contacts(&self) -> &[Contact] {
+        &self.contacts[..self.active_contact_count]
+    }
+
+    /// Sets a callback to receive pinch gestures recognized from multi-touch contacts, or `None` to disable pinch
+    /// recognition (the default). Raw contacts remain available from [`Self::active_contacts`].
+    pub fn set_pinch_callback(&mut self, callback: Option<PinchCallback>) {
+        self.pinch_callback = callback;
+        self.pinch_recognizer = PinchRecognizer::default();
+    }
+
+    // Updates the tracked contacts from a multi-touch report, and then updates the pointer state from the primary
+    // contact (or, for a two-finger scroll on a touch pad, the Z axis).
+    fn contact_handler(&mut self, contacts: &[ContactFields], contact_count: Option<&VariableField>, report: &[u8]) {
+        // In hybrid mode, devices with more contacts than fit in a single report send the total contact count in the
+        // first report of a frame and zero in the remaining reports. Only that many contacts carry valid data.
+        let mut valid_contacts = contacts.len();
+        if let Some(contact_count) = contact_count {
+            let count = contact_count.field_value(report).unwrap_or(0).max(0) as usize;
+            if count != 0 {
+                self.pending_contact_count = count;
+            }
+            valid_contacts = valid_contacts.min(self.pending_contact_count);
+            self.pending_contact_count -= valid_contacts;
+        }
+
+        for fields in contacts.iter().take(valid_contacts) {
+            let (Some(contact_id), Some(tip_switch)) =
+                (fields.contact_id.field_value(report), fields.tip_switch.field_value(report))
+            else {
+                continue;
+            };
+            let contact_id = contact_id as u32;
+            let active_contacts = self.active_contact_count;
+            let index = self.contacts[..active_contacts].iter().position(|contact| contact.contact_id == contact_id);
+
+            match (tip_switch != 0, index) {
+                (true, Some(index)) => {
+                    let contact = &mut self.contacts[index];
+                    contact.x =
+                        Self::resolve_axis(contact.x, fields.x.clone(), report, self.x_range).unwrap_or(contact.x);
+                    contact.y =
+                        Self::resolve_axis(contact.y, fields.y.clone(), report, self.y_range).unwrap_or(contact.y);
+                }
+                (true, None) if active_contacts < MAX_CONTACTS => {
+                    let x = Self::resolve_axis(self.x_range.center(), fields.x.clone(), report, self.x_range);
+                    let y = Self::resolve_axis(self.y_range.center(), fields.y.clone(), report, self.y_range);
+                    if let (Some(x), Some(y)) = (x, y) {
+                        self.contacts[active_contacts] = Contact { contact_id, x, y };
+                        self.active_contact_count += 1;
+                    }
+                }
+                (true, None) => {
+                    debugln!(DEBUG_VERBOSE, "{:}: ignoring contact {:} beyond maximum", function!(), contact_id);
+                }
+                (false, Some(index)) => {
+                    self.contacts.copy_within(index + 1..active_contacts, index);
+                    self.active_contact_count -= 1;
+                }
+                (false, None) => (), // contact-up for a contact that was never down; nothing to do.
+            }
+        }
+
+        if let Some(pinch_callback) = self.pinch_callback {
+            if let Some(pinch) = self.pinch_recognizer.update(&self.contacts[..self.active_contact_count]) {
+                pinch_callback(pinch);
+            }
+        }
+
+        // on a touch pad, two contacts moving together scroll instead of moving the pointer.
+        if self.two_finger_scroll {
+            if let Some(steps) = self.scroll_recognizer.update(&self.contacts[..self.active_contact_count]) {
+                self.scroll(steps);
+                return;
+            }
+        }
+
+        // the primary contact drives the absolute pointer position and tip switch state.
+        let (x, y, tip_switch) = match self.active_contacts().first() {
+            Some(primary) => (primary.x, primary.y, 1),
+            None => (self.current_state.current_x, self.current_state.current_y, 0),
+        };
+        let tip_switch_bit = 1 << (DIGITIZER_TIP_SWITCH - DIGITIZER_SWITCH_MIN);
+        let buttons = (self.current_state.active_buttons & !tip_switch_bit) | (tip_switch * tip_switch_bit);
+        if (x, y, buttons)
+            != (self.current_state.current_x, self.current_state.current_y, self.current_state.active_buttons)
+        {
+            self.current_state.current_x = x;
+            self.current_state.current_y = y;
+            self.current_state.active_buttons = buttons;
+            self.state_changed = true;
+        }
+    }
+
+    // Installs a periodic timer that samples the number of reports received in each window to detect the polling rate.
+    fn install_polling_rate_event(&mut self) -> Result<(), efi::Status> {
+        let mut polling_rate_event: efi::Event = ptr::null_mut();
+        let status = self.boot_services.create_event(
+            efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL,
+            efi::TPL_CALLBACK,
+            Some(on_polling_rate_timer),
+            self as *mut Self as *mut c_void,
+            ptr::addr_of_mut!(polling_rate_event),
+        );
+        if status.is_error() {
+            Err(status)?;
+        }
+
+        let status = self.boot_services.set_timer(polling_rate_event, efi::TIMER_PERIODIC, POLLING_RATE_WINDOW);
+        if status.is_error() {
+            let _ = self.boot_services.close_event(polling_rate_event);
+            Err(status)?;
+        }
+
+        self.polling_rate_event = polling_rate_event;
+        Ok(())
+    }
+
+    // Samples the number of reports received in the last window. Devices typically only send reports while in use, so
+    // the highest observed rate is treated as the polling rate of the device.
+    fn sample_polling_rate(&mut self) {
+        let old_tpl = self.boot_services.raise_tpl(efi::TPL_NOTIFY);
+        let report_count = core::mem::take(&mut self.report_count);
+        self.boot_services.restore_tpl(old_tpl);
+
+        // the window is one second, so the report count is the rate in Hz.
+        if report_count > self.polling_rate {
+            self.polling_rate = report_count;
+            debugln!(
+                DEBUG_INFO,
+                "{:}: detected polling rate of {:}Hz for controller {:?}",
+                function!(),
+                report_count,
+                self.controller
+            );
+        }
+    }
+
     fn reset_state(&mut self) {
         self.current_state = Default::default();
         // initialize pointer to center of screen
-        self.current_state.current_x = CENTER;
-        self.current_state.current_y = CENTER;
+        self.current_state.current_x = self.x_range.center();
+        self.current_state.current_y = self.y_range.center();
+        if self.relative_z {
+            self.current_state.current_z = AxisRange::default().center();
+        }
         self.state_changed = false;
+        self.active_contact_count = 0;
+        self.pending_contact_count = 0;
+        self.x_remainder = 0;
+        self.y_remainder = 0;
+        self.x_counter = None;
+        self.y_counter = None;
     }
 }
 
@@ -250,6 +769,13 @@ impl HidReportReceiver for PointerHidHandler {
 
         self.controller = Some(controller);
 
+        // the detected polling rate is only logged, so the device remains usable if the timer cannot be installed.
+        if self.polling_rate_detection {
+            if let Err(status) = self.install_polling_rate_event() {
+                debugln!(DEBUG_WARN, "{:?}: Failed to install polling rate event: {:?}", function!(), status);
+            }
+        }
+
         Ok(())
     }
     fn receive_report(&mut self, report: &[u8], _hid_io: &dyn HidIo) {
@@ -271,6 +797,8 @@ impl HidReportReceiver for PointerHidHandler {
             }
 
             if let Some(report_data) = self.input_reports.get(&report_id).cloned() {
+                self.report_count += 1;
+
                 if report.len() != report_data.report_size {
                     //Some devices report extra bytes in their reports. Warn about this, but try and process anyway.
                     debugln!(
@@ -286,10 +814,22 @@ impl HidReportReceiver for PointerHidHandler {
                     //break 'report_processing;
                 }
 
+                // For digitizers that report a tip switch (e.g. resistive touch panels), X/Y are only valid while the tip
+                // switch is active. Otherwise they are noise and must be ignored.
+                let tip_inactive =
+                    report_data.tip_switch.as_ref().is_some_and(|tip| tip.field_value(report).unwrap_or(0) == 0);
+
                 // hand the report data to the handler for each relevant field for field-specific processing.
                 for field in report_data.relevant_fields {
+                    if tip_inactive && matches!(u32::from(field.field.usage), GENERIC_DESKTOP_X | GENERIC_DESKTOP_Y) {
+                        continue;
+                    }
                     (field.report_handler)(self, field.field, report);
                 }
+
+                if !report_data.contacts.is_empty() {
+                    self.contact_handler(&report_data.contacts, report_data.contact_count.as_ref(), report);
+                }
             }
         }
 
@@ -299,6 +839,12 @@ impl HidReportReceiver for PointerHidHandler {
 
 impl Drop for PointerHidHandler {
     fn drop(&mut self) {
+        if !self.polling_rate_event.is_null() {
+            let status = self.boot_services.close_event(self.polling_rate_event);
+            if status.is_error() {
+                debugln!(DEBUG_ERROR, "{:?}: Failed to close polling rate event: {:?}", function!(), status);
+            }
+        }
         if let Some(controller) = self.controller {
             let status = PointerContext::uninstall(self.boot_services, self.agent, controller);
             if status.is_err() {
@@ -308,18 +854,34 @@ impl Drop for PointerHidHandler {
     }
 }
 
+// Event callback for the polling rate timer.
+extern "efiapi" fn on_polling_rate_timer(_event: efi::Event, context: *mut c_void) {
+    if let Some(pointer_handler) = unsafe { (context as *mut PointerHidHandler).as_mut() } {
+        pointer_handler.sample_polling_rate();
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use core::{cmp::min, ffi::c_void};
+    use core::{
+        cmp::min,
+        ffi::c_void,
+        sync::atomic::{AtomicPtr, Ordering},
+    };
 
     use crate::{
         boot_services::MockUefiBootServices,
+        config::{self, HidConfig},
         hid_io::{HidReportReceiver, MockHidIo},
-        pointer::{AXIS_RESOLUTION, CENTER},
+        pointer::{
+            Contact, PinchDirection, PinchEvent, AXIS_RESOLUTION, CENTER, GENERIC_DESKTOP_WHEEL, GENERIC_DESKTOP_X,
+            MAX_CONTACTS, POLLING_RATE_WINDOW,
+        },
     };
-    use r_efi::efi;
+    use hidparser::report_data_types::Usage;
+    use r_efi::{efi, protocols};
 
-    use super::PointerHidHandler;
+    use super::{AxisRange, PointerHidHandler};
 
     static MINIMAL_BOOT_KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
         0x05, 0x01, // USAGE_PAGE (Generic Desktop)
@@ -366,6 +928,41 @@ mod test {
         0xc0, // END_COLLECTION
     ];
 
+    static THREE_BUTTON_WHEEL_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x02, // USAGE (Mouse)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x09, 0x01, //   USAGE(Pointer)
+        0xa1, 0x00, //   COLLECTION (Physical)
+        0x05, 0x09, //     USAGE_PAGE (Button)
+        0x19, 0x01, //     USAGE_MINIMUM(1)
+        0x29, 0x03, //     USAGE_MAXIMUM(3)
+        0x15, 0x00, //     LOGICAL_MINIMUM(0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM(1)
+        0x95, 0x03, //     REPORT_COUNT(3)
+        0x75, 0x01, //     REPORT_SIZE(1)
+        0x81, 0x02, //     INPUT(Data, Variable, Absolute)
+        0x95, 0x01, //     REPORT_COUNT(1)
+        0x75, 0x05, //     REPORT_SIZE(5)
+        0x81, 0x01, //     INPUT(Constant, Array, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x15, 0x81, //     LOGICAL_MINIMUM (-127)
+        0x25, 0x7f, //     LOGICAL_MAXIMUM (127)
+        0x75, 0x08, //     REPORT_SIZE (8)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x06, //     INPUT(Data, Variable, Relative)
+        0x09, 0x38, //     USAGE (Wheel)
+        0x15, 0x81, //     LOGICAL_MINIMUM (-127)
+        0x25, 0x7f, //     LOGICAL_MAXIMUM (127)
+        0x75, 0x08, //     REPORT_SIZE (8)
+        0x95, 0x01, //     REPORT_COUNT (1)
+        0x81, 0x06, //     INPUT(Data, Variable, Relative)
+        0xc0, //   END_COLLECTION
+        0xc0, // END_COLLECTION
+    ];
+
     static ABS_POINTER_REPORT_DESCRIPTOR: &[u8] = &[
         0x05, 0x01, // USAGE_PAGE (Generic Desktop)
         0x09, 0x02, // USAGE (Mouse)
@@ -396,34 +993,394 @@ mod test {
         0xc0, // END_COLLECTION
     ];
 
-    // In this module, the usage model for boot_services is global static, and so &'static dyn UefiBootServices is used
-    // throughout the API. For testing, each test will have a different set of expectations on the UefiBootServices mock
-    // object, and the mock object itself expects to be "mut", which makes it hard to handle as a single global static.
-    // Instead, raw pointers are used to simulate a MockUefiBootServices instance with 'static lifetime.
-    // This object needs to outlive anything that uses it - once created, it will live until the end of the program.
-    fn create_fake_static_boot_service() -> &'static mut MockUefiBootServices {
-        unsafe { Box::into_raw(Box::new(MockUefiBootServices::new())).as_mut().unwrap() }
-    }
+    static TOUCH_PANEL_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x0d, // USAGE_PAGE (Digitizers)
+        0x09, 0x04, // USAGE (Touch Screen)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x09, 0x22, //   USAGE (Finger)
+        0xa1, 0x02, //   COLLECTION (Logical)
+        0x09, 0x42, //     USAGE (Tip Switch)
+        0x15, 0x00, //     LOGICAL_MINIMUM (0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM (1)
+        0x75, 0x01, //     REPORT_SIZE (1)
+        0x95, 0x01, //     REPORT_COUNT (1)
+        0x81, 0x02, //     INPUT (Data, Variable, Absolute)
+        0x75, 0x07, //     REPORT_SIZE (7)
+        0x81, 0x01, //     INPUT (Constant, Array, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x15, 0x00, //     LOGICAL_MINIMUM (0)
+        0x26, 0xff, 0x0f, // LOGICAL_MAXIMUM (4095)
+        0x75, 0x10, //     REPORT_SIZE (16)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x02, //     INPUT (Data, Variable, Absolute)
+        0xc0, //   END_COLLECTION
+        0xc0, // END_COLLECTION
+    ];
 
-    #[test]
-    fn pointer_initialize_should_fail_if_report_descriptor_not_supported() {
-        let boot_services = create_fake_static_boot_service();
+    static MULTI_TOUCH_PANEL_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x0d, // USAGE_PAGE (Digitizers)
+        0x09, 0x04, // USAGE (Touch Screen)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x85, 0x01, //   REPORT_ID (1)
+        0x09, 0x22, //   USAGE (Finger)
+        0xa1, 0x02, //   COLLECTION (Logical)
+        0x09, 0x42, //     USAGE (Tip Switch)
+        0x15, 0x00, //     LOGICAL_MINIMUM (0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM (1)
+        0x75, 0x01, //     REPORT_SIZE (1)
+        0x95, 0x01, //     REPORT_COUNT (1)
+        0x81, 0x02, //     INPUT (Data, Variable, Absolute)
+        0x75, 0x07, //     REPORT_SIZE (7)
+        0x81, 0x01, //     INPUT (Constant, Array, Absolute)
+        0x09, 0x51, //     USAGE (Contact Identifier)
+        0x25, 0x7f, //     LOGICAL_MAXIMUM (127)
+        0x75, 0x08, //     REPORT_SIZE (8)
+        0x81, 0x02, //     INPUT (Data, Variable, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x26, 0xff, 0x0f, // LOGICAL_MAXIMUM (4095)
+        0x75, 0x10, //     REPORT_SIZE (16)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x02, //     INPUT (Data, Variable, Absolute)
+        0x05, 0x0d, //     USAGE_PAGE (Digitizers)
+        0xc0, //   END_COLLECTION
+        0x09, 0x22, //   USAGE (Finger)
+        0xa1, 0x02, //   COLLECTION (Logical)
+        0x09, 0x42, //     USAGE (Tip Switch)
+        0x15, 0x00, //     LOGICAL_MINIMUM (0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM (1)
+        0x75, 0x01, //     REPORT_SIZE (1)
+        0x95, 0x01, //     REPORT_COUNT (1)
+        0x81, 0x02, //     INPUT (Data, Variable, Absolute)
+        0x75, 0x07, //     REPORT_SIZE (7)
+        0x81, 0x01, //     INPUT (Constant, Array, Absolute)
+        0x09, 0x51, //     USAGE (Contact Identifier)
+        0x25, 0x7f, //     LOGICAL_MAXIMUM (127)
+        0x75, 0x08, //     REPORT_SIZE (8)
+        0x81, 0x02, //     INPUT (Data, Variable, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x26, 0xff, 0x0f, // LOGICAL_MAXIMUM (4095)
+        0x75, 0x10, //     REPORT_SIZE (16)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x02, //     INPUT (Data, Variable, Absolute)
+        0x05, 0x0d, //     USAGE_PAGE (Digitizers)
+        0xc0, //   END_COLLECTION
+        0x09, 0x54, //   USAGE (Contact Count)
+        0x25, 0x0a, //   LOGICAL_MAXIMUM (10)
+        0x75, 0x08, //   REPORT_SIZE (8)
+        0x95, 0x01, //   REPORT_COUNT (1)
+        0x81, 0x02, //   INPUT (Data, Variable, Absolute)
+        0xc0, // END_COLLECTION
+    ];
 
-        let agent = 0x1 as efi::Handle;
-        let mut pointer_handler = PointerHidHandler::new(boot_services, agent);
-        let mut hid_io = MockHidIo::new();
-        hid_io
-            .expect_get_report_descriptor()
-            .returning(|| Ok(hidparser::parse_report_descriptor(&MINIMAL_BOOT_KEYBOARD_REPORT_DESCRIPTOR).unwrap()));
+    static SPLIT_WHEEL_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x02, // USAGE (Mouse)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x85, 0x01, //   REPORT_ID (1)
+        0x09, 0x01, //   USAGE(Pointer)
+        0xa1, 0x00, //   COLLECTION (Physical)
+        0x05, 0x09, //     USAGE_PAGE (Button)
+        0x19, 0x01, //     USAGE_MINIMUM(1)
+        0x29, 0x03, //     USAGE_MAXIMUM(3)
+        0x15, 0x00, //     LOGICAL_MINIMUM(0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM(1)
+        0x95, 0x03, //     REPORT_COUNT(3)
+        0x75, 0x01, //     REPORT_SIZE(1)
+        0x81, 0x02, //     INPUT(Data, Variable, Absolute)
+        0x95, 0x01, //     REPORT_COUNT(1)
+        0x75, 0x05, //     REPORT_SIZE(5)
+        0x81, 0x01, //     INPUT(Constant, Array, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x15, 0x81, //     LOGICAL_MINIMUM (-127)
+        0x25, 0x7f, //     LOGICAL_MAXIMUM (127)
+        0x75, 0x08, //     REPORT_SIZE (8)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x06, //     INPUT(Data, Variable, Relative)
+        0xc0, //   END_COLLECTION
+        0x85, 0x02, //   REPORT_ID (2)
+        0x09, 0x38, //   USAGE (Wheel)
+        0x15, 0x81, //   LOGICAL_MINIMUM (-127)
+        0x25, 0x7f, //   LOGICAL_MAXIMUM (127)
+        0x75, 0x08, //   REPORT_SIZE (8)
+        0x95, 0x01, //   REPORT_COUNT (1)
+        0x81, 0x06, //   INPUT(Data, Variable, Relative)
+        0xc0, // END_COLLECTION
+    ];
 
-        let controller = 0x2 as efi::Handle;
-        assert_eq!(pointer_handler.initialize(controller, &hid_io), Err(efi::Status::UNSUPPORTED));
-    }
+    // 16x the logical resolution of MOUSE_REPORT_DESCRIPTOR over the same physical range, without a unit.
+    static HIGH_RESOLUTION_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x02, // USAGE (Mouse)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x09, 0x01, //   USAGE(Pointer)
+        0xa1, 0x00, //   COLLECTION (Physical)
+        0x05, 0x09, //     USAGE_PAGE (Button)
+        0x19, 0x01, //     USAGE_MINIMUM(1)
+        0x29, 0x03, //     USAGE_MAXIMUM(3)
+        0x15, 0x00, //     LOGICAL_MINIMUM(0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM(1)
+        0x95, 0x03, //     REPORT_COUNT(3)
+        0x75, 0x01, //     REPORT_SIZE(1)
+        0x81, 0x02, //     INPUT(Data, Variable, Absolute)
+        0x95, 0x01, //     REPORT_COUNT(1)
+        0x75, 0x05, //     REPORT_SIZE(5)
+        0x81, 0x01, //     INPUT(Constant, Array, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x16, 0x10, 0xf8, //     LOGICAL_MINIMUM (-2032)
+        0x26, 0xf0, 0x07, //     LOGICAL_MAXIMUM (2032)
+        0x35, 0x81, //     PHYSICAL_MINIMUM (-127)
+        0x45, 0x7f, //     PHYSICAL_MAXIMUM (127)
+        0x75, 0x10, //     REPORT_SIZE (16)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x06, //     INPUT(Data, Variable, Relative)
+        0xc0, //   END_COLLECTION
+        0xc0, // END_COLLECTION
+    ];
 
-    #[test]
-    fn successful_pointer_initialize_should_install_protocol_and_drop_should_tear_it_down() {
-        let boot_services = create_fake_static_boot_service();
-        static mut ABS_PTR_INTERFACE: *mut c_void = core::ptr::null_mut();
+    // 800 counts per inch: 32000 counts over 40 inches.
+    static INCH_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x02, // USAGE (Mouse)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x09, 0x01, //   USAGE(Pointer)
+        0xa1, 0x00, //   COLLECTION (Physical)
+        0x05, 0x09, //     USAGE_PAGE (Button)
+        0x19, 0x01, //     USAGE_MINIMUM(1)
+        0x29, 0x03, //     USAGE_MAXIMUM(3)
+        0x15, 0x00, //     LOGICAL_MINIMUM(0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM(1)
+        0x95, 0x03, //     REPORT_COUNT(3)
+        0x75, 0x01, //     REPORT_SIZE(1)
+        0x81, 0x02, //     INPUT(Data, Variable, Absolute)
+        0x95, 0x01, //     REPORT_COUNT(1)
+        0x75, 0x05, //     REPORT_SIZE(5)
+        0x81, 0x01, //     INPUT(Constant, Array, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x16, 0x80, 0xc1, //     LOGICAL_MINIMUM (-16000)
+        0x26, 0x80, 0x3e, //     LOGICAL_MAXIMUM (16000)
+        0x35, 0xec, //     PHYSICAL_MINIMUM (-20)
+        0x45, 0x14, //     PHYSICAL_MAXIMUM (20)
+        0x65, 0x13, //     UNIT (English Linear: Inch)
+        0x75, 0x10, //     REPORT_SIZE (16)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x06, //     INPUT(Data, Variable, Relative)
+        0xc0, //   END_COLLECTION
+        0xc0, // END_COLLECTION
+    ];
+
+    // 800 counts per inch in centimeters with a unit exponent: 8000 counts over 25.4 cm.
+    static UNIT_EXPONENT_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x02, // USAGE (Mouse)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x09, 0x01, //   USAGE(Pointer)
+        0xa1, 0x00, //   COLLECTION (Physical)
+        0x05, 0x09, //     USAGE_PAGE (Button)
+        0x19, 0x01, //     USAGE_MINIMUM(1)
+        0x29, 0x03, //     USAGE_MAXIMUM(3)
+        0x15, 0x00, //     LOGICAL_MINIMUM(0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM(1)
+        0x95, 0x03, //     REPORT_COUNT(3)
+        0x75, 0x01, //     REPORT_SIZE(1)
+        0x81, 0x02, //     INPUT(Data, Variable, Absolute)
+        0x95, 0x01, //     REPORT_COUNT(1)
+        0x75, 0x05, //     REPORT_SIZE(5)
+        0x81, 0x01, //     INPUT(Constant, Array, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x16, 0x60, 0xf0, //     LOGICAL_MINIMUM (-4000)
+        0x26, 0xa0, 0x0f, //     LOGICAL_MAXIMUM (4000)
+        0x36, 0x0a, 0xfb, //     PHYSICAL_MINIMUM (-1270)
+        0x46, 0xf6, 0x04, //     PHYSICAL_MAXIMUM (1270)
+        0x55, 0x0e, //     UNIT_EXPONENT (-2)
+        0x65, 0x11, //     UNIT (SI Linear: Centimeter)
+        0x75, 0x10, //     REPORT_SIZE (16)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x06, //     INPUT(Data, Variable, Relative)
+        0xc0, //   END_COLLECTION
+        0xc0, // END_COLLECTION
+    ];
+
+    // 8-bit relative X and 16-bit relative Y, each using the full signed range of its field.
+    static MIXED_SIZE_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x02, // USAGE (Mouse)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x09, 0x01, //   USAGE(Pointer)
+        0xa1, 0x00, //   COLLECTION (Physical)
+        0x05, 0x09, //     USAGE_PAGE (Button)
+        0x19, 0x01, //     USAGE_MINIMUM(1)
+        0x29, 0x03, //     USAGE_MAXIMUM(3)
+        0x15, 0x00, //     LOGICAL_MINIMUM(0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM(1)
+        0x95, 0x03, //     REPORT_COUNT(3)
+        0x75, 0x01, //     REPORT_SIZE(1)
+        0x81, 0x02, //     INPUT(Data, Variable, Absolute)
+        0x95, 0x01, //     REPORT_COUNT(1)
+        0x75, 0x05, //     REPORT_SIZE(5)
+        0x81, 0x01, //     INPUT(Constant, Array, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x15, 0x80, //     LOGICAL_MINIMUM (-128)
+        0x25, 0x7f, //     LOGICAL_MAXIMUM (127)
+        0x75, 0x08, //     REPORT_SIZE (8)
+        0x95, 0x01, //     REPORT_COUNT (1)
+        0x81, 0x06, //     INPUT(Data, Variable, Relative)
+        0x09, 0x31, //     USAGE (Y)
+        0x16, 0x00, 0x80, //     LOGICAL_MINIMUM (-32768)
+        0x26, 0xff, 0x7f, //     LOGICAL_MAXIMUM (32767)
+        0x75, 0x10, //     REPORT_SIZE (16)
+        0x95, 0x01, //     REPORT_COUNT (1)
+        0x81, 0x06, //     INPUT(Data, Variable, Relative)
+        0xc0, //   END_COLLECTION
+        0xc0, // END_COLLECTION
+    ];
+
+    // reports X/Y as free-running absolute counters (Absolute, Wrap) that must be differenced to get movement.
+    static COUNTER_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+        0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+        0x09, 0x02, // USAGE (Mouse)
+        0xa1, 0x01, // COLLECTION (Application)
+        0x09, 0x01, //   USAGE(Pointer)
+        0xa1, 0x00, //   COLLECTION (Physical)
+        0x05, 0x09, //     USAGE_PAGE (Button)
+        0x19, 0x01, //     USAGE_MINIMUM(1)
+        0x29, 0x03, //     USAGE_MAXIMUM(3)
+        0x15, 0x00, //     LOGICAL_MINIMUM(0)
+        0x25, 0x01, //     LOGICAL_MAXIMUM(1)
+        0x95, 0x03, //     REPORT_COUNT(3)
+        0x75, 0x01, //     REPORT_SIZE(1)
+        0x81, 0x02, //     INPUT(Data, Variable, Absolute)
+        0x95, 0x01, //     REPORT_COUNT(1)
+        0x75, 0x05, //     REPORT_SIZE(5)
+        0x81, 0x01, //     INPUT(Constant, Array, Absolute)
+        0x05, 0x01, //     USAGE_PAGE (Generic Desktop)
+        0x09, 0x30, //     USAGE (X)
+        0x09, 0x31, //     USAGE (Y)
+        0x15, 0x00, //     LOGICAL_MINIMUM (0)
+        0x26, 0xff, 0x00, //     LOGICAL_MAXIMUM (255)
+        0x75, 0x08, //     REPORT_SIZE (8)
+        0x95, 0x02, //     REPORT_COUNT (2)
+        0x81, 0x0a, //     INPUT(Data, Variable, Absolute, Wrap)
+        0xc0, //   END_COLLECTION
+        0xc0, // END_COLLECTION
+    ];
+
+    // builds a MULTI_TOUCH_PANEL_REPORT_DESCRIPTOR report from up to two (tip, contact id, x, y) contacts and the contact
+    // count.
+    fn multi_touch_report(contacts: &[(u8, u8, u16, u16)], contact_count: u8) -> Vec<u8> {
+        let mut report = vec![0x01];
+        for index in 0..2 {
+            let (tip, id, x, y) = contacts.get(index).cloned().unwrap_or_default();
+            report.extend([tip, id]);
+            report.extend(x.to_le_bytes());
+            report.extend(y.to_le_bytes());
+        }
+        report.push(contact_count);
+        report
+    }
+
+    // returns MULTI_TOUCH_PANEL_REPORT_DESCRIPTOR with the application collection usage changed to Touch Pad.
+    fn touch_pad_report_descriptor() -> &'static [u8] {
+        let mut descriptor = MULTI_TOUCH_PANEL_REPORT_DESCRIPTOR.to_vec();
+        assert_eq!(descriptor[2..4], [0x09, 0x04]);
+        descriptor[3] = 0x05;
+        descriptor.leak()
+    }
+
+    // In this module, the usage model for boot_services is global static, and so &'static dyn UefiBootServices is used
+    // throughout the API. For testing, each test will have a different set of expectations on the UefiBootServices mock
+    // object, and the mock object itself expects to be "mut", which makes it hard to handle as a single global static.
+    // Instead, raw pointers are used to simulate a MockUefiBootServices instance with 'static lifetime.
+    // This object needs to outlive anything that uses it - once created, it will live until the end of the program.
+    fn create_fake_static_boot_service() -> &'static mut MockUefiBootServices {
+        unsafe { Box::into_raw(Box::new(MockUefiBootServices::new())).as_mut().unwrap() }
+    }
+
+    // Returns a handler initialized with the given report descriptor, along with the HidIo it was initialized with.
+    // The boot services mock accepts the calls made by initialize(), receive_report() and drop().
+    fn initialized_handler(descriptor: &'static [u8]) -> (PointerHidHandler, MockHidIo) {
+        let (pointer_handler, hid_io, _) = configured_handler(descriptor, |_| ());
+        (pointer_handler, hid_io)
+    }
+
+    // As initialized_handler, but `configure` is called on the handler before it is initialized. Also returns the
+    // Absolute Pointer interface installed by the handler.
+    fn configured_handler(
+        descriptor: &'static [u8],
+        configure: impl FnOnce(&mut PointerHidHandler),
+    ) -> (PointerHidHandler, MockHidIo, *mut protocols::absolute_pointer::Protocol) {
+        let boot_services = create_fake_static_boot_service();
+        let abs_ptr_interface: &'static AtomicPtr<c_void> = Box::leak(Box::new(AtomicPtr::new(core::ptr::null_mut())));
+
+        // expected on PointerHidHandler::initialize().
+        boot_services.expect_create_event().returning(|_, _, _, _, _| efi::Status::SUCCESS);
+        boot_services.expect_install_protocol_interface().returning(move |_, _, _, interface| {
+            abs_ptr_interface.store(interface, Ordering::SeqCst);
+            efi::Status::SUCCESS
+        });
+
+        // expected on PointerHidHandler::drop().
+        boot_services.expect_open_protocol().returning(move |_, _, interface, _, _, _| {
+            unsafe { *interface = abs_ptr_interface.load(Ordering::SeqCst) };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_uninstall_protocol_interface().returning(|_, _, _| efi::Status::SUCCESS);
+        boot_services.expect_close_event().returning(|_| efi::Status::SUCCESS);
+
+        // expected on PointerHidHandler::receive_report
+        boot_services.expect_raise_tpl().returning(|_| efi::TPL_APPLICATION);
+        boot_services.expect_restore_tpl().returning(|_| ());
+
+        let agent = 0x1 as efi::Handle;
+        let mut pointer_handler = PointerHidHandler::new(boot_services, agent);
+        configure(&mut pointer_handler);
+        let mut hid_io = MockHidIo::new();
+        hid_io
+            .expect_get_report_descriptor()
+            .returning(move || Ok(hidparser::parse_report_descriptor(descriptor).unwrap()));
+
+        let controller = 0x2 as efi::Handle;
+        assert_eq!(pointer_handler.initialize(controller, &hid_io), Ok(()));
+        (
+            pointer_handler,
+            hid_io,
+            abs_ptr_interface.load(Ordering::SeqCst) as *mut protocols::absolute_pointer::Protocol,
+        )
+    }
+
+    #[test]
+    fn pointer_initialize_should_fail_if_report_descriptor_not_supported() {
+        let boot_services = create_fake_static_boot_service();
+
+        let agent = 0x1 as efi::Handle;
+        let mut pointer_handler = PointerHidHandler::new(boot_services, agent);
+        let mut hid_io = MockHidIo::new();
+        hid_io
+            .expect_get_report_descriptor()
+            .returning(|| Ok(hidparser::parse_report_descriptor(&MINIMAL_BOOT_KEYBOARD_REPORT_DESCRIPTOR).unwrap()));
+
+        let controller = 0x2 as efi::Handle;
+        assert_eq!(pointer_handler.initialize(controller, &hid_io), Err(efi::Status::UNSUPPORTED));
+    }
+
+    #[test]
+    fn successful_pointer_initialize_should_install_protocol_and_drop_should_tear_it_down() {
+        let boot_services = create_fake_static_boot_service();
+        static mut ABS_PTR_INTERFACE: *mut c_void = core::ptr::null_mut();
 
         // expected on PointerHidHandler::initialize().
         boot_services.expect_create_event().returning(|_, _, _, _, _| efi::Status::SUCCESS);
@@ -498,7 +1455,7 @@ mod test {
         assert_eq!(pointer_handler.current_state.active_buttons, 0);
         assert_eq!(pointer_handler.current_state.current_x, CENTER);
         assert_eq!(pointer_handler.current_state.current_y, CENTER);
-        assert_eq!(pointer_handler.current_state.current_z, 0);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER);
         assert_eq!(pointer_handler.state_changed, false);
 
         //click two buttons and move the cursor (+32,+32)
@@ -508,7 +1465,7 @@ mod test {
         assert_eq!(pointer_handler.current_state.active_buttons, 0x05);
         assert_eq!(pointer_handler.current_state.current_x, CENTER + 32);
         assert_eq!(pointer_handler.current_state.current_y, CENTER + 32);
-        assert_eq!(pointer_handler.current_state.current_z, 0);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER);
         assert_eq!(pointer_handler.state_changed, true);
 
         //un-click and move the cursor (+32,-16) and wheel(+32).
@@ -518,7 +1475,7 @@ mod test {
         assert_eq!(pointer_handler.current_state.active_buttons, 0);
         assert_eq!(pointer_handler.current_state.current_x, CENTER + 64);
         assert_eq!(pointer_handler.current_state.current_y, CENTER + 16);
-        assert_eq!(pointer_handler.current_state.current_z, 32);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 32);
 
         //un-click and move the cursor (+32,-32) and wheel(+32).
         let report: &[u8] = &[0x00, 0x20, 0xE0, 0x20]; //0xE0 = -32.
@@ -527,7 +1484,7 @@ mod test {
         assert_eq!(pointer_handler.current_state.active_buttons, 0);
         assert_eq!(pointer_handler.current_state.current_x, CENTER + 96);
         assert_eq!(pointer_handler.current_state.current_y, CENTER - 16);
-        assert_eq!(pointer_handler.current_state.current_z, 64);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 64);
 
         //move the cursor (0,127) until is past saturation, and check the value each time
         let report: &[u8] = &[0x00, 0x00, 0x7F, 0x0]; //0x7F = +127.
@@ -550,6 +1507,304 @@ mod test {
         }
     }
 
+    #[test]
+    fn receive_report_should_swap_primary_and_secondary_buttons_if_configured() {
+        let (mut pointer_handler, hid_io) = initialized_handler(MOUSE_REPORT_DESCRIPTOR);
+
+        //five-button mouse: all five buttons are reported in order without swap.
+        let report: &[u8] = &[0x01, 0x00, 0x00, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x01);
+
+        let report: &[u8] = &[0x1E, 0x00, 0x00, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x1E);
+
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.active_buttons, 0);
+
+        //with swap, button 1 and button 2 are exchanged and buttons 3-5 are unaffected.
+        pointer_handler.set_button_swap(true);
+
+        let report: &[u8] = &[0x01, 0x00, 0x00, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x02);
+
+        let report: &[u8] = &[0x1E, 0x00, 0x00, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x1D);
+
+        let report: &[u8] = &[0x1F, 0x00, 0x00, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x1F);
+
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.active_buttons, 0);
+    }
+
+    #[test]
+    fn receive_report_should_apply_pointer_scale_set_through_config_protocol() {
+        let boot_services = create_fake_static_boot_service();
+        static mut CONFIG_INTERFACE: *mut c_void = core::ptr::null_mut();
+
+        // expected on HidConfig::install().
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, interface| {
+            unsafe { CONFIG_INTERFACE = interface };
+            efi::Status::SUCCESS
+        });
+
+        // use a dedicated config rather than the global one so that other tests are not affected.
+        let hid_config: &'static HidConfig = Box::leak(Box::new(HidConfig::new()));
+        hid_config.install(boot_services, 0x1 as efi::Handle).unwrap();
+        let config_protocol = unsafe { CONFIG_INTERFACE } as *mut config::Protocol;
+
+        let (mut pointer_handler, hid_io) = initialized_handler(MOUSE_REPORT_DESCRIPTOR);
+        pointer_handler.set_config(hid_config);
+
+        //move the cursor (+10,-10) at the default scale.
+        let report: &[u8] = &[0x00, 0x0A, 0xF6, 0x00]; //0xF6 = -10.
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 10);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 10);
+
+        //double the scale through the protocol; the same movement should now move twice as far.
+        assert_eq!(unsafe { ((*config_protocol).set_pointer_scale)(config_protocol, 200) }, efi::Status::SUCCESS);
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 30);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 30);
+
+        //at 25%, movement smaller than one unit carries over to the next report.
+        assert_eq!(unsafe { ((*config_protocol).set_pointer_scale)(config_protocol, 25) }, efi::Status::SUCCESS);
+        let report: &[u8] = &[0x00, 0x02, 0x00, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 30);
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 31);
+    }
+
+    #[test]
+    fn receive_report_should_use_first_of_duplicate_axis_usages() {
+        // relative mouse that declares the X usage twice.
+        static DUPLICATE_X_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+            0x05, 0x01, // USAGE_PAGE (Generic Desktop)
+            0x09, 0x02, // USAGE (Mouse)
+            0xa1, 0x01, // COLLECTION (Application)
+            0x09, 0x01, //   USAGE(Pointer)
+            0xa1, 0x00, //   COLLECTION (Physical)
+            0x09, 0x30, //     USAGE (X)
+            0x09, 0x30, //     USAGE (X)
+            0x09, 0x31, //     USAGE (Y)
+            0x15, 0x81, //     LOGICAL_MINIMUM (-127)
+            0x25, 0x7f, //     LOGICAL_MAXIMUM (127)
+            0x75, 0x08, //     REPORT_SIZE (8)
+            0x95, 0x03, //     REPORT_COUNT (3)
+            0x81, 0x06, //     INPUT(Data, Variable, Relative)
+            0xc0, //   END_COLLECTION
+            0xc0, // END_COLLECTION
+        ];
+
+        let (mut pointer_handler, hid_io) = initialized_handler(DUPLICATE_X_MOUSE_REPORT_DESCRIPTOR);
+
+        let report_data = pointer_handler.input_reports.get(&None).unwrap();
+        let x_fields =
+            report_data.relevant_fields.iter().filter(|field| u32::from(field.field.usage) == GENERIC_DESKTOP_X);
+        assert_eq!(x_fields.count(), 1);
+
+        //only the first X field moves the cursor; the duplicate is ignored.
+        let report: &[u8] = &[0x10, 0x20, 0x05];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 16);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER + 5);
+    }
+
+    #[test]
+    fn receive_report_should_process_wheel_reports() {
+        let (mut pointer_handler, hid_io) = initialized_handler(THREE_BUTTON_WHEEL_MOUSE_REPORT_DESCRIPTOR);
+        assert!(pointer_handler.supported_usages.contains(&Usage::from(GENERIC_DESKTOP_WHEEL)));
+
+        //click the left button, move the cursor (+5,-3) and scroll the wheel (+2).
+        let report: &[u8] = &[0x01, 0x05, 0xFD, 0x02]; //0xFD = -3.
+        pointer_handler.receive_report(report, &hid_io);
+
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x01);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 5);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 3);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 2);
+
+        //release the button and scroll the wheel only (+3); X/Y should not move.
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x03];
+        pointer_handler.receive_report(report, &hid_io);
+
+        assert_eq!(pointer_handler.current_state.active_buttons, 0);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 5);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 3);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 5);
+    }
+
+    #[test]
+    fn polling_rate_should_be_detected_from_report_cadence() {
+        let boot_services = create_fake_static_boot_service();
+
+        static mut ABS_PTR_INTERFACE: *mut c_void = core::ptr::null_mut();
+        static TIMER_CONTEXT: AtomicPtr<c_void> = AtomicPtr::new(core::ptr::null_mut());
+        const TIMER_EVENT: efi::Event = 0x3 as efi::Event;
+
+        // expected on PointerHidHandler::initialize().
+        boot_services.expect_create_event().returning(|r#type, _, notify, context, event| {
+            if r#type == efi::EVT_TIMER | efi::EVT_NOTIFY_SIGNAL {
+                assert!(notify == Some(super::on_polling_rate_timer));
+                TIMER_CONTEXT.store(context, Ordering::SeqCst);
+                unsafe { event.write(TIMER_EVENT) };
+            }
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_set_timer().returning(|event, r#type, trigger_time| {
+            assert_eq!(event, TIMER_EVENT);
+            assert_eq!(r#type, efi::TIMER_PERIODIC);
+            assert_eq!(trigger_time, POLLING_RATE_WINDOW);
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_install_protocol_interface().returning(|_, _, _, interface| {
+            unsafe { ABS_PTR_INTERFACE = interface };
+            efi::Status::SUCCESS
+        });
+
+        // expected on PointerHidHandler::drop().
+        boot_services.expect_open_protocol().returning(|_, _, interface, _, _, _| {
+            unsafe { *interface = ABS_PTR_INTERFACE };
+            efi::Status::SUCCESS
+        });
+        boot_services.expect_uninstall_protocol_interface().returning(|_, _, _| efi::Status::SUCCESS);
+        boot_services.expect_close_event().returning(|_| efi::Status::SUCCESS);
+
+        // expected on PointerHidHandler::receive_report
+        boot_services.expect_raise_tpl().returning(|_| efi::TPL_APPLICATION);
+        boot_services.expect_restore_tpl().returning(|_| ());
+
+        let agent = 0x1 as efi::Handle;
+        let mut pointer_handler = PointerHidHandler::new(boot_services, agent);
+        let mut hid_io = MockHidIo::new();
+        hid_io
+            .expect_get_report_descriptor()
+            .returning(|| Ok(hidparser::parse_report_descriptor(&SPLIT_WHEEL_MOUSE_REPORT_DESCRIPTOR).unwrap()));
+
+        let controller = 0x2 as efi::Handle;
+        pointer_handler.set_polling_rate_detection(true);
+        assert_eq!(pointer_handler.initialize(controller, &hid_io), Ok(()));
+        assert_eq!(pointer_handler.polling_rate_event, TIMER_EVENT);
+        assert_eq!(pointer_handler.polling_rate, 0);
+
+        // a 125Hz mouse sends 125 reports in a window.
+        let report: &[u8] = &[0x01, 0x00, 0x01, 0x00];
+        for _ in 0..125 {
+            pointer_handler.receive_report(report, &hid_io);
+        }
+        super::on_polling_rate_timer(TIMER_EVENT, TIMER_CONTEXT.load(Ordering::SeqCst));
+        assert_eq!(pointer_handler.polling_rate, 125);
+        assert_eq!(pointer_handler.report_count, 0);
+
+        // a partially idle window should not lower the detected rate.
+        for _ in 0..10 {
+            pointer_handler.receive_report(report, &hid_io);
+        }
+        super::on_polling_rate_timer(TIMER_EVENT, TIMER_CONTEXT.load(Ordering::SeqCst));
+        assert_eq!(pointer_handler.polling_rate, 125);
+
+        // reports for unsupported report IDs are not counted.
+        let report: &[u8] = &[0x03, 0x00, 0x01, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+        super::on_polling_rate_timer(TIMER_EVENT, TIMER_CONTEXT.load(Ordering::SeqCst));
+        assert_eq!(pointer_handler.report_count, 0);
+    }
+
+    #[test]
+    fn receive_report_should_merge_wheel_from_separate_report_id() {
+        let (mut pointer_handler, hid_io) = initialized_handler(SPLIT_WHEEL_MOUSE_REPORT_DESCRIPTOR);
+        assert_eq!(pointer_handler.input_reports.len(), 2);
+        assert!(pointer_handler.supported_usages.contains(&Usage::from(GENERIC_DESKTOP_WHEEL)));
+
+        //report ID 1: click the left button and move the cursor (+5,-3).
+        let report: &[u8] = &[0x01, 0x01, 0x05, 0xFD]; //0xFD = -3.
+        pointer_handler.receive_report(report, &hid_io);
+
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x01);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 5);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 3);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER);
+
+        //report ID 2: scroll the wheel (+4); button and cursor state should be retained.
+        let report: &[u8] = &[0x02, 0x04];
+        pointer_handler.receive_report(report, &hid_io);
+
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x01);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 5);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 3);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 4);
+
+        //report ID 2: scroll the wheel back (-1).
+        let report: &[u8] = &[0x02, 0xFF];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 3);
+    }
+
+    #[test]
+    fn receive_report_should_sign_extend_wheel_reports() {
+        let (mut pointer_handler, hid_io) = initialized_handler(THREE_BUTTON_WHEEL_MOUSE_REPORT_DESCRIPTOR);
+
+        //scroll the wheel to the positive boundary (+127) twice.
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x7F];
+        pointer_handler.receive_report(report, &hid_io);
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 254);
+
+        //scroll the wheel to the negative boundary (0x81 = -127); must not be treated as +129.
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0x81];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 127);
+
+        //scroll -1 (0xFF).
+        let report: &[u8] = &[0x00, 0x00, 0x00, 0xFF];
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 126);
+
+        //X/Y and buttons are unaffected by wheel movement.
+        assert_eq!(pointer_handler.current_state.active_buttons, 0);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER);
+    }
+
+    #[test]
+    fn receive_report_should_scroll_down_from_the_initial_state() {
+        let (mut pointer_handler, hid_io) = initialized_handler(THREE_BUTTON_WHEEL_MOUSE_REPORT_DESCRIPTOR);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER);
+
+        //scroll the wheel down (-1) from rest.
+        pointer_handler.receive_report(&[0x00, 0x00, 0x00, 0xFF], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER - 1);
+        assert!(pointer_handler.state_changed);
+    }
+
+    #[test]
+    fn receive_report_should_keep_scrolling_past_the_ends_of_the_z_axis() {
+        let (mut pointer_handler, hid_io) = initialized_handler(THREE_BUTTON_WHEEL_MOUSE_REPORT_DESCRIPTOR);
+
+        //scroll the wheel up (+127) until Z passes the top of the range; every report should change the state.
+        for _ in 0..5 {
+            pointer_handler.state_changed = false;
+            pointer_handler.receive_report(&[0x00, 0x00, 0x00, 0x7F], &hid_io);
+            assert!(pointer_handler.state_changed);
+        }
+        //Z wraps around to the bottom of the range: CENTER + 5 * 127 = 1147, less the 1025 values of the range.
+        assert_eq!(pointer_handler.current_state.current_z, 122);
+
+        //scroll the wheel down (-127) past the bottom of the range: 122 - 127 wraps around to the top of the range.
+        pointer_handler.receive_report(&[0x00, 0x00, 0x00, 0x81], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_z, AXIS_RESOLUTION - 4);
+        assert!(pointer_handler.state_changed);
+    }
+
     #[test]
     fn receive_report_should_process_absolute_reports() {
         let boot_services = create_fake_static_boot_service();
@@ -609,6 +1864,322 @@ mod test {
         assert_eq!(pointer_handler.state_changed, true);
     }
 
+    #[test]
+    fn receive_report_should_scale_absolute_reports_to_configured_ranges() {
+        // a 1920x1080 display, with the panel mounted upside down vertically.
+        let (mut pointer_handler, hid_io, abs_ptr_interface) =
+            configured_handler(ABS_POINTER_REPORT_DESCRIPTOR, |pointer_handler| {
+                pointer_handler
+                    .set_axis_ranges(
+                        AxisRange { min: 0, max: 1920, invert: false },
+                        AxisRange { min: 0, max: 1080, invert: true },
+                    )
+                    .unwrap()
+            });
+        assert_eq!(pointer_handler.current_state.current_x, 960);
+        assert_eq!(pointer_handler.current_state.current_y, 540);
+
+        // the published mode matches the configured ranges.
+        let mode = unsafe { &*(*abs_ptr_interface).mode };
+        assert_eq!((mode.absolute_min_x, mode.absolute_max_x), (0, 1920));
+        assert_eq!((mode.absolute_min_y, mode.absolute_max_y), (0, 1080));
+        assert_eq!((mode.absolute_min_z, mode.absolute_max_z), (0, AXIS_RESOLUTION));
+
+        // the logical range (0-4095) is scaled onto each axis, and Y is inverted.
+        for (x, y, expected_x, expected_y) in [(0u16, 0u16, 0, 1080), (4095, 4095, 1920, 0), (1024, 1024, 480, 810)] {
+            let mut report = vec![0x00];
+            report.extend(x.to_le_bytes());
+            report.extend(y.to_le_bytes());
+            report.extend([0x00, 0x00]);
+            pointer_handler.receive_report(&report, &hid_io);
+            assert_eq!(pointer_handler.current_state.current_x, expected_x);
+            assert_eq!(pointer_handler.current_state.current_y, expected_y);
+        }
+    }
+
+    #[test]
+    fn axis_range_should_pass_through_degenerate_axes() {
+        let descriptor = hidparser::parse_report_descriptor(ABS_POINTER_REPORT_DESCRIPTOR).unwrap();
+        let hidparser::ReportField::Variable(mut field) = descriptor.input_reports[0].fields[2].clone() else {
+            panic!("expected a variable X field");
+        };
+        field.logical_minimum = 100.into();
+        field.logical_maximum = 100.into();
+
+        let range = AxisRange { min: 0, max: 1920, invert: false };
+        assert_eq!(range.project(&field, 100), Some(100));
+        assert_eq!(range.project(&field, 5000), Some(1920));
+        let range = AxisRange { invert: true, ..range };
+        assert_eq!(range.project(&field, 100), Some(1820));
+    }
+
+    #[test]
+    fn set_axis_ranges_should_reject_invalid_ranges() {
+        let boot_services = create_fake_static_boot_service();
+        let mut pointer_handler = PointerHidHandler::new(boot_services, 0x1 as efi::Handle);
+
+        let valid = AxisRange { min: 0, max: 1920, invert: false };
+        let reversed = AxisRange { min: 1080, max: 0, invert: false };
+        let too_large = AxisRange { min: 0, max: u64::MAX, invert: false };
+        assert_eq!(pointer_handler.set_axis_ranges(reversed, valid), Err(efi::Status::INVALID_PARAMETER));
+        assert_eq!(pointer_handler.set_axis_ranges(valid, reversed), Err(efi::Status::INVALID_PARAMETER));
+        assert_eq!(pointer_handler.set_axis_ranges(valid, too_large), Err(efi::Status::INVALID_PARAMETER));
+        assert_eq!(pointer_handler.x_range, AxisRange::default());
+        assert_eq!(pointer_handler.y_range, AxisRange::default());
+
+        // an empty range is allowed, and pins the pointer to a single position.
+        let empty = AxisRange { min: 100, max: 100, invert: false };
+        assert_eq!(pointer_handler.set_axis_ranges(valid, empty), Ok(()));
+        assert_eq!(pointer_handler.current_state.current_y, 100);
+    }
+
+    #[test]
+    fn receive_report_should_ignore_xy_when_tip_switch_inactive() {
+        let (mut pointer_handler, hid_io) = initialized_handler(TOUCH_PANEL_REPORT_DESCRIPTOR);
+
+        //tip switch inactive with garbage X/Y - should be ignored.
+        let report: &[u8] = &[0x00, 0x37, 0x01, 0xA5, 0x0E];
+        pointer_handler.receive_report(report, &hid_io);
+
+        assert_eq!(pointer_handler.current_state.active_buttons, 0);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER);
+        assert_eq!(pointer_handler.state_changed, false);
+
+        //tip switch active at (1024, 3072) - should update position and tip state.
+        let report: &[u8] = &[0x01, 0x00, 0x04, 0x00, 0x0C];
+        pointer_handler.receive_report(report, &hid_io);
+
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x01);
+        assert_eq!(pointer_handler.current_state.current_x, 256);
+        assert_eq!(pointer_handler.current_state.current_y, 768);
+        assert_eq!(pointer_handler.state_changed, true);
+
+        //tip switch released with garbage X/Y - tip state should clear but X/Y should be retained.
+        let report: &[u8] = &[0x00, 0xFF, 0x0F, 0x00, 0x00];
+        pointer_handler.receive_report(report, &hid_io);
+
+        assert_eq!(pointer_handler.current_state.active_buttons, 0);
+        assert_eq!(pointer_handler.current_state.current_x, 256);
+        assert_eq!(pointer_handler.current_state.current_y, 768);
+    }
+
+    #[test]
+    fn receive_report_should_track_multi_touch_contacts() {
+        let (mut pointer_handler, hid_io) = initialized_handler(MULTI_TOUCH_PANEL_REPORT_DESCRIPTOR);
+
+        // contact-up for a contact that was never down is ignored.
+        pointer_handler.receive_report(&multi_touch_report(&[(0, 7, 0x400, 0x400)], 1), &hid_io);
+        assert!(pointer_handler.active_contacts().is_empty());
+        assert_eq!(pointer_handler.current_state.active_buttons, 0);
+        assert!(!pointer_handler.state_changed);
+
+        // first contact down at (1024, 3072) becomes the primary contact.
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 3, 0x400, 0xC00)], 1), &hid_io);
+        assert_eq!(pointer_handler.active_contacts(), &[Contact { contact_id: 3, x: 256, y: 768 }]);
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x01);
+        assert_eq!(pointer_handler.current_state.current_x, 256);
+        assert_eq!(pointer_handler.current_state.current_y, 768);
+        assert!(pointer_handler.state_changed);
+
+        // second contact down in the first slot; the primary contact does not change, and the unused slot (contact id
+        // zero, tip up) beyond the contact count is not treated as a contact-up.
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 5, 0x800, 0x800), (1, 3, 0x400, 0xC00)], 2), &hid_io);
+        assert_eq!(
+            pointer_handler.active_contacts(),
+            &[Contact { contact_id: 3, x: 256, y: 768 }, Contact { contact_id: 5, x: 512, y: 512 }]
+        );
+        assert_eq!(pointer_handler.current_state.current_x, 256);
+        assert_eq!(pointer_handler.current_state.current_y, 768);
+
+        // hybrid mode: three contacts are reported across two reports; the second report has a zero contact count.
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 3, 0x400, 0xC00), (1, 5, 0x800, 0x800)], 3), &hid_io);
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 9, 0xFFF, 0x000)], 0), &hid_io);
+        assert_eq!(pointer_handler.active_contacts().len(), 3);
+        assert_eq!(pointer_handler.active_contacts()[2], Contact { contact_id: 9, x: AXIS_RESOLUTION, y: 0 });
+
+        // primary contact lifts; the next oldest contact becomes primary.
+        pointer_handler.receive_report(&multi_touch_report(&[(0, 3, 0x400, 0xC00), (1, 5, 0x800, 0x800)], 3), &hid_io);
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 9, 0xFFF, 0x000)], 0), &hid_io);
+        assert_eq!(
+            pointer_handler.active_contacts(),
+            &[Contact { contact_id: 5, x: 512, y: 512 }, Contact { contact_id: 9, x: AXIS_RESOLUTION, y: 0 }]
+        );
+        assert_eq!(pointer_handler.current_state.active_buttons, 0x01);
+        assert_eq!(pointer_handler.current_state.current_x, 512);
+        assert_eq!(pointer_handler.current_state.current_y, 512);
+
+        // all contacts lift; the tip switch clears and the last position is retained.
+        pointer_handler.receive_report(&multi_touch_report(&[(0, 5, 0x800, 0x800), (0, 9, 0xFFF, 0x000)], 2), &hid_io);
+        assert!(pointer_handler.active_contacts().is_empty());
+        assert_eq!(pointer_handler.current_state.active_buttons, 0);
+        assert_eq!(pointer_handler.current_state.current_x, 512);
+        assert_eq!(pointer_handler.current_state.current_y, 512);
+
+        // contacts beyond the maximum are ignored.
+        for id in 0..(MAX_CONTACTS as u8 + 2) {
+            pointer_handler.receive_report(&multi_touch_report(&[(1, id, 0x100, 0x100)], 1), &hid_io);
+        }
+        assert_eq!(pointer_handler.active_contacts().len(), MAX_CONTACTS);
+        assert!(pointer_handler.active_contacts().iter().all(|contact| contact.contact_id < MAX_CONTACTS as u32));
+    }
+
+    #[test]
+    fn receive_report_should_scroll_with_two_contacts_on_a_touch_pad() {
+        let (mut pointer_handler, hid_io) = initialized_handler(touch_pad_report_descriptor());
+        assert!(pointer_handler.supported_usages.contains(&Usage::from(GENERIC_DESKTOP_WHEEL)));
+        assert_eq!(pointer_handler.current_state.current_z, CENTER);
+
+        // two contacts go down at y = 512, then move up together by 256 (64 after scaling) without moving the pointer.
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x600, 0x800), (1, 2, 0xA00, 0x800)], 2), &hid_io);
+        let (x, y) = (pointer_handler.current_state.current_x, pointer_handler.current_state.current_y);
+        pointer_handler.state_changed = false;
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x600, 0x700), (1, 2, 0xA00, 0x700)], 2), &hid_io);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 4);
+        assert_eq!((pointer_handler.current_state.current_x, pointer_handler.current_state.current_y), (x, y));
+        assert!(pointer_handler.state_changed);
+
+        // moving down together scrolls down.
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x600, 0x900), (1, 2, 0xA00, 0x900)], 2), &hid_io);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER - 4);
+        assert_eq!((pointer_handler.current_state.current_x, pointer_handler.current_state.current_y), (x, y));
+    }
+
+    #[test]
+    fn receive_report_should_move_the_pointer_with_one_contact_on_a_touch_pad() {
+        let (mut pointer_handler, hid_io) = initialized_handler(touch_pad_report_descriptor());
+
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x600, 0x800)], 1), &hid_io);
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x600, 0x700)], 1), &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, 384);
+        assert_eq!(pointer_handler.current_state.current_y, 448);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER);
+
+        // a touch screen does not scroll with two contacts.
+        let (mut pointer_handler, hid_io) = initialized_handler(MULTI_TOUCH_PANEL_REPORT_DESCRIPTOR);
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x600, 0x800), (1, 2, 0xA00, 0x800)], 2), &hid_io);
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x600, 0x700), (1, 2, 0xA00, 0x700)], 2), &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, 384);
+        assert_eq!(pointer_handler.current_state.current_y, 448);
+        assert_eq!(pointer_handler.current_state.current_z, 0);
+    }
+
+    #[test]
+    fn pinch_callback_should_receive_pinch_gestures() {
+        static PINCH_EVENTS: std::sync::Mutex<Vec<PinchEvent>> = std::sync::Mutex::new(Vec::new());
+
+        let (mut pointer_handler, hid_io) = initialized_handler(MULTI_TOUCH_PANEL_REPORT_DESCRIPTOR);
+
+        // pinch recognition is off by default.
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x600, 0x800), (1, 2, 0xA00, 0x800)], 2), &hid_io);
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x400, 0x800), (1, 2, 0xC00, 0x800)], 2), &hid_io);
+        pointer_handler.receive_report(&multi_touch_report(&[(0, 1, 0x400, 0x800), (0, 2, 0xC00, 0x800)], 2), &hid_io);
+
+        pointer_handler.set_pinch_callback(Some(|pinch| PINCH_EVENTS.lock().unwrap().push(pinch)));
+
+        // contacts start 1024 apart (256 after scaling), diverge to 2048 apart, then converge to 512 apart.
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x600, 0x800), (1, 2, 0xA00, 0x800)], 2), &hid_io);
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x400, 0x800), (1, 2, 0xC00, 0x800)], 2), &hid_io);
+        pointer_handler.receive_report(&multi_touch_report(&[(1, 1, 0x700, 0x800), (1, 2, 0x900, 0x800)], 2), &hid_io);
+
+        // the raw contacts remain available.
+        assert_eq!(pointer_handler.active_contacts().len(), 2);
+
+        let events = PINCH_EVENTS.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].direction, PinchDirection::Out);
+        assert!(events[0].scale_percent.abs_diff(200) <= 1);
+        assert_eq!(events[1].direction, PinchDirection::In);
+        assert!(events[1].scale_percent.abs_diff(50) <= 1);
+    }
+
+    #[test]
+    fn receive_report_should_normalize_relative_movement_to_reference_resolution() {
+        // the same physical motion (+8, -4 at the reference resolution), as reported by an 8-bit mouse without a
+        // declared resolution and by 800 counts per inch mice that declare their resolution in inches and centimeters.
+        // A device that declares a physical range without a unit is not scaled.
+        let devices: [(&'static [u8], &[u8]); 4] = [
+            (MOUSE_REPORT_DESCRIPTOR, &[0x00, 0x08, 0xFC, 0x00]),
+            (INCH_MOUSE_REPORT_DESCRIPTOR, &[0x00, 0x10, 0x00, 0xF8, 0xFF]),
+            (UNIT_EXPONENT_MOUSE_REPORT_DESCRIPTOR, &[0x00, 0x10, 0x00, 0xF8, 0xFF]),
+            (HIGH_RESOLUTION_MOUSE_REPORT_DESCRIPTOR, &[0x00, 0x08, 0x00, 0xFC, 0xFF]),
+        ];
+
+        for (descriptor, report) in devices {
+            let (mut pointer_handler, hid_io) = initialized_handler(descriptor);
+            pointer_handler.receive_report(report, &hid_io);
+            assert_eq!(pointer_handler.current_state.current_x, CENTER + 8);
+            assert_eq!(pointer_handler.current_state.current_y, CENTER - 4);
+        }
+
+        // movement smaller than one count at the reference resolution accumulates across reports.
+        let (mut pointer_handler, hid_io) = initialized_handler(INCH_MOUSE_REPORT_DESCRIPTOR);
+        let report: &[u8] = &[0x00, 0x01, 0x00, 0xFF, 0xFF]; // (+1, -1) logical = (+0.5, -0.5) at the reference.
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER);
+
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 1);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 1);
+
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 1);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 1);
+
+        pointer_handler.receive_report(report, &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 2);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 2);
+    }
+
+    #[test]
+    fn receive_report_should_difference_absolute_counters() {
+        let (mut pointer_handler, hid_io) = initialized_handler(COUNTER_MOUSE_REPORT_DESCRIPTOR);
+
+        // the first report only establishes the baseline counter values.
+        pointer_handler.receive_report(&[0x00, 250, 100], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER);
+
+        pointer_handler.receive_report(&[0x00, 253, 96], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 3);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 4);
+
+        // X wraps from 253 to 2 (+5), Y wraps from 96 down through 0 to 250 (-102).
+        pointer_handler.receive_report(&[0x00, 2, 250], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 8);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 106);
+
+        pointer_handler.receive_report(&[0x00, 10, 250], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER + 16);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 106);
+    }
+
+    #[test]
+    fn receive_report_should_sign_extend_relative_movement() {
+        let (mut pointer_handler, hid_io) = initialized_handler(MIXED_SIZE_MOUSE_REPORT_DESCRIPTOR);
+
+        // 0xFF (8-bit) and 0xFF 0xFF (16-bit) are both -1.
+        pointer_handler.receive_report(&[0x00, 0xFF, 0xFF, 0xFF], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER - 1);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 1);
+
+        // 0x80 (8-bit) and 0x80 0xFF (16-bit) are both -128.
+        pointer_handler.receive_report(&[0x00, 0x80, 0x80, 0xFF], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER - 129);
+        assert_eq!(pointer_handler.current_state.current_y, CENTER - 129);
+
+        // the largest negative 16-bit movement is clamped to the edge of the axis.
+        pointer_handler.receive_report(&[0x00, 0x00, 0x00, 0x80], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER - 129);
+        assert_eq!(pointer_handler.current_state.current_y, 0);
+
+        // positive movement is unaffected.
+        pointer_handler.receive_report(&[0x00, 0x7F, 0xFF, 0x7F], &hid_io);
+        assert_eq!(pointer_handler.current_state.current_x, CENTER - 2);
+        assert_eq!(pointer_handler.current_state.current_y, AXIS_RESOLUTION);
+    }
+
     #[test]
     fn bad_reports_should_be_processed_with_best_effort() {
         let boot_services = create_fake_static_boot_service();
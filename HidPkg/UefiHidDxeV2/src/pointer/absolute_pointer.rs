@@ -104,15 +104,15 @@ impl PointerContext {
         let mut mode: protocols::absolute_pointer::Mode = Default::default();
 
         if pointer_handler.supported_usages.contains(&Usage::from(super::GENERIC_DESKTOP_X)) {
-            mode.absolute_max_x = super::AXIS_RESOLUTION;
-            mode.absolute_min_x = 0;
+            mode.absolute_max_x = pointer_handler.x_range.max;
+            mode.absolute_min_x = pointer_handler.x_range.min;
         } else {
             debugln!(DEBUG_WARN, "No x-axis usages found in the report descriptor.");
         }
 
         if pointer_handler.supported_usages.contains(&Usage::from(super::GENERIC_DESKTOP_Y)) {
-            mode.absolute_max_y = super::AXIS_RESOLUTION;
-            mode.absolute_min_y = 0;
+            mode.absolute_max_y = pointer_handler.y_range.max;
+            mode.absolute_min_y = pointer_handler.y_range.min;
         } else {
             debugln!(DEBUG_WARN, "No y-axis usages found in the report descriptor.");
         }
@@ -475,7 +475,7 @@ mod test {
         assert_eq!(pointer_handler.current_state.active_buttons, 0);
         assert_eq!(pointer_handler.current_state.current_x, CENTER);
         assert_eq!(pointer_handler.current_state.current_y, CENTER);
-        assert_eq!(pointer_handler.current_state.current_z, 0);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER);
         assert_eq!(pointer_handler.state_changed, false);
 
         //click two buttons and move the cursor (+32,+32,+32)
@@ -485,7 +485,7 @@ mod test {
         assert_eq!(pointer_handler.current_state.active_buttons, 0x5);
         assert_eq!(pointer_handler.current_state.current_x, CENTER + 0x20);
         assert_eq!(pointer_handler.current_state.current_y, CENTER + 0x20);
-        assert_eq!(pointer_handler.current_state.current_z, 0x20);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 0x20);
         assert_eq!(pointer_handler.state_changed, true);
 
         //reset state
@@ -498,7 +498,7 @@ mod test {
         assert_eq!(pointer_handler.current_state.active_buttons, 0);
         assert_eq!(pointer_handler.current_state.current_x, CENTER);
         assert_eq!(pointer_handler.current_state.current_y, CENTER);
-        assert_eq!(pointer_handler.current_state.current_z, 0);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER);
         assert_eq!(pointer_handler.state_changed, false);
     }
 
@@ -560,7 +560,7 @@ mod test {
         assert_eq!(pointer_handler.current_state.active_buttons, 0);
         assert_eq!(pointer_handler.current_state.current_x, CENTER);
         assert_eq!(pointer_handler.current_state.current_y, CENTER);
-        assert_eq!(pointer_handler.current_state.current_z, 0);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER);
         assert_eq!(pointer_handler.state_changed, false);
 
         //click two buttons and move the cursor (+32,+32,+32)
@@ -570,7 +570,7 @@ mod test {
         assert_eq!(pointer_handler.current_state.active_buttons, 0x5);
         assert_eq!(pointer_handler.current_state.current_x, CENTER + 0x20);
         assert_eq!(pointer_handler.current_state.current_y, CENTER + 0x20);
-        assert_eq!(pointer_handler.current_state.current_z, 0x20);
+        assert_eq!(pointer_handler.current_state.current_z, CENTER + 0x20);
         assert_eq!(pointer_handler.state_changed, true);
 
         let mut absolute_pointer_state: protocols::absolute_pointer::State = Default::default();
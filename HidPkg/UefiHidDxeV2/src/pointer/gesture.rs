@@ -0,0 +1,253 @@
+//! Pointer gesture recognition.
+//!
+//! This module recognizes gestures from the contacts reported by multi-touch digitizers.
+//!
+//! ## License
+//!
+//! Copyright (c) Microsoft Corporation. All rights reserved.
+//! SPDX-License-Identifier: BSD-2-Clause-Patent
+//!
+use super::Contact;
+
+// minimum change in scale (in percent) since the last reported pinch before another pinch is reported.
+const PINCH_THRESHOLD_PERCENT: u32 = 10;
+// vertical distance (on the contact scale) the contacts of a two-finger scroll move for each scroll step.
+const SCROLL_STEP_DISTANCE: i64 = 16;
+
+/// Direction of a pinch gesture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinchDirection {
+    /// The contacts moved closer together (zoom out).
+    In,
+    /// The contacts moved further apart (zoom in).
+    Out,
+}
+
+/// A pinch gesture event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinchEvent {
+    /// Direction of movement since the last reported pinch.
+    pub direction: PinchDirection,
+    /// Distance between the contacts relative to the distance when the gesture started, in percent.
+    pub scale_percent: u32,
+}
+
+/// Callback invoked with recognized pinch gestures. Invoked at TPL_NOTIFY from report processing.
+pub type PinchCallback = fn(PinchEvent);
+
+// Recognizes pinch gestures from the two oldest active contacts.
+#[derive(Debug, Default)]
+pub(crate) struct PinchRecognizer {
+    contact_ids: Option<(u32, u32)>,
+    start_distance: u64,
+    last_scale: u32,
+}
+
+impl PinchRecognizer {
+    // Updates the recognizer with the current active contacts (in the order they went down). Returns a pinch event if
+    // the scale has changed by at least the threshold since the last event.
+    pub(crate) fn update(&mut self, contacts: &[Contact]) -> Option<PinchEvent> {
+        let [first, second, ..] = contacts else {
+            self.contact_ids = None;
+            return None;
+        };
+
+        let distance = distance(first, second);
+
+        // a new pair of contacts (or a pair that started at the same point) starts a new gesture.
+        if self.contact_ids != Some((first.contact_id, second.contact_id)) || self.start_distance == 0 {
+            self.contact_ids = Some((first.contact_id, second.contact_id));
+            self.start_distance = distance;
+            self.last_scale = 100;
+            return None;
+        }
+
+        let scale = (distance * 100 / self.start_distance) as u32;
+        if scale.abs_diff(self.last_scale) < PINCH_THRESHOLD_PERCENT {
+            return None;
+        }
+
+        let direction = if scale < self.last_scale { PinchDirection::In } else { PinchDirection::Out };
+        self.last_scale = scale;
+        Some(PinchEvent { direction, scale_percent: scale })
+    }
+}
+
+// Recognizes two-finger scroll gestures from the two oldest active contacts.
+#[derive(Debug, Default)]
+pub(crate) struct ScrollRecognizer {
+    last_contacts: Option<(Contact, Contact)>,
+    remainder: i64,
+}
+
+impl ScrollRecognizer {
+    // Updates the recognizer with the current active contacts (in the order they went down). Returns None if there are
+    // fewer than two contacts (i.e. no scroll is in progress). Otherwise returns the number of scroll steps since the
+    // last update: positive when the contacts move up together (like a wheel rotated away from the user), negative
+    // when they move down, and zero when they did not move together.
+    pub(crate) fn update(&mut self, contacts: &[Contact]) -> Option<i64> {
+        let [first, second, ..] = contacts else {
+            self.last_contacts = None;
+            return None;
+        };
+
+        let steps = match self.last_contacts {
+            Some((last_first, last_second))
+                if (last_first.contact_id, last_second.contact_id) == (first.contact_id, second.contact_id) =>
+            {
+                let first_delta = first.y as i64 - last_first.y as i64;
+                let second_delta = second.y as i64 - last_second.y as i64;
+                // contacts moving apart or together (e.g. a pinch) do not scroll.
+                if first_delta.signum() == second_delta.signum() {
+                    self.remainder -= (first_delta + second_delta) / 2;
+                }
+                let steps = self.remainder / SCROLL_STEP_DISTANCE;
+                self.remainder %= SCROLL_STEP_DISTANCE;
+                steps
+            }
+            // a new pair of contacts starts a new gesture.
+            _ => {
+                self.remainder = 0;
+                0
+            }
+        };
+        self.last_contacts = Some((*first, *second));
+        Some(steps)
+    }
+}
+
+// Returns the distance between two contacts.
+fn distance(first: &Contact, second: &Contact) -> u64 {
+    let dx = first.x.abs_diff(second.x);
+    let dy = first.y.abs_diff(second.y);
+    isqrt(dx * dx + dy * dy)
+}
+
+// Integer square root (rounded down).
+fn isqrt(value: u64) -> u64 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+#[cfg(test)]
+mod test {
+    use super::{isqrt, PinchDirection, PinchEvent, PinchRecognizer, ScrollRecognizer, SCROLL_STEP_DISTANCE};
+    use crate::pointer::Contact;
+
+    fn contacts(separation: u64) -> [Contact; 2] {
+        [
+            Contact { contact_id: 1, x: 512 - separation / 2, y: 512 },
+            Contact { contact_id: 2, x: 512 + separation / 2, y: 512 },
+        ]
+    }
+
+    #[test]
+    fn isqrt_should_round_down() {
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(1), 1);
+        assert_eq!(isqrt(8), 2);
+        assert_eq!(isqrt(9), 3);
+        assert_eq!(isqrt(2 * 1024 * 1024), 1448);
+    }
+
+    #[test]
+    fn diverging_contacts_should_pinch_out() {
+        let mut recognizer = PinchRecognizer::default();
+        assert_eq!(recognizer.update(&contacts(100)), None);
+
+        // small movements below the threshold are not reported.
+        assert_eq!(recognizer.update(&contacts(104)), None);
+        assert_eq!(
+            recognizer.update(&contacts(150)),
+            Some(PinchEvent { direction: PinchDirection::Out, scale_percent: 150 })
+        );
+        assert_eq!(
+            recognizer.update(&contacts(200)),
+            Some(PinchEvent { direction: PinchDirection::Out, scale_percent: 200 })
+        );
+    }
+
+    #[test]
+    fn converging_contacts_should_pinch_in() {
+        let mut recognizer = PinchRecognizer::default();
+        assert_eq!(recognizer.update(&contacts(400)), None);
+        assert_eq!(
+            recognizer.update(&contacts(300)),
+            Some(PinchEvent { direction: PinchDirection::In, scale_percent: 75 })
+        );
+        assert_eq!(
+            recognizer.update(&contacts(100)),
+            Some(PinchEvent { direction: PinchDirection::In, scale_percent: 25 })
+        );
+
+        // reversing direction mid-gesture reports relative to the original separation.
+        assert_eq!(
+            recognizer.update(&contacts(200)),
+            Some(PinchEvent { direction: PinchDirection::Out, scale_percent: 50 })
+        );
+    }
+
+    #[test]
+    fn contact_changes_should_restart_the_gesture() {
+        let mut recognizer = PinchRecognizer::default();
+        assert_eq!(recognizer.update(&contacts(100)), None);
+
+        // a single contact ends the gesture.
+        assert_eq!(recognizer.update(&contacts(200)[..1]), None);
+        assert_eq!(recognizer.update(&contacts(200)), None);
+
+        // a different pair of contacts starts a new gesture.
+        let mut other_pair = contacts(400);
+        other_pair[1].contact_id = 3;
+        assert_eq!(recognizer.update(&other_pair), None);
+        assert_eq!(
+            recognizer.update(&[other_pair[0], Contact { x: 512 + 400, ..other_pair[1] }]),
+            Some(PinchEvent { direction: PinchDirection::Out, scale_percent: 150 })
+        );
+    }
+
+    fn scroll_contacts(first_y: u64, second_y: u64) -> [Contact; 2] {
+        [Contact { contact_id: 1, x: 256, y: first_y }, Contact { contact_id: 2, x: 768, y: second_y }]
+    }
+
+    #[test]
+    fn contacts_moving_together_should_scroll() {
+        let mut recognizer = ScrollRecognizer::default();
+        assert_eq!(recognizer.update(&scroll_contacts(512, 512)), Some(0));
+
+        // moving up scrolls up, one step per SCROLL_STEP_DISTANCE; the remainder carries over to the next update.
+        let distance = SCROLL_STEP_DISTANCE as u64;
+        assert_eq!(recognizer.update(&scroll_contacts(512 - 3 * distance, 512 - 3 * distance)), Some(3));
+        assert_eq!(recognizer.update(&scroll_contacts(512 - 3 * distance - 8, 512 - 3 * distance - 8)), Some(0));
+        assert_eq!(recognizer.update(&scroll_contacts(512 - 4 * distance, 512 - 4 * distance)), Some(1));
+
+        // moving down scrolls down, by the average movement of the contacts.
+        assert_eq!(recognizer.update(&scroll_contacts(512 - distance, 512 - 3 * distance)), Some(-2));
+    }
+
+    #[test]
+    fn contacts_not_moving_together_should_not_scroll() {
+        let mut recognizer = ScrollRecognizer::default();
+
+        // a single contact is not a scroll.
+        assert_eq!(recognizer.update(&scroll_contacts(512, 512)[..1]), None);
+
+        // contacts moving in opposite directions (or only one of them moving) do not scroll.
+        assert_eq!(recognizer.update(&scroll_contacts(512, 512)), Some(0));
+        assert_eq!(recognizer.update(&scroll_contacts(400, 600)), Some(0));
+        assert_eq!(recognizer.update(&scroll_contacts(400, 400)), Some(0));
+
+        // a different pair of contacts starts a new gesture.
+        let mut other_pair = scroll_contacts(200, 200);
+        other_pair[1].contact_id = 3;
+        assert_eq!(recognizer.update(&other_pair), Some(0));
+    }
+}
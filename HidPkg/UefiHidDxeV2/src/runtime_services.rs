@@ -0,0 +1,233 @@
+//! Provides a safe wrapper for the UEFI Runtime Services used in this crate.
+//!
+//! This module centralizes access to the runtime services table so that consumers do not need to dereference the raw
+//! table pointer themselves. Services return an error rather than panic if the table has not been initialized.
+//!
+//! ## License
+//!
+//! Copyright (C) Microsoft Corporation. All rights reserved.
+//!
+//! SPDX-License-Identifier: BSD-2-Clause-Patent
+//!
+use core::{
+    ffi::c_void,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use r_efi::efi;
+
+/// Wraps the UEFI Runtime Services table.
+#[derive(Debug)]
+pub struct StandardUefiRuntimeServices {
+    runtime_services: AtomicPtr<efi::RuntimeServices>,
+}
+
+impl StandardUefiRuntimeServices {
+    /// Creates a new StandardUefiRuntimeServices.
+    /// Note: methods on this instance return `Err(efi::Status::NOT_READY)` until [`Self::initialize`] is called.
+    pub const fn new() -> Self {
+        Self { runtime_services: AtomicPtr::new(core::ptr::null_mut()) }
+    }
+
+    /// Initializes this instance of [`StandardUefiRuntimeServices`] with a pointer to the runtime services table.
+    pub fn initialize(&self, runtime_services: *mut efi::RuntimeServices) {
+        self.runtime_services.store(runtime_services, Ordering::SeqCst)
+    }
+
+    // Returns a reference to the runtime services table, or NOT_READY if uninitialized.
+    fn runtime_services(&self) -> Result<&efi::RuntimeServices, efi::Status> {
+        let runtime_services_ptr = self.runtime_services.load(Ordering::SeqCst);
+        unsafe { runtime_services_ptr.as_ref() }.ok_or(efi::Status::NOT_READY)
+    }
+
+    /// Resets the system. On real firmware this does not return if the table is initialized.
+    pub fn reset_system(&self, reset_type: efi::ResetType, reset_status: efi::Status) -> Result<(), efi::Status> {
+        let runtime_services = self.runtime_services()?;
+        (runtime_services.reset_system)(reset_type, reset_status, 0, core::ptr::null_mut());
+        Ok(())
+    }
+
+    /// Reads the variable `name` (a null-terminated UCS-2 string) under `vendor_guid` into `data`.
+    ///
+    /// Returns the variable attributes and the number of bytes written to `data`.
+    pub fn get_variable(
+        &self,
+        name: &[u16],
+        vendor_guid: &efi::Guid,
+        data: &mut [u8],
+    ) -> Result<(u32, usize), efi::Status> {
+        let runtime_services = self.runtime_services()?;
+        if name.last() != Some(&0) {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        let mut attributes = 0u32;
+        let mut data_size = data.len();
+        let status = (runtime_services.get_variable)(
+            name.as_ptr() as *mut u16,
+            vendor_guid as *const efi::Guid as *mut efi::Guid,
+            core::ptr::addr_of_mut!(attributes),
+            core::ptr::addr_of_mut!(data_size),
+            data.as_mut_ptr() as *mut c_void,
+        );
+        if status.is_error() {
+            return Err(status);
+        }
+        Ok((attributes, data_size))
+    }
+
+    /// Writes `data` to the variable `name` (a null-terminated UCS-2 string) under `vendor_guid`.
+    pub fn set_variable(
+        &self,
+        name: &[u16],
+        vendor_guid: &efi::Guid,
+        attributes: u32,
+        data: &[u8],
+    ) -> Result<(), efi::Status> {
+        let runtime_services = self.runtime_services()?;
+        if name.last() != Some(&0) {
+            return Err(efi::Status::INVALID_PARAMETER);
+        }
+
+        let status = (runtime_services.set_variable)(
+            name.as_ptr() as *mut u16,
+            vendor_guid as *const efi::Guid as *mut efi::Guid,
+            attributes,
+            data.len(),
+            data.as_ptr() as *mut c_void,
+        );
+        if status.is_error() {
+            return Err(status);
+        }
+        Ok(())
+    }
+}
+
+impl Default for StandardUefiRuntimeServices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl Sync for StandardUefiRuntimeServices {}
+unsafe impl Send for StandardUefiRuntimeServices {}
+
+#[cfg(test)]
+mod test {
+    use core::{
+        ffi::c_void,
+        mem::MaybeUninit,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use r_efi::efi;
+
+    use super::StandardUefiRuntimeServices;
+
+    const TEST_GUID: efi::Guid = efi::Guid::from_fields(0, 0, 0, 0, 0, &[0, 0, 0, 0, 0, 0]);
+    const TEST_NAME: &[u16] = &[b'T' as u16, b'e' as u16, b's' as u16, b't' as u16, 0];
+    const TEST_ATTRIBUTES: u32 = efi::VARIABLE_BOOTSERVICE_ACCESS | efi::VARIABLE_NON_VOLATILE;
+
+    static RESET_TYPE: AtomicUsize = AtomicUsize::new(usize::MAX);
+    static SET_VARIABLE_DATA: AtomicUsize = AtomicUsize::new(0);
+
+    extern "efiapi" fn mock_reset_system(
+        reset_type: efi::ResetType,
+        _reset_status: efi::Status,
+        _data_size: usize,
+        _data: *mut c_void,
+    ) {
+        RESET_TYPE.store(reset_type as usize, Ordering::SeqCst);
+    }
+
+    extern "efiapi" fn mock_get_variable(
+        name: *mut u16,
+        vendor_guid: *mut efi::Guid,
+        attributes: *mut u32,
+        data_size: *mut usize,
+        data: *mut c_void,
+    ) -> efi::Status {
+        assert_eq!(unsafe { core::slice::from_raw_parts(name, TEST_NAME.len()) }, TEST_NAME);
+        assert_eq!(unsafe { *vendor_guid }, TEST_GUID);
+        unsafe {
+            if *data_size < 2 {
+                data_size.write(2);
+                return efi::Status::BUFFER_TOO_SMALL;
+            }
+            attributes.write(TEST_ATTRIBUTES);
+            data_size.write(2);
+            data.cast::<[u8; 2]>().write([0x12, 0x34]);
+        }
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_set_variable(
+        name: *mut u16,
+        vendor_guid: *mut efi::Guid,
+        attributes: u32,
+        data_size: usize,
+        data: *mut c_void,
+    ) -> efi::Status {
+        assert_eq!(unsafe { core::slice::from_raw_parts(name, TEST_NAME.len()) }, TEST_NAME);
+        assert_eq!(unsafe { *vendor_guid }, TEST_GUID);
+        assert_eq!(attributes, TEST_ATTRIBUTES);
+        if data_size != 1 {
+            return efi::Status::INVALID_PARAMETER;
+        }
+        SET_VARIABLE_DATA.store(unsafe { *(data as *const u8) } as usize, Ordering::SeqCst);
+        efi::Status::SUCCESS
+    }
+
+    #[test]
+    fn runtime_services_should_not_be_usable_before_initialization() {
+        let runtime_services = StandardUefiRuntimeServices::new();
+        let mut data = [0u8; 2];
+
+        assert_eq!(runtime_services.reset_system(efi::RESET_WARM, efi::Status::SUCCESS), Err(efi::Status::NOT_READY));
+        assert_eq!(runtime_services.get_variable(TEST_NAME, &TEST_GUID, &mut data), Err(efi::Status::NOT_READY));
+        assert_eq!(
+            runtime_services.set_variable(TEST_NAME, &TEST_GUID, TEST_ATTRIBUTES, &data),
+            Err(efi::Status::NOT_READY)
+        );
+    }
+
+    #[test]
+    fn standard_uefi_runtime_services_should_wrap_runtime_services() {
+        let runtime_services = MaybeUninit::<efi::RuntimeServices>::zeroed();
+        let mut runtime_services = unsafe { runtime_services.assume_init() };
+        runtime_services.reset_system = mock_reset_system;
+        runtime_services.get_variable = mock_get_variable;
+        runtime_services.set_variable = mock_set_variable;
+
+        let test_runtime_services = StandardUefiRuntimeServices::new();
+        test_runtime_services.initialize(&mut runtime_services as *mut efi::RuntimeServices);
+
+        assert_eq!(test_runtime_services.reset_system(efi::RESET_COLD, efi::Status::SUCCESS), Ok(()));
+        assert_eq!(RESET_TYPE.load(Ordering::SeqCst), efi::RESET_COLD as usize);
+
+        let mut data = [0u8; 4];
+        assert_eq!(test_runtime_services.get_variable(TEST_NAME, &TEST_GUID, &mut data), Ok((TEST_ATTRIBUTES, 2)));
+        assert_eq!(data[..2], [0x12, 0x34]);
+        assert_eq!(
+            test_runtime_services.get_variable(TEST_NAME, &TEST_GUID, &mut data[..1]),
+            Err(efi::Status::BUFFER_TOO_SMALL)
+        );
+
+        assert_eq!(test_runtime_services.set_variable(TEST_NAME, &TEST_GUID, TEST_ATTRIBUTES, &[0x56]), Ok(()));
+        assert_eq!(SET_VARIABLE_DATA.load(Ordering::SeqCst), 0x56);
+        assert_eq!(
+            test_runtime_services.set_variable(TEST_NAME, &TEST_GUID, TEST_ATTRIBUTES, &data),
+            Err(efi::Status::INVALID_PARAMETER)
+        );
+
+        // names must be null-terminated.
+        assert_eq!(
+            test_runtime_services.get_variable(&TEST_NAME[..2], &TEST_GUID, &mut data),
+            Err(efi::Status::INVALID_PARAMETER)
+        );
+        assert_eq!(
+            test_runtime_services.set_variable(&TEST_NAME[..2], &TEST_GUID, TEST_ATTRIBUTES, &data),
+            Err(efi::Status::INVALID_PARAMETER)
+        );
+    }
+}
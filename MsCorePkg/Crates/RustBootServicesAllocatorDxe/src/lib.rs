@@ -2,6 +2,8 @@
 //!
 //! Implements a global allocator based on UEFI AllocatePool().
 //! Memory is allocated from the EFI_BOOT_SERVICES_DATA pool.
+//! Page-granular allocations that bypass the global allocator are available via
+//! [`BootServicesAllocator::allocate_pages()`].
 //!
 //! ## Examples and Usage
 //!
@@ -34,7 +36,7 @@
 use core::{
     alloc::{GlobalAlloc, Layout},
     ffi::c_void,
-    sync::atomic::AtomicPtr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
 };
 
 use r_efi::efi;
@@ -45,22 +47,69 @@ pub static GLOBAL_ALLOCATOR: BootServicesAllocator = BootServicesAllocator::new(
 
 const ALLOC_TRACKER_SIG: u32 = 0x706F6F6C; //arbitrary sig
 
+/// Size of a UEFI page in bytes.
+pub const UEFI_PAGE_SIZE: usize = 0x1000;
+
+/// Returns the number of UEFI pages required to hold `size` bytes.
+pub const fn size_to_pages(size: usize) -> usize {
+    size.div_ceil(UEFI_PAGE_SIZE)
+}
+
 // Used to track allocations that need larger alignment than the UEFI Pool alignment (8 bytes).
 struct AllocationTracker {
     signature: u32,
     orig_ptr: *mut c_void,
 }
 
+/// Snapshot of allocator statistics, see [`BootServicesAllocator::stats()`].
+///
+/// Byte counts are the sizes requested from AllocatePool(), so they include the padding and tracking structure used
+/// to satisfy alignments larger than the pool alignment.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AllocatorStats {
+    /// Bytes currently allocated and not yet freed.
+    pub outstanding_bytes: usize,
+    /// Largest value of `outstanding_bytes` observed.
+    pub peak_bytes: usize,
+    /// Number of successful allocations.
+    pub allocation_count: usize,
+    /// Number of frees.
+    pub free_count: usize,
+}
+
 /// Boot services allocator implementation. Must be initialized with a boot_services pointer before use,
 /// see [`BootServicesAllocator::init()`].
 pub struct BootServicesAllocator {
     boot_services: AtomicPtr<efi::BootServices>,
+    outstanding_bytes: AtomicUsize,
+    peak_bytes: AtomicUsize,
+    allocation_count: AtomicUsize,
+    free_count: AtomicUsize,
 }
 
 impl BootServicesAllocator {
     // Create a new instance. const fn to allow static initialization.
     const fn new() -> Self {
-        BootServicesAllocator { boot_services: AtomicPtr::new(core::ptr::null_mut()) }
+        BootServicesAllocator {
+            boot_services: AtomicPtr::new(core::ptr::null_mut()),
+            outstanding_bytes: AtomicUsize::new(0),
+            peak_bytes: AtomicUsize::new(0),
+            allocation_count: AtomicUsize::new(0),
+            free_count: AtomicUsize::new(0),
+        }
+    }
+
+    // update statistics for a successful pool allocation of the given size.
+    fn record_alloc(&self, pool_size: usize) {
+        let outstanding = self.outstanding_bytes.fetch_add(pool_size, Ordering::Relaxed).wrapping_add(pool_size);
+        self.peak_bytes.fetch_max(outstanding, Ordering::Relaxed);
+        self.allocation_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // update statistics for a pool free of the given size.
+    fn record_dealloc(&self, pool_size: usize) {
+        self.outstanding_bytes.fetch_sub(pool_size, Ordering::Relaxed);
+        self.free_count.fetch_add(1, Ordering::Relaxed);
     }
 
     // implement allocation using EFI boot services AllocatePool() call.
@@ -74,7 +123,10 @@ impl BootServicesAllocator {
                     layout.size(),
                     core::ptr::addr_of_mut!(ptr),
                 ) {
-                    efi::Status::SUCCESS => ptr as *mut u8,
+                    efi::Status::SUCCESS => {
+                        self.record_alloc(layout.size());
+                        ptr as *mut u8
+                    }
                     _ => core::ptr::null_mut(),
                 }
             }
@@ -96,6 +148,7 @@ impl BootServicesAllocator {
                     efi::Status::SUCCESS => orig_ptr as *mut u8,
                     _ => return core::ptr::null_mut(),
                 };
+                self.record_alloc(expanded_size);
 
                 //align the pointer up to the required alignment.
                 let final_ptr = unsafe { final_ptr.add(final_ptr.align_offset(expanded_layout.align())) };
@@ -123,11 +176,12 @@ impl BootServicesAllocator {
             0..=8 => {
                 //pointer was allocated directly, so free it directly.
                 let _ = (boot_services.free_pool)(ptr as *mut c_void);
+                self.record_dealloc(layout.size());
             }
             _ => {
                 //pointer was potentially adjusted for alignment. Recover tracking structure to retrieve the original
                 //pointer to free.
-                let (_, tracking_offset) = match layout.extend(Layout::new::<AllocationTracker>()) {
+                let (expanded_layout, tracking_offset) = match layout.extend(Layout::new::<AllocationTracker>()) {
                     Ok(x) => x,
                     Err(_) => return,
                 };
@@ -136,6 +190,7 @@ impl BootServicesAllocator {
                 };
                 debug_assert_eq!(tracker.signature, ALLOC_TRACKER_SIG);
                 let _ = (boot_services.free_pool)(tracker.orig_ptr);
+                self.record_dealloc(expanded_layout.size() + expanded_layout.align());
             }
         }
     }
@@ -144,6 +199,57 @@ impl BootServicesAllocator {
     pub fn init(&self, boot_services: *mut efi::BootServices) {
         self.boot_services.store(boot_services, core::sync::atomic::Ordering::SeqCst);
     }
+
+    // Returns a reference to the boot services table, or NOT_READY if uninitialized.
+    fn boot_services(&self) -> Result<&efi::BootServices, efi::Status> {
+        let bs_ptr = self.boot_services.load(Ordering::SeqCst);
+        unsafe { bs_ptr.as_ref() }.ok_or(efi::Status::NOT_READY)
+    }
+
+    /// Allocates `count` contiguous pages of the given memory type using AllocatePages(). The returned pointer is
+    /// [`UEFI_PAGE_SIZE`] aligned and must be released with [`Self::free_pages()`] using the same count.
+    ///
+    /// This is separate from the [`GlobalAlloc`] implementation and is not counted in [`Self::stats()`].
+    pub fn allocate_pages(&self, memory_type: efi::MemoryType, count: usize) -> Result<*mut u8, efi::Status> {
+        let boot_services = self.boot_services()?;
+        let mut address: efi::PhysicalAddress = 0;
+        match (boot_services.allocate_pages)(
+            efi::ALLOCATE_ANY_PAGES,
+            memory_type,
+            count,
+            core::ptr::addr_of_mut!(address),
+        ) {
+            efi::Status::SUCCESS => Ok(address as usize as *mut u8),
+            status => Err(status),
+        }
+    }
+
+    /// Frees `count` pages at `ptr` previously allocated with [`Self::allocate_pages()`].
+    pub fn free_pages(&self, ptr: *mut u8, count: usize) -> Result<(), efi::Status> {
+        let boot_services = self.boot_services()?;
+        match (boot_services.free_pages)(ptr as usize as efi::PhysicalAddress, count) {
+            efi::Status::SUCCESS => Ok(()),
+            status => Err(status),
+        }
+    }
+
+    /// Returns a snapshot of the allocation statistics for this allocator.
+    pub fn stats(&self) -> AllocatorStats {
+        AllocatorStats {
+            outstanding_bytes: self.outstanding_bytes.load(Ordering::Relaxed),
+            peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+            allocation_count: self.allocation_count.load(Ordering::Relaxed),
+            free_count: self.free_count.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets the allocation and free counts, and resets the peak to the current outstanding bytes. Outstanding bytes
+    /// are not reset, since they are still allocated.
+    pub fn reset_stats(&self) {
+        self.allocation_count.store(0, Ordering::Relaxed);
+        self.free_count.store(0, Ordering::Relaxed);
+        self.peak_bytes.store(self.outstanding_bytes.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
 }
 
 unsafe impl GlobalAlloc for BootServicesAllocator {
@@ -183,7 +289,9 @@ mod tests {
     use r_efi::efi;
     use std::collections::BTreeMap;
 
-    use crate::{AllocationTracker, BootServicesAllocator, ALLOC_TRACKER_SIG};
+    use crate::{
+        size_to_pages, AllocationTracker, AllocatorStats, BootServicesAllocator, ALLOC_TRACKER_SIG, UEFI_PAGE_SIZE,
+    };
 
     static ALLOCATION_TRACKER: spin::Mutex<BTreeMap<usize, Layout>> = spin::Mutex::new(BTreeMap::new());
 
@@ -214,6 +322,33 @@ mod tests {
         efi::Status::SUCCESS
     }
 
+    static PAGE_TRACKER: spin::Mutex<BTreeMap<u64, usize>> = spin::Mutex::new(BTreeMap::new());
+
+    extern "efiapi" fn mock_allocate_pages(
+        allocate_type: efi::AllocateType,
+        memory_type: efi::MemoryType,
+        pages: usize,
+        memory: *mut efi::PhysicalAddress,
+    ) -> efi::Status {
+        assert_eq!(allocate_type, efi::ALLOCATE_ANY_PAGES);
+        assert_eq!(memory_type, efi::RUNTIME_SERVICES_DATA);
+
+        let layout = Layout::from_size_align(pages * 0x1000, 0x1000).unwrap();
+        let address = unsafe { System.alloc(layout) } as u64;
+        unsafe { memory.write(address) };
+        PAGE_TRACKER.lock().insert(address, pages);
+        efi::Status::SUCCESS
+    }
+
+    extern "efiapi" fn mock_free_pages(memory: efi::PhysicalAddress, pages: usize) -> efi::Status {
+        let Some(allocated_pages) = PAGE_TRACKER.lock().remove(&memory) else {
+            return efi::Status::NOT_FOUND;
+        };
+        assert_eq!(pages, allocated_pages);
+        unsafe { System.dealloc(memory as *mut u8, Layout::from_size_align(pages * 0x1000, 0x1000).unwrap()) };
+        efi::Status::SUCCESS
+    }
+
     extern "efiapi" fn mock_raise_tpl(_new_tpl: efi::Tpl) -> efi::Tpl {
         efi::TPL_APPLICATION
     }
@@ -225,6 +360,8 @@ mod tests {
         let mut boot_services: efi::BootServices = unsafe { boot_services.assume_init() };
         boot_services.allocate_pool = mock_allocate_pool;
         boot_services.free_pool = mock_free_pool;
+        boot_services.allocate_pages = mock_allocate_pages;
+        boot_services.free_pages = mock_free_pages;
         boot_services.raise_tpl = mock_raise_tpl;
         boot_services.restore_tpl = mock_restore_tpl;
         boot_services
@@ -269,4 +406,108 @@ mod tests {
 
         assert!(!ALLOCATION_TRACKER.lock().contains_key(&(orig_ptr_addr)));
     }
+
+    #[test]
+    fn pages_should_be_allocated_and_freed_with_matching_count() {
+        static ALLOCATOR: BootServicesAllocator = BootServicesAllocator::new();
+        assert_eq!(ALLOCATOR.allocate_pages(efi::RUNTIME_SERVICES_DATA, 1), Err(efi::Status::NOT_READY));
+
+        ALLOCATOR.init(&mut mock_boot_services());
+
+        assert_eq!(size_to_pages(0), 0);
+        assert_eq!(size_to_pages(1), 1);
+        assert_eq!(size_to_pages(UEFI_PAGE_SIZE), 1);
+        assert_eq!(size_to_pages(UEFI_PAGE_SIZE + 1), 2);
+        assert_eq!(size_to_pages(3 * UEFI_PAGE_SIZE), 3);
+
+        let count = size_to_pages(0x2801);
+        let ptr = ALLOCATOR.allocate_pages(efi::RUNTIME_SERVICES_DATA, count).unwrap();
+        assert!(!ptr.is_null());
+        assert_eq!(ptr.align_offset(UEFI_PAGE_SIZE), 0);
+        assert_eq!(PAGE_TRACKER.lock().get(&(ptr as u64)), Some(&3));
+
+        // page allocations are not part of the GlobalAlloc statistics.
+        assert_eq!(ALLOCATOR.stats(), AllocatorStats::default());
+
+        assert_eq!(ALLOCATOR.free_pages(ptr, count), Ok(()));
+        assert!(!PAGE_TRACKER.lock().contains_key(&(ptr as u64)));
+        assert_eq!(ALLOCATOR.free_pages(ptr, count), Err(efi::Status::NOT_FOUND));
+    }
+
+    #[test]
+    fn stats_should_track_outstanding_and_peak_bytes() {
+        static ALLOCATOR: BootServicesAllocator = BootServicesAllocator::new();
+        ALLOCATOR.init(&mut mock_boot_services());
+
+        assert_eq!(ALLOCATOR.stats(), AllocatorStats::default());
+
+        let layout_a = Layout::from_size_align(0x40, 0x8).unwrap();
+        let layout_b = Layout::from_size_align(0x100, 0x8).unwrap();
+        let layout_c = Layout::from_size_align(0x20, 0x8).unwrap();
+
+        let a = unsafe { ALLOCATOR.alloc(layout_a) };
+        let b = unsafe { ALLOCATOR.alloc(layout_b) };
+        unsafe { ALLOCATOR.dealloc(a, layout_a) };
+        let c = unsafe { ALLOCATOR.alloc(layout_c) };
+        unsafe { ALLOCATOR.dealloc(b, layout_b) };
+        unsafe { ALLOCATOR.dealloc(c, layout_c) };
+
+        assert_eq!(
+            ALLOCATOR.stats(),
+            AllocatorStats { outstanding_bytes: 0, peak_bytes: 0x140, allocation_count: 3, free_count: 3 }
+        );
+
+        // aligned allocations account for the full pool allocation including alignment overhead.
+        let aligned_layout = Layout::from_size_align(0x40, 0x1000).unwrap();
+        let aligned = unsafe { ALLOCATOR.alloc(aligned_layout) };
+        let (_, tracking_offset) = aligned_layout.extend(Layout::new::<AllocationTracker>()).unwrap();
+        let orig_ptr_addr = unsafe { (*aligned.add(tracking_offset).cast::<AllocationTracker>()).orig_ptr as usize };
+        let pool_size = ALLOCATION_TRACKER.lock().get(&orig_ptr_addr).unwrap().size();
+
+        let stats = ALLOCATOR.stats();
+        assert_eq!(stats.outstanding_bytes, pool_size);
+        assert_eq!(stats.peak_bytes, pool_size.max(0x140));
+
+        ALLOCATOR.reset_stats();
+        assert_eq!(
+            ALLOCATOR.stats(),
+            AllocatorStats { outstanding_bytes: pool_size, peak_bytes: pool_size, allocation_count: 0, free_count: 0 }
+        );
+
+        unsafe { ALLOCATOR.dealloc(aligned, aligned_layout) };
+        assert_eq!(
+            ALLOCATOR.stats(),
+            AllocatorStats { outstanding_bytes: 0, peak_bytes: pool_size, allocation_count: 0, free_count: 1 }
+        );
+    }
+
+    #[test]
+    fn alignments_up_to_page_size_should_be_honored() {
+        static ALLOCATOR: BootServicesAllocator = BootServicesAllocator::new();
+        ALLOCATOR.init(&mut mock_boot_services());
+
+        for align in (4..=12).map(|shift| 1usize << shift) {
+            for size in [1, 0x7, 0x40, 0x1000, 0x1801] {
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = unsafe { ALLOCATOR.alloc(layout) };
+                assert!(!ptr.is_null());
+                assert_eq!(ptr.align_offset(align), 0);
+
+                // the whole requested size must be usable.
+                unsafe { ptr.write_bytes(0xA5, size) };
+
+                let (_, tracking_offset) = layout.extend(Layout::new::<AllocationTracker>()).unwrap();
+                let tracker = unsafe {
+                    ptr.add(tracking_offset).cast::<AllocationTracker>().as_mut().expect("tracking pointer is invalid")
+                };
+                assert_eq!(tracker.signature, ALLOC_TRACKER_SIG);
+                let orig_ptr_addr = tracker.orig_ptr as usize;
+                assert!(orig_ptr_addr <= ptr as usize);
+                assert!(ALLOCATION_TRACKER.lock().contains_key(&orig_ptr_addr));
+
+                unsafe { ALLOCATOR.dealloc(ptr, layout) };
+                assert!(!ALLOCATION_TRACKER.lock().contains_key(&orig_ptr_addr));
+            }
+        }
+    }
 }